@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter};
+use std::ops::{BitAnd, BitOr, BitXor, Not, Shl, Shr};
+
+pub const MAX_BIT_WIDTH: usize = 1024;
+const WORD_BITS: usize = u64::BITS as usize;
+const WORD_COUNT: usize = MAX_BIT_WIDTH / WORD_BITS;
+
+/// A two-valued bit vector with any width from 1 to [`MAX_BIT_WIDTH`] bits.
+///
+/// Bits at or above `len` are always kept at zero, so two vectors of the same width
+/// compare equal exactly when all of their bits do.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "BitVectorDefinition", into = "BitVectorDefinition")]
+pub struct BitVector {
+    len: u16,
+    words: [u64; WORD_COUNT],
+}
+
+impl BitVector {
+    pub fn zeros(len: usize) -> Self {
+        assert!(
+            (1..=MAX_BIT_WIDTH).contains(&len),
+            "bit width '{len}' is outside of 1..={MAX_BIT_WIDTH}"
+        );
+        Self {
+            len: len as u16,
+            words: [0; WORD_COUNT],
+        }
+    }
+    /// Creates a vector from little endian words. Bits that do not fit into `len` are dropped.
+    pub fn from_words(len: usize, words: &[u64]) -> Self {
+        let mut result = Self::zeros(len);
+        for (target, source) in result.words.iter_mut().zip(words) {
+            *target = *source;
+        }
+        result.clear_unused_bits();
+        result
+    }
+    pub fn from_u128(len: usize, value: u128) -> Self {
+        Self::from_words(len, &[value as u64, (value >> 64) as u64])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+    /// The words that hold the bits of this vector, least significant first.
+    pub fn words(&self) -> &[u64] {
+        &self.words[..self.len().div_ceil(WORD_BITS)]
+    }
+    pub fn low_u128(&self) -> u128 {
+        self.words[0] as u128 | (self.words[1] as u128) << 64
+    }
+
+    /// Returns `false` for indices outside of the vector.
+    pub fn get(&self, index: usize) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+        (self.words[index / WORD_BITS] >> (index % WORD_BITS)) & 1 != 0
+    }
+    /// Writes outside of the vector are ignored.
+    pub fn set(&mut self, index: usize, value: bool) {
+        if index >= self.len() {
+            return;
+        }
+        let word = &mut self.words[index / WORD_BITS];
+        let mask = 1 << (index % WORD_BITS);
+        if value {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+    /// Zero extends or truncates the vector to `len` bits.
+    pub fn resize(self, len: usize) -> Self {
+        Self::from_words(len, &self.words)
+    }
+
+    /// The most significant bit, which is the sign in two's complement.
+    pub fn sign_bit(&self) -> bool {
+        self.get(self.len() - 1)
+    }
+    pub fn zero_extend(self, len: usize) -> Self {
+        debug_assert!(len >= self.len(), "zero extension can not shrink a vector");
+        self.resize(len)
+    }
+    pub fn sign_extend(self, len: usize) -> Self {
+        debug_assert!(len >= self.len(), "sign extension can not shrink a vector");
+        let mut result = self.resize(len);
+        if self.sign_bit() {
+            for i in self.len()..len {
+                result.set(i, true);
+            }
+        }
+        result
+    }
+
+    /// Shifts towards the most significant bit, filling with the sign bit instead of zeros.
+    pub fn arithmetic_shr(self, amount: usize) -> Self {
+        let mut result = self >> amount;
+        if self.sign_bit() {
+            for i in self.len().saturating_sub(amount)..self.len() {
+                result.set(i, true);
+            }
+        }
+        result
+    }
+    pub fn rotate_left(self, amount: usize) -> Self {
+        let amount = amount % self.len();
+        if amount == 0 {
+            return self;
+        }
+        (self << amount) | (self >> (self.len() - amount))
+    }
+    pub fn rotate_right(self, amount: usize) -> Self {
+        let amount = amount % self.len();
+        self.rotate_left(self.len() - amount)
+    }
+
+    pub fn wrapping_add(self, rhs: Self) -> Self {
+        self.add_with_carry(rhs, false)
+    }
+    pub fn wrapping_sub(self, rhs: Self) -> Self {
+        // a - b == a + !b + 1 in two's complement
+        self.add_with_carry(!rhs.resize(self.len().max(rhs.len())), true)
+    }
+    pub fn wrapping_mul(self, rhs: Self) -> Self {
+        let mut result = Self::zeros(self.len().max(rhs.len()));
+        let word_count = result.len().div_ceil(WORD_BITS);
+        for i in 0..word_count {
+            let mut carry = 0u128;
+            for j in 0..word_count - i {
+                let product = self.words[i] as u128 * rhs.words[j] as u128
+                    + result.words[i + j] as u128
+                    + carry;
+                result.words[i + j] = product as u64;
+                carry = product >> 64;
+            }
+        }
+        result.clear_unused_bits();
+        result
+    }
+    fn add_with_carry(self, rhs: Self, carry: bool) -> Self {
+        let mut result = Self::zeros(self.len().max(rhs.len()));
+        let mut carry = carry as u128;
+        for (i, word) in result.words.iter_mut().enumerate() {
+            let sum = self.words[i] as u128 + rhs.words[i] as u128 + carry;
+            *word = sum as u64;
+            carry = sum >> 64;
+        }
+        result.clear_unused_bits();
+        result
+    }
+
+    /// Compares both vectors as unsigned numbers, zero extending the narrower one.
+    pub fn cmp_unsigned(&self, rhs: &Self) -> Ordering {
+        self.words.iter().rev().cmp(rhs.words.iter().rev())
+    }
+    /// Compares both vectors as two's complement numbers, sign extending the narrower one.
+    pub fn cmp_signed(&self, rhs: &Self) -> Ordering {
+        let len = self.len().max(rhs.len());
+        let left = self.sign_extend(len);
+        let right = rhs.sign_extend(len);
+        match (left.sign_bit(), right.sign_bit()) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            _ => left.cmp_unsigned(&right),
+        }
+    }
+
+    fn clear_unused_bits(&mut self) {
+        let len = self.len();
+        for (i, word) in self.words.iter_mut().enumerate() {
+            let first_bit = i * WORD_BITS;
+            if first_bit >= len {
+                *word = 0;
+            } else if len - first_bit < WORD_BITS {
+                *word &= (1 << (len - first_bit)) - 1;
+            }
+        }
+    }
+
+    /// Formats the vector as `0x` followed by the hex digits, most significant first.
+    pub fn to_hex_string(self) -> String {
+        let digits = self.len().div_ceil(4);
+        let mut result = String::with_capacity(digits + 2);
+        result.push_str("0x");
+        for digit in (0..digits).rev() {
+            let nibble = (self.words[digit * 4 / WORD_BITS] >> (digit * 4 % WORD_BITS)) & 0xF;
+            result.push(char::from_digit(nibble as u32, 16).unwrap());
+        }
+        result
+    }
+    /// Formats the vector as an unsigned decimal number.
+    pub fn to_decimal_string(self) -> String {
+        let mut words = self.words;
+        let mut digits = vec![];
+        loop {
+            // long division by 10, most significant word first
+            let mut remainder = 0u128;
+            for word in words.iter_mut().rev() {
+                let current = remainder << WORD_BITS | *word as u128;
+                *word = (current / 10) as u64;
+                remainder = current % 10;
+            }
+            digits.push(char::from_digit(remainder as u32, 10).unwrap());
+            if words.iter().all(|word| *word == 0) {
+                break;
+            }
+        }
+        digits.iter().rev().collect()
+    }
+    /// Parses a `0x` (hex) or `0b` (binary) prefixed literal. Underscores are ignored.
+    pub fn parse_literal(len: usize, literal: &str) -> Result<Self, String> {
+        let (radix_bits, digits) = if let Some(digits) = literal.strip_prefix("0x") {
+            (4, digits)
+        } else if let Some(digits) = literal.strip_prefix("0b") {
+            (1, digits)
+        } else {
+            return Err(format!(
+                "bit vector literal '{literal}' has to start with '0x' or '0b'"
+            ));
+        };
+        let mut result = Self::zeros(len);
+        let digits: Vec<char> = digits.chars().filter(|c| *c != '_').collect();
+        if digits.is_empty() {
+            return Err(format!("bit vector literal '{literal}' has no digits"));
+        }
+        for (position, digit) in digits.iter().rev().enumerate() {
+            let value = digit.to_digit(1 << radix_bits).ok_or_else(|| {
+                format!("invalid digit '{digit}' in bit vector literal '{literal}'")
+            })?;
+            for bit in 0..radix_bits {
+                if (value >> bit) & 1 == 0 {
+                    continue;
+                }
+                let index = position * radix_bits + bit;
+                if index >= len {
+                    return Err(format!(
+                        "bit vector literal '{literal}' does not fit into {len} bits"
+                    ));
+                }
+                result.set(index, true);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl Debug for BitVector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BitVector({}: {})", self.len, self.to_hex_string())
+    }
+}
+
+impl BitOr for BitVector {
+    type Output = Self;
+
+    /// The narrower side gets zero extended to the width of the wider one.
+    fn bitor(self, rhs: Self) -> Self::Output {
+        let mut result = Self::zeros(self.len().max(rhs.len()));
+        for (i, word) in result.words.iter_mut().enumerate() {
+            *word = self.words[i] | rhs.words[i];
+        }
+        result
+    }
+}
+
+impl BitAnd for BitVector {
+    type Output = Self;
+
+    /// The narrower side gets zero extended to the width of the wider one.
+    fn bitand(self, rhs: Self) -> Self::Output {
+        let mut result = Self::zeros(self.len().max(rhs.len()));
+        for (i, word) in result.words.iter_mut().enumerate() {
+            *word = self.words[i] & rhs.words[i];
+        }
+        result
+    }
+}
+
+impl BitXor for BitVector {
+    type Output = Self;
+
+    /// The narrower side gets zero extended to the width of the wider one.
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        let mut result = Self::zeros(self.len().max(rhs.len()));
+        for (i, word) in result.words.iter_mut().enumerate() {
+            *word = self.words[i] ^ rhs.words[i];
+        }
+        result
+    }
+}
+
+impl Not for BitVector {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        let mut result = self;
+        for word in result.words.iter_mut() {
+            *word = !*word;
+        }
+        result.clear_unused_bits();
+        result
+    }
+}
+
+impl Shl<usize> for BitVector {
+    type Output = Self;
+
+    /// Shifts towards the most significant bit. Bits shifted past the width are dropped.
+    fn shl(self, amount: usize) -> Self::Output {
+        let mut result = Self::zeros(self.len());
+        if amount >= self.len() {
+            return result;
+        }
+        let word_shift = amount / WORD_BITS;
+        let bit_shift = amount % WORD_BITS;
+        for i in word_shift..WORD_COUNT {
+            let source = i - word_shift;
+            result.words[i] = self.words[source] << bit_shift;
+            if bit_shift > 0 && source > 0 {
+                result.words[i] |= self.words[source - 1] >> (WORD_BITS - bit_shift);
+            }
+        }
+        result.clear_unused_bits();
+        result
+    }
+}
+
+impl Shr<usize> for BitVector {
+    type Output = Self;
+
+    /// Logical shift towards the least significant bit, filling with zeros.
+    fn shr(self, amount: usize) -> Self::Output {
+        let mut result = Self::zeros(self.len());
+        if amount >= self.len() {
+            return result;
+        }
+        let word_shift = amount / WORD_BITS;
+        let bit_shift = amount % WORD_BITS;
+        for i in 0..WORD_COUNT - word_shift {
+            let source = i + word_shift;
+            result.words[i] = self.words[source] >> bit_shift;
+            if bit_shift > 0 && source + 1 < WORD_COUNT {
+                result.words[i] |= self.words[source + 1] << (WORD_BITS - bit_shift);
+            }
+        }
+        result
+    }
+}
+
+/// The json representation of a [`BitVector`]: a width and either a plain number or a
+/// `0x`/`0b` prefixed string for values that do not fit into a json number.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct BitVectorDefinition {
+    width: usize,
+    #[serde(default)]
+    value: BitVectorLiteral,
+}
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+enum BitVectorLiteral {
+    Number(u64),
+    Text(String),
+}
+impl Default for BitVectorLiteral {
+    fn default() -> Self {
+        BitVectorLiteral::Number(0)
+    }
+}
+/// Values wider than a json number are written as hex strings.
+impl From<BitVector> for BitVectorDefinition {
+    fn from(bits: BitVector) -> Self {
+        let value = if bits.len() <= 64 {
+            BitVectorLiteral::Number(bits.words[0])
+        } else {
+            BitVectorLiteral::Text(bits.to_hex_string())
+        };
+        Self {
+            width: bits.len(),
+            value,
+        }
+    }
+}
+impl TryFrom<BitVectorDefinition> for BitVector {
+    type Error = String;
+
+    fn try_from(definition: BitVectorDefinition) -> Result<Self, Self::Error> {
+        let width = definition.width;
+        if !(1..=MAX_BIT_WIDTH).contains(&width) {
+            return Err(format!(
+                "bit width '{width}' is outside of 1..={MAX_BIT_WIDTH}"
+            ));
+        }
+        match definition.value {
+            BitVectorLiteral::Number(value) => {
+                if width < 64 && value >> width != 0 {
+                    return Err(format!("value '{value}' does not fit into {width} bits"));
+                }
+                Ok(BitVector::from_words(width, &[value]))
+            }
+            BitVectorLiteral::Text(literal) => BitVector::parse_literal(width, &literal),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use super::*;
+use std::ops::Range;
+
+/// The bits of the bus each other connection of a [`BlockKind::Splitter`] or
+/// [`BlockKind::Merger`] stands for, by connection index. End exclusive, so `4..8` are the
+/// bits 4 to 7.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct BitRanges(pub Vec<Range<usize>>);
+
+impl BitRanges {
+    /// Consecutive ranges for connections of the given widths, starting at bit 0.
+    pub fn consecutive(widths: impl IntoIterator<Item = usize>) -> Self {
+        let mut start = 0;
+        Self(
+            widths
+                .into_iter()
+                .map(|width| {
+                    let range = start..start + width;
+                    start += width;
+                    range
+                })
+                .collect(),
+        )
+    }
+
+    /// The new value of the output with the given index, `template` being its current value.
+    /// A splitter copies the range of the output from its first input, a merger puts each input
+    /// into its range of the output. Bits not covered by any range are 0.
+    pub fn output_value(
+        &self,
+        kind: BlockKind,
+        inputs: &[ConnectionValues],
+        index: usize,
+        template: ConnectionValues,
+    ) -> Option<ConnectionValues> {
+        let mut result = template.with_values_of(ConnectionValues::Single(false));
+        match kind {
+            BlockKind::Splitter => {
+                let bus = inputs.first()?;
+                let range = self.0.get(index)?;
+                copy_bits(*bus, range.clone(), &mut result, 0);
+            }
+            BlockKind::Merger => {
+                for (input, range) in inputs.iter().zip(self.0.iter()) {
+                    copy_bits(*input, 0..range.len(), &mut result, range.start);
+                }
+            }
+            _ => return None,
+        }
+        Some(result)
+    }
+}
+
+/// Copies the bits `range` of `source` to `target`, starting at bit `offset`. Bits outside of
+/// either value are skipped.
+fn copy_bits(
+    source: ConnectionValues,
+    range: Range<usize>,
+    target: &mut ConnectionValues,
+    offset: usize,
+) {
+    let target_len = target.len();
+    for (i, bit) in range.enumerate() {
+        if bit >= source.len() || offset + i >= target_len {
+            break;
+        }
+        target.set_level(offset + i, source.get_level(bit));
+    }
+}
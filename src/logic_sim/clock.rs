@@ -0,0 +1,77 @@
+use super::*;
+use crate::logic_sim::propagation::DirtyQueue;
+use crate::logic_sim::simulation::SimulationState;
+
+/// Drives every output of a [`BlockKind::Clock`] block with a square wave. Timed in simulation
+/// ticks, so it runs at the same speed relative to the circuit whatever the frame rate is.
+#[derive(Component, Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ClockGenerator {
+    /// Ticks of one full cycle.
+    pub period: u32,
+    /// Part of the period the output is high, from 0.0 to 1.0.
+    #[serde(default = "default_duty_cycle")]
+    pub duty_cycle: f32,
+    /// Ticks the wave is delayed by, to line up several clocks.
+    #[serde(default)]
+    pub phase: u32,
+    /// A disabled clock keeps its outputs low. Can be switched at runtime.
+    #[serde(default = "default_enabled", rename = "start_enabled")]
+    pub enabled: bool,
+}
+fn default_duty_cycle() -> f32 {
+    0.5
+}
+fn default_enabled() -> bool {
+    true
+}
+impl Default for ClockGenerator {
+    fn default() -> Self {
+        Self {
+            period: 2,
+            duty_cycle: default_duty_cycle(),
+            phase: 0,
+            enabled: default_enabled(),
+        }
+    }
+}
+
+impl ClockGenerator {
+    /// The output level at `tick`: high for the first part of each period, low for the rest.
+    pub fn level_at(&self, tick: u64) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let period = u64::from(self.period.max(1));
+        let high_ticks =
+            (period as f64 * f64::from(self.duty_cycle.clamp(0.0, 1.0))).round() as u64;
+        let position = (tick + period - u64::from(self.phase) % period) % period;
+        position < high_ticks
+    }
+    /// Whether the output changes at `tick`, compared to the tick before.
+    pub fn edge_at(&self, tick: u64) -> bool {
+        tick > 0 && self.level_at(tick) != self.level_at(tick - 1)
+    }
+}
+
+/// Sets the outputs of every clock to its level for the current tick.
+pub(super) fn drive_clocks(
+    clocks: Query<(&ClockGenerator, &Children)>,
+    mut connections: Query<&mut Connection, With<OutputConnection>>,
+    mut queue: ResMut<DirtyQueue>,
+    mut simulation: ResMut<SimulationState>,
+) {
+    for (clock, children) in clocks.iter() {
+        let level = ConnectionValues::Single(clock.level_at(simulation.tick));
+        for child in children.iter() {
+            let Ok(mut connection) = connections.get_mut(*child) else {
+                continue;
+            };
+            let value = connection.values.with_values_of(level);
+            if connection.values != value {
+                connection.bypass_change_detection().values = value;
+                queue.mark_connection(*child);
+                simulation.mark_changed();
+            }
+        }
+    }
+}
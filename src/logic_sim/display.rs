@@ -0,0 +1,245 @@
+use super::*;
+use crate::logic_sim::block_label::CanvasText;
+use bevy::color::palettes::basic::LIME;
+
+/// Part of the block size left free around a display.
+const DISPLAY_MARGIN: f32 = 0.1;
+/// Width of a 7-segment segment relative to the digit height.
+const SEGMENT_THICKNESS: f32 = 0.06;
+const UNLIT: Srgba = Srgba::rgb(0.15, 0.15, 0.15);
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberFormat {
+    Binary,
+    #[default]
+    Hex,
+    Unsigned,
+    Signed,
+}
+
+/// How a display block shows its inputs, see [`BlockKind::is_display`].
+#[derive(Component, Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisplaySettings {
+    /// Used by [`BlockKind::NumberDisplay`].
+    #[serde(default)]
+    pub format: NumberFormat,
+    /// LEDs per row of a [`BlockKind::LedMatrix`]. Defaults to the width of the first input if
+    /// the matrix has several inputs (one per row), otherwise to a square.
+    #[serde(default)]
+    pub columns: Option<u32>,
+}
+
+/// The value as text in the given format. Digits with X or Z bits show as `X` or `Z`, decimal
+/// numbers with any of them as a single `X`.
+pub fn format_value(values: ConnectionValues, format: NumberFormat) -> String {
+    let levels = values.to_logic_vector();
+    let bits = levels.known_bits();
+    match (format, bits) {
+        (NumberFormat::Binary, _) => (0..levels.len())
+            .rev()
+            .map(|i| levels.get(i).to_char())
+            .collect(),
+        (NumberFormat::Hex, Some(bits)) => bits.to_hex_string(),
+        (NumberFormat::Hex, None) => {
+            let digits: String = (0..levels.len().div_ceil(4))
+                .rev()
+                .map(|digit| {
+                    let nibble: Vec<_> = (digit * 4..(digit * 4 + 4).min(levels.len()))
+                        .map(|i| levels.get(i))
+                        .collect();
+                    if nibble.contains(&LogicLevel::Unknown) {
+                        'X'
+                    } else if nibble.contains(&LogicLevel::HighImpedance) {
+                        'Z'
+                    } else {
+                        let value = nibble
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, level)| **level == LogicLevel::High)
+                            .fold(0, |value, (i, _)| value | 1 << i);
+                        char::from_digit(value, 16).unwrap()
+                    }
+                })
+                .collect();
+            format!("0x{digits}")
+        }
+        (NumberFormat::Unsigned, Some(bits)) => bits.to_decimal_string(),
+        (NumberFormat::Signed, Some(bits)) if bits.sign_bit() => {
+            let magnitude = BitVector::zeros(bits.len()).wrapping_sub(bits);
+            format!("-{}", magnitude.to_decimal_string())
+        }
+        (NumberFormat::Signed, Some(bits)) => bits.to_decimal_string(),
+        (NumberFormat::Unsigned | NumberFormat::Signed, None) => "X".to_string(),
+    }
+}
+
+pub struct DisplayPlugin;
+impl Plugin for DisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (draw_displays, update_number_displays));
+    }
+}
+
+fn draw_displays(
+    displays: Query<(
+        &BlockKind,
+        &BlockVisuals,
+        &GlobalTransform,
+        &Children,
+        Option<&DisplaySettings>,
+    )>,
+    connections: Query<&Connection, With<InputConnection>>,
+    mut gizmos: Gizmos,
+) {
+    for (kind, visuals, transform, children, settings) in displays.iter() {
+        let inputs = block_inputs(children, &connections);
+        let Some(&first) = inputs.first() else {
+            continue;
+        };
+        let center = transform.translation().xy();
+        let size = visuals.size.as_vec2() * transform.scale().xy() * (1.0 - 2.0 * DISPLAY_MARGIN);
+        match kind {
+            BlockKind::Led => {
+                draw_led(
+                    center,
+                    size.min_element() / 2.0,
+                    first.get_level(0),
+                    &mut gizmos,
+                );
+            }
+            BlockKind::SevenSegment => draw_seven_segment(center, size, first, &mut gizmos),
+            BlockKind::LedMatrix => {
+                let settings = settings.copied().unwrap_or_default();
+                draw_led_matrix(center, size, &inputs, settings, &mut gizmos);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Shows the value of the first input of every [`BlockKind::NumberDisplay`] in its label.
+fn update_number_displays(
+    displays: Query<(&BlockKind, &Children, Option<&DisplaySettings>)>,
+    connections: Query<&Connection, With<InputConnection>>,
+    mut labels: Query<&mut Text2d, With<CanvasText>>,
+) {
+    for (kind, children, settings) in displays.iter() {
+        if *kind != BlockKind::NumberDisplay {
+            continue;
+        }
+        let Some(&value) = block_inputs(children, &connections).first() else {
+            continue;
+        };
+        let text = format_value(value, settings.copied().unwrap_or_default().format);
+        for child in children.iter() {
+            let Ok(mut label) = labels.get_mut(*child) else {
+                continue;
+            };
+            if label.0 != text {
+                label.0 = text.clone();
+            }
+        }
+    }
+}
+
+/// The values of the inputs of a block, ordered by their index.
+fn block_inputs(
+    children: &Children,
+    connections: &Query<&Connection, With<InputConnection>>,
+) -> Vec<ConnectionValues> {
+    let mut inputs: Vec<_> = children
+        .iter()
+        .filter_map(|child| connections.get(*child).ok())
+        .map(|connection| (connection.index, connection.values))
+        .collect();
+    inputs.sort_by_key(|(index, _)| *index);
+    inputs.into_iter().map(|(_, values)| values).collect()
+}
+
+fn level_color(level: LogicLevel, lit: Srgba) -> Srgba {
+    match level {
+        LogicLevel::Low => UNLIT,
+        LogicLevel::High => lit,
+        LogicLevel::Unknown => YELLOW,
+        LogicLevel::HighImpedance => BLUE,
+    }
+}
+
+fn draw_led(center: Vec2, radius: f32, level: LogicLevel, gizmos: &mut Gizmos) {
+    let color = level_color(level, LIME);
+    gizmos.circle_2d(center, radius, WHITE);
+    // gizmos only draw outlines, so fill the inside with rings
+    let rings = 8;
+    for ring in 1..=rings {
+        gizmos.circle_2d(center, radius * ring as f32 / (rings + 1) as f32, color);
+    }
+}
+
+/// Segments a to g are the bits 0 to 6 of `segments`, the decimal point is bit 7.
+fn draw_seven_segment(center: Vec2, size: Vec2, segments: ConnectionValues, gizmos: &mut Gizmos) {
+    let height = size.y.min(size.x * 2.0);
+    let half = Vec2::new(height / 4.0, height / 2.0);
+    let (left, right, top, bottom) = (-half.x, half.x, half.y, -half.y);
+    let lines = [
+        (Vec2::new(left, top), Vec2::new(right, top)),
+        (Vec2::new(right, top), Vec2::new(right, 0.0)),
+        (Vec2::new(right, 0.0), Vec2::new(right, bottom)),
+        (Vec2::new(left, bottom), Vec2::new(right, bottom)),
+        (Vec2::new(left, 0.0), Vec2::new(left, bottom)),
+        (Vec2::new(left, top), Vec2::new(left, 0.0)),
+        (Vec2::new(left, 0.0), Vec2::new(right, 0.0)),
+    ];
+    let thickness = height * SEGMENT_THICKNESS;
+    for (index, (start, end)) in lines.into_iter().enumerate() {
+        let color = level_color(segment_level(segments, index), RED);
+        // leave a gap at the corners, like on a real display
+        let gap = (end - start).normalize() * thickness;
+        let (start, end) = (center + start + gap, center + end - gap);
+        let normal = (end - start).perp().normalize();
+        for step in -2..=2 {
+            let offset = normal * thickness * step as f32 / 4.0;
+            gizmos.line_2d(start + offset, end + offset, color);
+        }
+    }
+    let point = center + Vec2::new(right + thickness * 2.0, bottom);
+    let color = level_color(segment_level(segments, 7), RED);
+    gizmos.circle_2d(point, thickness / 2.0, color);
+}
+fn segment_level(segments: ConnectionValues, index: usize) -> LogicLevel {
+    if index < segments.len() {
+        segments.get_level(index)
+    } else {
+        LogicLevel::Low
+    }
+}
+
+/// Bit 0 of the first input is the top left LED, rows continue with the following bits and
+/// inputs.
+fn draw_led_matrix(
+    center: Vec2,
+    size: Vec2,
+    inputs: &[ConnectionValues],
+    settings: DisplaySettings,
+    gizmos: &mut Gizmos,
+) {
+    let levels: Vec<_> = inputs
+        .iter()
+        .flat_map(|input| (0..input.len()).map(|i| input.get_level(i)))
+        .collect();
+    let columns = settings.columns.unwrap_or_else(|| {
+        if inputs.len() > 1 {
+            inputs[0].len() as u32
+        } else {
+            (levels.len() as f32).sqrt().ceil() as u32
+        }
+    });
+    let columns = columns.max(1) as usize;
+    let rows = levels.len().div_ceil(columns);
+    let cell = (size.x / columns as f32).min(size.y / rows as f32);
+    let top_left = center + Vec2::new(-(columns as f32), rows as f32) * cell / 2.0;
+    for (index, level) in levels.into_iter().enumerate() {
+        let (row, column) = (index / columns, index % columns);
+        let pos = top_left + Vec2::new(column as f32 + 0.5, -(row as f32) - 0.5) * cell;
+        draw_led(pos, cell * 0.4, level, gizmos);
+    }
+}
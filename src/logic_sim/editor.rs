@@ -0,0 +1,514 @@
+use super::*;
+use crate::logic_sim::bit_vector::MAX_BIT_WIDTH;
+use crate::logic_sim::history::{EditCommand, EditHistory};
+use crate::logic_sim::reload::{BlockTreeQuery, block_path};
+use crate::logic_sim::save::CircuitDefinitions;
+use crate::utils::get_cursor_world_pos;
+use bevy::color::palettes::basic::{AQUA, GRAY};
+use bevy::picking::focus::HoverMap;
+use bevy::picking::pointer::PointerId;
+use bevy::utils::HashMap;
+use bevy_inspector_egui::bevy_egui::{EguiContext, EguiPreUpdateSet};
+
+/// Blocks that can be placed from the palette.
+const PALETTE: [BlockKind; 12] = [
+    BlockKind::And,
+    BlockKind::Or,
+    BlockKind::Not,
+    BlockKind::Xor,
+    BlockKind::Nand,
+    BlockKind::Nor,
+    BlockKind::DFlipFlop,
+    BlockKind::Clock,
+    BlockKind::Switch,
+    BlockKind::Button,
+    BlockKind::Led,
+    BlockKind::NumberDisplay,
+];
+const PALETTE_BLOCK_SIZE: i32 = 50;
+const SELECTION_MARGIN: f32 = 4.0;
+
+/// Editing mode for the canvas, toggled with E: blocks can be placed from the palette, dragged
+/// around and deleted with Delete or Backspace, + and - change the width of the connection under
+/// the cursor. Clicks no longer reach switches, buttons and connections while editing.
+pub struct EditorPlugin;
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorState>()
+            .init_resource::<KeyboardCapture>()
+            .add_observer(on_press_block)
+            .add_systems(Startup, setup_palette)
+            .add_systems(
+                PreUpdate,
+                capture_keyboard.after(EguiPreUpdateSet::BeginPass),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_editor.run_if(keyboard_free),
+                    place_from_palette,
+                    drop_dragged_blocks,
+                    dragged_follow_cursor,
+                    delete_selected.run_if(keyboard_free),
+                    change_connection_width.run_if(keyboard_free),
+                    draw_selection,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct EditorState {
+    pub enabled: bool,
+}
+
+/// Run condition for systems that only run while editing.
+pub(super) fn editing(editor: Res<EditorState>) -> bool {
+    editor.enabled
+}
+
+/// Whether an egui widget, like a field of the inspector, takes the keys pressed this frame.
+#[derive(Resource, Debug, Default)]
+pub(super) struct KeyboardCapture {
+    egui: bool,
+}
+
+fn capture_keyboard(mut contexts: Query<&mut EguiContext>, mut capture: ResMut<KeyboardCapture>) {
+    let egui = contexts
+        .iter_mut()
+        .any(|mut context| context.get_mut().wants_keyboard_input());
+    if capture.egui != egui {
+        capture.egui = egui;
+    }
+}
+
+/// Run condition for keyboard shortcuts, which should not fire while typing into egui.
+pub(super) fn keyboard_free(capture: Res<KeyboardCapture>) -> bool {
+    !capture.egui
+}
+
+/// A block that follows the cursor, keeping the offset it had to it when it was picked up.
+#[derive(Component, Debug)]
+struct Dragged {
+    offset: Vec2,
+    /// Where the block was picked up, in the coordinates of its parent.
+    start: Vec2,
+    /// Placed from the palette and following the cursor until the next press, instead of until
+    /// the button is released.
+    placing: bool,
+}
+
+/// The block or wire that Delete removes.
+#[derive(Component, Debug)]
+pub(super) struct Selected;
+
+#[derive(Component, Debug)]
+struct Palette;
+#[derive(Component, Debug)]
+struct PaletteEntry(BlockKind);
+
+fn setup_palette(mut commands: Commands) {
+    commands
+        .spawn((
+            Palette,
+            Visibility::Hidden,
+            BackgroundColor(Color::BLACK.with_alpha(0.8)),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.0),
+                right: Val::Px(12.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+        ))
+        .with_children(|palette| {
+            for kind in PALETTE {
+                palette
+                    .spawn((
+                        PaletteEntry(kind),
+                        Button,
+                        BackgroundColor(Color::from(GRAY)),
+                        Node {
+                            padding: UiRect::axes(Val::Px(8.0), Val::Px(2.0)),
+                            ..default()
+                        },
+                    ))
+                    .with_child((
+                        Text::new(format!("{kind:?}")),
+                        TextFont {
+                            font_size: 18.0,
+                            ..default()
+                        },
+                    ));
+            }
+        });
+}
+
+fn toggle_editor(
+    input: Res<ButtonInput<KeyCode>>,
+    mut editor: ResMut<EditorState>,
+    mut palette: Query<&mut Visibility, With<Palette>>,
+    selected: Query<Entity, With<Selected>>,
+    mut commands: Commands,
+) {
+    if !input.just_pressed(KeyCode::KeyE) {
+        return;
+    }
+    editor.enabled = !editor.enabled;
+    info!(
+        "Editing {}",
+        if editor.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    for mut visibility in palette.iter_mut() {
+        *visibility = if editor.enabled {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+    if !editor.enabled {
+        for entity in selected.iter() {
+            commands.entity(entity).remove::<(Selected, Dragged)>();
+        }
+    }
+}
+
+/// Spawns the chosen block inside the top level block, following the cursor until it is placed.
+fn place_from_palette(
+    mut commands: Commands,
+    entries: Query<(&Interaction, &PaletteEntry), Changed<Interaction>>,
+    roots: Query<&Children, With<Root>>,
+    blocks: Query<(&Block, Option<&Children>)>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (interaction, entry) in entries.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some((top, top_block, children)) = roots
+            .iter()
+            .flat_map(|children| children.iter())
+            .find_map(|child| Some((*child, blocks.get(*child).ok()?)))
+            .map(|(top, (block, children))| (top, block, children))
+        else {
+            warn!("there is no circuit to place a block in");
+            continue;
+        };
+        // ids only have to be unique among the blocks inside the same block
+        let id = children
+            .into_iter()
+            .flat_map(|children| children.iter())
+            .filter_map(|child| blocks.get(*child).ok())
+            .map(|(block, _)| block.id)
+            .chain([top_block.id])
+            .max()
+            .unwrap_or_default()
+            + 1;
+        let definition = palette_block(entry.0, id);
+        let mut placed = None;
+        commands.entity(top).with_children(|parent| {
+            let spawned = spawn_block_definition(
+                parent,
+                &asset_server,
+                &mut meshes,
+                &mut materials,
+                definition,
+                INNER_BLOCK_Z_OFFSET,
+            );
+            placed = Some(spawned.entity);
+        });
+        if let Some(placed) = placed {
+            commands.entity(placed).insert(Dragged {
+                offset: Vec2::ZERO,
+                start: Vec2::ZERO,
+                placing: true,
+            });
+        }
+    }
+}
+
+/// A block of the given kind with single bit connections, except for the number display
+/// showing a byte.
+fn palette_block(kind: BlockKind, id: usize) -> BlockDefinition {
+    let (inputs, outputs) = match kind {
+        BlockKind::Not => (1, 1),
+        BlockKind::Led | BlockKind::NumberDisplay => (1, 0),
+        BlockKind::Clock | BlockKind::Switch | BlockKind::Button => (0, 1),
+        BlockKind::DFlipFlop => (2, 2),
+        _ => (2, 1),
+    };
+    let value = if kind == BlockKind::NumberDisplay {
+        ConnectionValues::Byte(0)
+    } else {
+        ConnectionValues::Single(false)
+    };
+    let connection = |id| ConnectionDefinition { id, value };
+    BlockDefinition {
+        id,
+        pos: Vec2::ZERO,
+        size: IVec2::splat(PALETTE_BLOCK_SIZE),
+        name: format!("{kind:?}"),
+        color: Color::srgb(0.0, 0.4, 0.6),
+        kind,
+        delay: 0,
+        clock: None,
+        contents: None,
+        display: None,
+        bit_ranges: vec![],
+        inner_blocks: vec![],
+        wires: vec![],
+        inputs: (1..=inputs).map(connection).collect(),
+        outputs: (inputs + 1..=inputs + outputs).map(connection).collect(),
+        library_instance: None,
+    }
+}
+
+/// Selects the pressed block and picks it up. The top level block stays where it is.
+fn on_press_block(
+    mut press: Trigger<Pointer<Down>>,
+    editor: Res<EditorState>,
+    blocks: Query<(&GlobalTransform, &Transform, &Parent, Option<&Dragged>), With<Block>>,
+    roots: Query<(), With<Root>>,
+    selected: Query<Entity, With<Selected>>,
+    mut commands: Commands,
+) {
+    if !editor.enabled || press.button != PointerButton::Primary {
+        return;
+    }
+    let entity = press.entity();
+    let Ok((transform, local, parent, dragged)) = blocks.get(entity) else {
+        return;
+    };
+    // the blocks containing this one should not be picked up as well
+    press.propagate(false);
+    // a block from the palette follows the cursor, the press placing it lands on the block
+    // itself and is left to `drop_dragged_blocks`
+    if roots.contains(parent.get()) || dragged.is_some_and(|dragged| dragged.placing) {
+        return;
+    }
+    for previous in selected.iter() {
+        commands.entity(previous).remove::<Selected>();
+    }
+    let offset = match press.hit.position {
+        Some(position) => transform.translation().xy() - position.xy(),
+        None => Vec2::ZERO,
+    };
+    commands.entity(entity).insert((
+        Selected,
+        Dragged {
+            offset,
+            start: local.translation.xy(),
+            placing: false,
+        },
+    ));
+}
+
+/// Releasing the button drops dragged blocks, pressing it places a block from the palette. Both
+/// go into the [`EditHistory`].
+fn drop_dragged_blocks(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    dragged: Query<(Entity, Ref<Dragged>, &Transform, &Parent)>,
+    tree: BlockTreeQuery,
+    circuit: CircuitDefinitions,
+    mut history: ResMut<EditHistory>,
+) {
+    for (entity, dragged, transform, parent) in dragged.iter() {
+        let dropped = if dragged.placing {
+            // the press on the palette that spawned the block is not the one placing it
+            mouse.just_pressed(MouseButton::Left) && !dragged.is_added()
+        } else {
+            !mouse.pressed(MouseButton::Left)
+        };
+        if !dropped {
+            continue;
+        }
+        commands.entity(entity).remove::<Dragged>();
+        let position = transform.translation.xy();
+        if dragged.placing {
+            if let Some(definition) = circuit.block(entity) {
+                history.record(vec![EditCommand::AddBlock {
+                    parent: block_path(parent.get(), &tree),
+                    definition: Box::new(definition),
+                }]);
+            }
+        } else if position != dragged.start {
+            history.record(vec![EditCommand::MoveBlock {
+                block: block_path(entity, &tree),
+                from: dragged.start,
+                to: position,
+            }]);
+        }
+    }
+}
+
+/// Moves dragged blocks to the cursor. Their connections move along, and with them the wires.
+fn dragged_follow_cursor(
+    mut dragged: Query<(&Dragged, &Parent, &mut Transform)>,
+    parents: Query<&GlobalTransform>,
+    camera_query: Single<(&Camera, &GlobalTransform)>,
+    windows: Query<&Window>,
+) {
+    let Some(point) = get_cursor_world_pos(camera_query, windows) else {
+        return;
+    };
+    for (dragged, parent, mut transform) in dragged.iter_mut() {
+        let Ok(parent_transform) = parents.get(parent.get()) else {
+            continue;
+        };
+        let target = (point + dragged.offset).extend(0.0);
+        let local = parent_transform.affine().inverse().transform_point3(target);
+        if transform.translation.xy() != local.xy() {
+            transform.translation = local.xy().extend(transform.translation.z);
+        }
+    }
+}
+
+/// Removes the selected blocks and wires. Wires lose their ends at removed blocks, wires left
+/// with fewer than two ends are removed too.
+fn delete_selected(
+    input: Res<ButtonInput<KeyCode>>,
+    editor: Res<EditorState>,
+    selected: Query<(Entity, &Parent, Has<Wire>), With<Selected>>,
+    wires: Query<(Entity, &Wire, &Parent)>,
+    tree: BlockTreeQuery,
+    circuit: CircuitDefinitions,
+    mut history: ResMut<EditHistory>,
+) {
+    if !editor.enabled || !input.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]) {
+        return;
+    }
+    let mut edit = vec![];
+    // what is left of the wires changed so far, `None` once they are removed
+    let mut remaining: HashMap<Entity, Option<WireDefinition>> = HashMap::new();
+    let mut trim_wire = |wire: Entity, keep: &dyn Fn(&ConnectionDefinitionRef) -> bool| {
+        let Ok((_, Wire { index, .. }, parent)) = wires.get(wire) else {
+            return;
+        };
+        let current = remaining.entry(wire).or_insert_with(|| circuit.wire(wire));
+        let Some(old) = current.clone() else {
+            return;
+        };
+        let mut trimmed = old.clone();
+        trimmed.connections.retain(keep);
+        if trimmed.connections.len() == old.connections.len() {
+            return;
+        }
+        let block = block_path(parent.get(), &tree);
+        edit.push(EditCommand::RemoveWire {
+            block: block.clone(),
+            index: *index,
+            wire: old,
+        });
+        if trimmed.connections.len() >= 2 {
+            edit.push(EditCommand::AddWire {
+                block,
+                index: *index,
+                wire: trimmed.clone(),
+            });
+            *current = Some(trimmed);
+        } else {
+            *current = None;
+        }
+    };
+    let mut removed_blocks = vec![];
+    for (entity, parent, is_wire) in selected.iter() {
+        if is_wire {
+            trim_wire(entity, &|_| false);
+            continue;
+        }
+        let Some(definition) = circuit.block(entity) else {
+            continue;
+        };
+        // wires inside the block go with it, only the ones next to it lose an end
+        let id = definition.id;
+        for (wire, _, wire_parent) in wires.iter() {
+            if wire_parent.get() == parent.get() {
+                trim_wire(wire, &|connection| connection.parent_block != id);
+            }
+        }
+        removed_blocks.push(EditCommand::RemoveBlock {
+            parent: block_path(parent.get(), &tree),
+            definition: Box::new(definition),
+        });
+    }
+    edit.extend(removed_blocks);
+    history.perform(edit);
+}
+
+/// Makes the connection under the cursor one bit wider with + or one bit narrower with -,
+/// keeping as many of its bits as fit. Connections on a wire keep their width, the net would
+/// no longer match.
+fn change_connection_width(
+    input: Res<ButtonInput<KeyCode>>,
+    editor: Res<EditorState>,
+    hover_map: Res<HoverMap>,
+    connections: Query<(Entity, &Connection, &BlockReference, &Name)>,
+    wires: Query<&Wire>,
+    tree: BlockTreeQuery,
+    mut history: ResMut<EditHistory>,
+) {
+    if !editor.enabled {
+        return;
+    }
+    let change: isize = if input.any_just_pressed([KeyCode::Equal, KeyCode::NumpadAdd]) {
+        1
+    } else if input.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        -1
+    } else {
+        return;
+    };
+    let Some((entity, connection, owner, name)) = hover_map
+        .get(&PointerId::Mouse)
+        .and_then(|hits| hits.keys().find_map(|entity| connections.get(*entity).ok()))
+    else {
+        return;
+    };
+    let width = connection.values.len().saturating_add_signed(change);
+    if !(1..=MAX_BIT_WIDTH).contains(&width) {
+        return;
+    }
+    let connected = wires
+        .iter()
+        .any(|wire| wire.connections().any(|c| c.0 == entity));
+    if connected {
+        info!("'{name}' is on a wire, remove the wire to change its width");
+        return;
+    }
+    let values = ConnectionValues::from_bit_vector(BitVector::zeros(width))
+        .with_values_of(connection.values);
+    history.perform(vec![EditCommand::SetConnection {
+        block: block_path(owner.0, &tree),
+        id: connection.id,
+        from: Box::new(connection.values),
+        to: Box::new(values),
+    }]);
+}
+
+fn draw_selection(
+    editor: Res<EditorState>,
+    selected: Query<(&BlockVisuals, &GlobalTransform), With<Selected>>,
+    mut gizmos: Gizmos,
+) {
+    if !editor.enabled {
+        return;
+    }
+    for (visuals, transform) in selected.iter() {
+        let size = visuals.size.as_vec2() * transform.scale().xy();
+        gizmos.rect_2d(
+            Isometry2d::from_translation(transform.translation().xy()),
+            size + SELECTION_MARGIN * 2.0,
+            AQUA,
+        );
+    }
+}
@@ -0,0 +1,451 @@
+use super::*;
+use crate::logic_sim::editor::keyboard_free;
+use bevy::ecs::system::{SystemParam, SystemState};
+use std::collections::VecDeque;
+
+/// Edits that can be undone, older ones are dropped.
+pub const MAX_HISTORY: usize = 100;
+
+/// Undo with Ctrl+Z and redo with Ctrl+Y or Ctrl+Shift+Z, for every change made to the blocks,
+/// wires and connections of the circuit.
+pub struct HistoryPlugin;
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditHistory>().add_systems(
+            Update,
+            (handle_history_keys.run_if(keyboard_free), apply_edits)
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// A reversible change to the circuit. Blocks are named by the ids of the blocks from the top
+/// level block down to them, so commands still apply after the blocks were spawned again.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditCommand {
+    AddBlock {
+        parent: Vec<usize>,
+        definition: Box<BlockDefinition>,
+    },
+    RemoveBlock {
+        parent: Vec<usize>,
+        definition: Box<BlockDefinition>,
+    },
+    MoveBlock {
+        block: Vec<usize>,
+        from: Vec2,
+        to: Vec2,
+    },
+    /// A wire inside `block`. Wires are found by their connections, `index` is the
+    /// [`Wire::index`] it gets when it is added.
+    AddWire {
+        block: Vec<usize>,
+        index: usize,
+        wire: WireDefinition,
+    },
+    RemoveWire {
+        block: Vec<usize>,
+        index: usize,
+        wire: WireDefinition,
+    },
+    /// Changes the [`WireWaypoints`] of the wire inside `block` with the connections of `wire`.
+    SetWaypoints {
+        block: Vec<usize>,
+        wire: WireDefinition,
+        from: Vec<Vec2>,
+        to: Vec<Vec2>,
+    },
+    /// Sets the value of a connection, which may change its width as well.
+    SetConnection {
+        block: Vec<usize>,
+        id: usize,
+        from: Box<ConnectionValues>,
+        to: Box<ConnectionValues>,
+    },
+}
+
+impl EditCommand {
+    /// The command that takes this one back.
+    pub fn inverse(&self) -> Self {
+        match self.clone() {
+            EditCommand::AddBlock { parent, definition } => {
+                EditCommand::RemoveBlock { parent, definition }
+            }
+            EditCommand::RemoveBlock { parent, definition } => {
+                EditCommand::AddBlock { parent, definition }
+            }
+            EditCommand::MoveBlock { block, from, to } => EditCommand::MoveBlock {
+                block,
+                from: to,
+                to: from,
+            },
+            EditCommand::AddWire { block, index, wire } => {
+                EditCommand::RemoveWire { block, index, wire }
+            }
+            EditCommand::RemoveWire { block, index, wire } => {
+                EditCommand::AddWire { block, index, wire }
+            }
+            EditCommand::SetWaypoints {
+                block,
+                wire,
+                from,
+                to,
+            } => EditCommand::SetWaypoints {
+                block,
+                wire,
+                from: to,
+                to: from,
+            },
+            EditCommand::SetConnection {
+                block,
+                id,
+                from,
+                to,
+            } => EditCommand::SetConnection {
+                block,
+                id,
+                from: to,
+                to: from,
+            },
+        }
+    }
+}
+
+/// The commands of one edit in the order they are applied in, taken back last to first.
+fn inverse(edit: &[EditCommand]) -> Vec<EditCommand> {
+    edit.iter().rev().map(EditCommand::inverse).collect()
+}
+
+/// The edits that can be undone and redone. Each edit is one action of the user, made of the
+/// commands it took.
+#[derive(Resource, Debug, Default)]
+pub struct EditHistory {
+    done: VecDeque<Vec<EditCommand>>,
+    undone: Vec<Vec<EditCommand>>,
+    /// Commands still to be applied to the circuit, in order.
+    pending: Vec<EditCommand>,
+}
+
+impl EditHistory {
+    /// Adds an edit that was already made to the circuit. Edits that were undone can no longer
+    /// be redone afterwards.
+    pub fn record(&mut self, edit: Vec<EditCommand>) {
+        if edit.is_empty() {
+            return;
+        }
+        self.undone.clear();
+        self.done.push_back(edit);
+        if self.done.len() > MAX_HISTORY {
+            self.done.pop_front();
+        }
+    }
+    /// Applies the edit to the circuit and records it.
+    pub fn perform(&mut self, edit: Vec<EditCommand>) {
+        self.pending.extend(edit.iter().cloned());
+        self.record(edit);
+    }
+    /// Takes back the latest edit. `false` if there is none.
+    pub fn undo(&mut self) -> bool {
+        let Some(edit) = self.done.pop_back() else {
+            return false;
+        };
+        self.pending.extend(inverse(&edit));
+        self.undone.push(edit);
+        true
+    }
+    /// Makes the latest undone edit again. `false` if there is none.
+    pub fn redo(&mut self) -> bool {
+        let Some(edit) = self.undone.pop() else {
+            return false;
+        };
+        self.pending.extend(edit.iter().cloned());
+        self.done.push_back(edit);
+        true
+    }
+    pub fn undo_count(&self) -> usize {
+        self.done.len()
+    }
+    pub fn redo_count(&self) -> usize {
+        self.undone.len()
+    }
+    /// The commands to apply, see [`apply_edits`].
+    pub fn take_pending(&mut self) -> Vec<EditCommand> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+fn handle_history_keys(input: Res<ButtonInput<KeyCode>>, mut history: ResMut<EditHistory>) {
+    if !input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if input.just_pressed(KeyCode::KeyY) || (shift && input.just_pressed(KeyCode::KeyZ)) {
+        if !history.redo() {
+            info!("nothing to redo");
+        }
+    } else if input.just_pressed(KeyCode::KeyZ) && !history.undo() {
+        info!("nothing to undo");
+    }
+}
+
+type EditBlockQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Block,
+        Option<&'static Parent>,
+        Option<&'static Children>,
+        &'static mut Transform,
+    ),
+>;
+
+/// What [`EditCommand`]s change.
+#[derive(SystemParam)]
+struct EditTargets<'w, 's> {
+    commands: Commands<'w, 's>,
+    asset_server: Res<'w, AssetServer>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+    blocks: EditBlockQuery<'w, 's>,
+    connections: Query<
+        'w,
+        's,
+        (
+            &'static mut Connection,
+            &'static BlockReference,
+            Has<InputConnection>,
+            &'static mut Mesh2d,
+        ),
+    >,
+    wires: Query<'w, 's, (Entity, &'static Wire, &'static Parent)>,
+    waypoints: Query<'w, 's, &'static mut WireWaypoints>,
+}
+
+/// Applies the commands of performed, undone and redone edits one after the other, so each of
+/// them sees the blocks and wires spawned by the ones before.
+fn apply_edits(world: &mut World, targets: &mut SystemState<EditTargets<'static, 'static>>) {
+    let pending = world.resource_mut::<EditHistory>().take_pending();
+    for command in pending {
+        if let Err(problem) = apply_edit(command, &mut targets.get_mut(world)) {
+            warn!("could not apply edit: {problem}");
+        }
+        targets.apply(world);
+    }
+}
+
+fn apply_edit(command: EditCommand, targets: &mut EditTargets) -> Result<(), String> {
+    match command {
+        EditCommand::AddBlock { parent, definition } => {
+            let parent = find_block(&parent, &targets.blocks)?;
+            let EditTargets {
+                commands,
+                asset_server,
+                meshes,
+                materials,
+                ..
+            } = targets;
+            commands.entity(parent).with_children(|children| {
+                spawn_block_definition(
+                    children,
+                    asset_server,
+                    meshes,
+                    materials,
+                    *definition,
+                    INNER_BLOCK_Z_OFFSET,
+                );
+            });
+        }
+        EditCommand::RemoveBlock {
+            mut parent,
+            definition,
+        } => {
+            parent.push(definition.id);
+            let block = find_block(&parent, &targets.blocks)?;
+            targets.commands.entity(block).despawn_recursive();
+        }
+        EditCommand::MoveBlock { block, to, .. } => {
+            let block = find_block(&block, &targets.blocks)?;
+            let (.., mut transform) = targets.blocks.get_mut(block).map_err(|e| e.to_string())?;
+            transform.translation = to.extend(transform.translation.z);
+        }
+        EditCommand::AddWire { block, index, wire } => {
+            let container = find_block(&block, &targets.blocks)?;
+            spawn_wire(container, index, wire, targets)?;
+        }
+        EditCommand::RemoveWire { block, wire, .. } => {
+            let container = find_block(&block, &targets.blocks)?;
+            let wire = find_wire(container, &wire, targets)
+                .ok_or_else(|| format!("block {block:?} has no wire {:?}", wire.connections))?;
+            targets.commands.entity(wire).despawn_recursive();
+        }
+        EditCommand::SetWaypoints {
+            block, wire, to, ..
+        } => {
+            let container = find_block(&block, &targets.blocks)?;
+            let wire = find_wire(container, &wire, targets)
+                .ok_or_else(|| format!("block {block:?} has no wire {:?}", wire.connections))?;
+            let mut waypoints = targets.waypoints.get_mut(wire).map_err(|e| e.to_string())?;
+            waypoints.0 = to;
+        }
+        EditCommand::SetConnection { block, id, to, .. } => {
+            let owner = find_block(&block, &targets.blocks)?;
+            let connection = find_connection_entity(owner, id, targets)
+                .ok_or_else(|| format!("block {block:?} has no connection {id}"))?;
+            let Ok((mut connection, _, _, mut mesh)) = targets.connections.get_mut(connection)
+            else {
+                unreachable!("the connection was just found");
+            };
+            if connection.values.len() != to.len() {
+                mesh.0 = connection_mesh(&mut targets.meshes, to.len());
+            }
+            connection.values = *to;
+        }
+    }
+    Ok(())
+}
+
+/// The block at the end of `path`, starting at the top level block.
+fn find_block(path: &[usize], blocks: &EditBlockQuery) -> Result<Entity, String> {
+    let missing = || format!("there is no block {path:?}");
+    let (top, inner) = path.split_first().ok_or_else(missing)?;
+    let (mut current, ..) = blocks
+        .iter()
+        .find(|(_, block, parent, ..)| {
+            block.id == *top && parent.is_none_or(|parent| !blocks.contains(parent.get()))
+        })
+        .ok_or_else(missing)?;
+    for id in inner {
+        let (.., children, _) = blocks.get(current).map_err(|_| missing())?;
+        current = children
+            .into_iter()
+            .flat_map(|children| children.iter())
+            .copied()
+            .find(|child| {
+                blocks
+                    .get(*child)
+                    .is_ok_and(|(_, block, ..)| block.id == *id)
+            })
+            .ok_or_else(missing)?;
+    }
+    Ok(current)
+}
+
+/// The wire inside `container` with the same connections as `definition`.
+fn find_wire(
+    container: Entity,
+    definition: &WireDefinition,
+    targets: &EditTargets,
+) -> Option<Entity> {
+    let mut ends = definition.connections.clone();
+    ends.sort();
+    targets
+        .wires
+        .iter()
+        .find(|(_, wire, parent)| parent.get() == container && wire_ends(wire, targets) == ends)
+        .map(|(wire, ..)| wire)
+}
+
+/// The connections of `wire` the way a [`WireDefinition`] refers to them, sorted.
+fn wire_ends(wire: &Wire, targets: &EditTargets) -> Vec<ConnectionDefinitionRef> {
+    let mut ends: Vec<_> = wire
+        .connections()
+        .filter_map(|connection| {
+            let (connection, owner, ..) = targets.connections.get(connection.0).ok()?;
+            let (_, block, ..) = targets.blocks.get(owner.0).ok()?;
+            Some(ConnectionDefinitionRef {
+                parent_block: block.id,
+                id: connection.id,
+            })
+        })
+        .collect();
+    ends.sort();
+    ends
+}
+
+/// The connection with the definition id `id` among the children of `block`.
+fn find_connection_entity(block: Entity, id: usize, targets: &EditTargets) -> Option<Entity> {
+    let (.., children, _) = targets.blocks.get(block).ok()?;
+    children?.iter().copied().find(|child| {
+        targets
+            .connections
+            .get(*child)
+            .is_ok_and(|(connection, ..)| connection.id == id)
+    })
+}
+
+/// Spawns a wire inside `container` the way [`spawn_block_definition`] does. Another index is
+/// used if `index` is taken, like after the circuit was reloaded.
+fn spawn_wire(
+    container: Entity,
+    index: usize,
+    wire: WireDefinition,
+    targets: &mut EditTargets,
+) -> Result<(), String> {
+    let (_, block, _, children, _) = targets.blocks.get(container).map_err(|e| e.to_string())?;
+    let block_id = block.id;
+    let children: Vec<Entity> = children
+        .map(|children| children.to_vec())
+        .unwrap_or_default();
+    let mut drivers = vec![];
+    let mut sinks = vec![];
+    for end in wire.connections.iter() {
+        let owner = if end.parent_block == block_id {
+            Some(container)
+        } else {
+            children.iter().copied().find(|child| {
+                targets
+                    .blocks
+                    .get(*child)
+                    .is_ok_and(|(_, block, ..)| block.id == end.parent_block)
+            })
+        };
+        let connection = owner
+            .and_then(|owner| find_connection_entity(owner, end.id, targets))
+            .ok_or_else(|| {
+                format!(
+                    "block {} inside block {block_id} has no connection {}",
+                    end.parent_block, end.id
+                )
+            })?;
+        let (_, _, is_input, _) = targets
+            .connections
+            .get(connection)
+            .map_err(|e| e.to_string())?;
+        // the block's own inputs and the outputs of its children drive the wire
+        if (owner == Some(container)) == is_input {
+            drivers.push(ConnectionReference(connection));
+        } else {
+            sinks.push(ConnectionReference(connection));
+        }
+    }
+    let taken = |index: usize| {
+        targets
+            .wires
+            .iter()
+            .any(|(_, wire, parent)| parent.get() == container && wire.index == index)
+    };
+    let index = if taken(index) {
+        targets
+            .wires
+            .iter()
+            .filter(|(_, _, parent)| parent.get() == container)
+            .map(|(_, wire, _)| wire.index + 1)
+            .max()
+            .unwrap_or_default()
+    } else {
+        index
+    };
+    targets.commands.entity(container).with_child((
+        Wire {
+            index,
+            drivers,
+            sinks,
+        },
+        PropagationDelay(wire.delay),
+        WireWaypoints(wire.waypoints),
+        Name::new(format!("Wire: {block_id}:{index}")),
+    ));
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use super::*;
+use crate::logic_sim::history::{EditCommand, EditHistory};
+use crate::logic_sim::reload::{BlockTreeQuery, block_path};
+use std::fmt::Debug;
+
+/// Toggles the clicked bit of a connection, or the whole value while shift is held. Connections
+/// that get their value from a wire are left alone, the wire would overwrite them right away.
+/// The change can be undone like an edit.
+pub(super) fn on_click_connection(
+    mut click: Trigger<Pointer<Click>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    editor: Res<EditorState>,
+    wires: Query<&Wire>,
+    mut connections: Query<(&mut Connection, &BlockReference, &GlobalTransform, &Name)>,
+    tree: BlockTreeQuery,
+    mut history: ResMut<EditHistory>,
+) {
+    if click.button != PointerButton::Primary || editor.enabled {
+        return;
+    }
+    let entity = click.entity();
+    let Ok((mut connection, owner, transform, name)) = connections.get_mut(entity) else {
+        return;
+    };
+    // the block below should not handle this click as well
+    click.propagate(false);
+    let driven = wires
+        .iter()
+        .any(|wire| wire.sinks.iter().any(|sink| sink.0 == entity));
+    if driven {
+        info!("'{name}' gets its value from a wire and cannot be set by hand");
+        return;
+    }
+    let before = connection.values;
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        connection.values = toggle_all(connection.values);
+    } else {
+        let Some(position) = click.hit.position else {
+            return;
+        };
+        let local_pos = transform.affine().inverse().transform_point3(position).xy();
+        let Some(index) = bit_index_at(local_pos, connection.values.len()) else {
+            return;
+        };
+        let level = match connection.values.get_level(index) {
+            LogicLevel::High => LogicLevel::Low,
+            _ => LogicLevel::High,
+        };
+        connection.values.set_level(index, level);
+    }
+    history.record(vec![EditCommand::SetConnection {
+        block: block_path(owner.0, &tree),
+        id: connection.id,
+        from: Box::new(before),
+        to: Box::new(connection.values),
+    }]);
+}
+
+/// Clicking a [`BlockKind::Switch`] toggles all of its outputs.
+pub(super) fn on_click_switch(
+    click: Trigger<Pointer<Click>>,
+    editor: Res<EditorState>,
+    blocks: Query<&Children>,
+    mut outputs: Query<&mut Connection, With<OutputConnection>>,
+) {
+    if click.button != PointerButton::Primary || editor.enabled {
+        return;
+    }
+    let Ok(children) = blocks.get(click.entity()) else {
+        return;
+    };
+    for child in children.iter() {
+        if let Ok(mut output) = outputs.get_mut(*child) {
+            output.values = toggle_all(output.values);
+        }
+    }
+}
+
+/// A [`BlockKind::Button`] sets all of its outputs high while it is pressed.
+pub(super) fn on_press_button(
+    press: Trigger<Pointer<Down>>,
+    editor: Res<EditorState>,
+    blocks: Query<&Children>,
+    outputs: Query<&mut Connection, With<OutputConnection>>,
+) {
+    if press.button == PointerButton::Primary && !editor.enabled {
+        set_outputs(press.entity(), true, blocks, outputs);
+    }
+}
+
+/// Releases a [`BlockKind::Button`], also when the pointer leaves it while pressed.
+pub(super) fn on_release_button<E: Debug + Clone + Reflect>(
+    release: Trigger<Pointer<E>>,
+    blocks: Query<&Children>,
+    outputs: Query<&mut Connection, With<OutputConnection>>,
+) {
+    set_outputs(release.entity(), false, blocks, outputs);
+}
+
+fn set_outputs(
+    block: Entity,
+    high: bool,
+    blocks: Query<&Children>,
+    mut outputs: Query<&mut Connection, With<OutputConnection>>,
+) {
+    let Ok(children) = blocks.get(block) else {
+        return;
+    };
+    for child in children.iter() {
+        let Ok(mut output) = outputs.get_mut(*child) else {
+            continue;
+        };
+        let low = output
+            .values
+            .with_values_of(ConnectionValues::Single(false));
+        let value = if high { !low } else { low };
+        // only write on a change, so hovering out of a released button does not trigger anything
+        if output.values != value {
+            output.values = value;
+        }
+    }
+}
+
+/// All bits high turns into all bits low, anything else into all bits high.
+pub(super) fn toggle_all(values: ConnectionValues) -> ConnectionValues {
+    let low = values.with_values_of(ConnectionValues::Single(false));
+    let high = !low;
+    if values == high { low } else { high }
+}
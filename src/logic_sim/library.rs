@@ -0,0 +1,450 @@
+use super::*;
+use bevy::asset::LoadState;
+use bevy::utils::HashMap;
+use serde::{Deserializer, de};
+use std::fmt::{Display, Formatter};
+
+const LIBRARY_FOLDER: &str = "logisim/library";
+const BLOCK_DEFINITION_EXTENSION: &str = ".blockdef.json";
+
+/// An entry of [`BlockDefinition::inner_blocks`]: either a complete definition written out in
+/// place, or an instance of a definition from another file.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum InnerBlockDefinition {
+    Instance(BlockInstanceDefinition),
+    Inline(BlockDefinition),
+}
+
+/// Entries with an `instance_of` field are instances, all others are written out in place.
+/// Unlike an untagged enum this keeps the error of the entry itself, with the field and the line
+/// it is about.
+impl<'de> Deserialize<'de> for InnerBlockDefinition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = InnerBlockFields::deserialize(deserializer)?;
+        if let Some(instance_of) = fields.instance_of {
+            return Ok(InnerBlockDefinition::Instance(BlockInstanceDefinition {
+                instance_of,
+                id: fields.id,
+                pos: fields.pos,
+                name: fields.name,
+                color: fields.color,
+                size: fields.size,
+                delay: fields.delay,
+                clock: fields.clock,
+                contents: fields.contents,
+                display: fields.display,
+                bit_ranges: fields.bit_ranges,
+                inputs: fields.inputs.unwrap_or_default(),
+                outputs: fields.outputs.unwrap_or_default(),
+            }));
+        }
+        fn required<T, E: de::Error>(value: Option<T>, field: &'static str) -> Result<T, E> {
+            value.ok_or_else(|| E::missing_field(field))
+        }
+        Ok(InnerBlockDefinition::Inline(BlockDefinition {
+            id: fields.id,
+            pos: fields.pos,
+            size: required(fields.size, "size")?,
+            name: required(fields.name, "name")?,
+            color: required(fields.color, "color")?,
+            kind: fields.kind.unwrap_or_default(),
+            delay: fields.delay.unwrap_or_default(),
+            clock: fields.clock,
+            contents: fields.contents,
+            display: fields.display,
+            bit_ranges: fields.bit_ranges.unwrap_or_default(),
+            inner_blocks: required(fields.inner_blocks, "inner_blocks")?,
+            wires: required(fields.wires, "wires")?,
+            inputs: required(fields.inputs, "inputs")?,
+            outputs: required(fields.outputs, "outputs")?,
+            library_instance: None,
+        }))
+    }
+}
+
+/// The fields of both a [`BlockInstanceDefinition`] and a [`BlockDefinition`], read in a single
+/// pass since which of the two an entry is only shows once all of it is read.
+#[derive(Deserialize)]
+struct InnerBlockFields {
+    instance_of: Option<String>,
+    id: usize,
+    pos: Vec2,
+    name: Option<String>,
+    color: Option<Color>,
+    size: Option<IVec2>,
+    kind: Option<BlockKind>,
+    delay: Option<u32>,
+    clock: Option<ClockGenerator>,
+    contents: Option<String>,
+    display: Option<DisplaySettings>,
+    bit_ranges: Option<Vec<Range<usize>>>,
+    inner_blocks: Option<Vec<InnerBlockDefinition>>,
+    wires: Option<Vec<WireDefinition>>,
+    inputs: Option<Vec<ConnectionDefinition>>,
+    outputs: Option<Vec<ConnectionDefinition>>,
+}
+
+/// Places the block defined in another `.blockdef.json` file. Everything but the id and position
+/// comes from that file unless overridden here, so edits to it show up in every circuit using it.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct BlockInstanceDefinition {
+    /// Either an asset path ending in `.blockdef.json` or the name of a file in the block library
+    /// folder (`half_adder` for `logisim/library/half_adder.blockdef.json`).
+    instance_of: String,
+    id: usize,
+    pos: Vec2,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<IVec2>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delay: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    clock: Option<ClockGenerator>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contents: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display: Option<DisplaySettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bit_ranges: Option<Vec<Range<usize>>>,
+    /// Initial values for some of the inputs, matched by id.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    inputs: Vec<ConnectionDefinition>,
+    /// Initial values for some of the outputs, matched by id.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    outputs: Vec<ConnectionDefinition>,
+}
+
+impl BlockInstanceDefinition {
+    fn asset_path(&self) -> String {
+        if self.instance_of.ends_with(BLOCK_DEFINITION_EXTENSION) {
+            self.instance_of.clone()
+        } else {
+            format!(
+                "{LIBRARY_FOLDER}/{}{BLOCK_DEFINITION_EXTENSION}",
+                self.instance_of
+            )
+        }
+    }
+    /// Applies the instance to `definition`, the resolved contents of the file it refers to.
+    pub(super) fn instantiate(&self, mut definition: BlockDefinition) -> BlockDefinition {
+        definition.library_instance = Some(Box::new(LibraryInstance {
+            instance_of: self.instance_of.clone(),
+            template: definition.clone(),
+        }));
+        change_id(&mut definition, self.id);
+        definition.pos = self.pos;
+        if let Some(name) = &self.name {
+            definition.name = name.clone();
+        }
+        if let Some(color) = self.color {
+            definition.color = color;
+        }
+        if let Some(size) = self.size {
+            definition.size = size;
+        }
+        if let Some(delay) = self.delay {
+            definition.delay = delay;
+        }
+        if let Some(clock) = self.clock {
+            definition.clock = Some(clock);
+        }
+        if let Some(contents) = &self.contents {
+            definition.contents = Some(contents.clone());
+        }
+        if let Some(display) = self.display {
+            definition.display = Some(display);
+        }
+        if let Some(bit_ranges) = &self.bit_ranges {
+            definition.bit_ranges = bit_ranges.clone();
+        }
+        override_values(&mut definition.inputs, &self.inputs, &self.instance_of);
+        override_values(&mut definition.outputs, &self.outputs, &self.instance_of);
+        definition
+    }
+    /// The instance of `template` that [`Self::instantiate`]s to `definition`. `None` if they
+    /// differ in something an instance cannot override, like the kind, the inner blocks or the
+    /// wires.
+    fn from_changes(
+        instance_of: String,
+        template: &BlockDefinition,
+        definition: &BlockDefinition,
+    ) -> Option<Self> {
+        let mut template = template.clone();
+        change_id(&mut template, definition.id);
+        let template = &template;
+        let same_ids = |a: &[ConnectionDefinition], b: &[ConnectionDefinition]| {
+            a.iter().map(|c| c.id).eq(b.iter().map(|c| c.id))
+        };
+        if definition.kind != template.kind
+            || structure(definition) != structure(template)
+            || !same_ids(&definition.inputs, &template.inputs)
+            || !same_ids(&definition.outputs, &template.outputs)
+        {
+            return None;
+        }
+        let changed_values = |template: &[ConnectionDefinition],
+                              values: &[ConnectionDefinition]| {
+            values
+                .iter()
+                .zip(template)
+                .filter(|(value, template)| value.value != template.value)
+                .map(|(value, _)| *value)
+                .collect()
+        };
+        // settings the file has but the block lacks were ignored for its kind, keeping them is
+        // harmless
+        Some(Self {
+            instance_of,
+            id: definition.id,
+            pos: definition.pos,
+            name: changed(&template.name, &definition.name),
+            color: changed(&template.color, &definition.color),
+            size: changed(&template.size, &definition.size),
+            delay: changed(&template.delay, &definition.delay),
+            clock: changed(&template.clock, &definition.clock).flatten(),
+            contents: changed(&template.contents, &definition.contents).flatten(),
+            display: changed(&template.display, &definition.display).flatten(),
+            bit_ranges: changed(&template.bit_ranges, &definition.bit_ranges)
+                .filter(|ranges| !ranges.is_empty()),
+            inputs: changed_values(&template.inputs, &definition.inputs),
+            outputs: changed_values(&template.outputs, &definition.outputs),
+        })
+    }
+}
+
+/// The file a block resolved from a [`BlockInstanceDefinition`] came from, kept on the spawned
+/// block so saving can write the instance again instead of a copy of the file.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct LibraryInstance {
+    instance_of: String,
+    /// The resolved definition from the file, before the overrides of the instance.
+    template: BlockDefinition,
+}
+
+/// The inverse of [`BlockLibrary::resolve`]: turns the inner blocks of `definition` that still
+/// match the file they were resolved from back into instances of it. Blocks that were changed
+/// beyond what an instance can override stay written out in place.
+pub fn restore_instances(definition: &mut BlockDefinition) {
+    for inner_block in definition.inner_blocks.iter_mut() {
+        let InnerBlockDefinition::Inline(child) = inner_block else {
+            continue;
+        };
+        let instance = child
+            .library_instance
+            .as_ref()
+            .and_then(|library_instance| {
+                BlockInstanceDefinition::from_changes(
+                    library_instance.instance_of.clone(),
+                    &library_instance.template,
+                    child,
+                )
+            });
+        match instance {
+            Some(instance) => *inner_block = InnerBlockDefinition::Instance(instance),
+            None => restore_instances(child),
+        }
+    }
+}
+/// Gives `definition` the id `id`, along with the ends of its wires at its own connections. An
+/// inner block that had `id` takes the old id instead, the ids of inner blocks only have to
+/// differ from each other and from the block containing them.
+fn change_id(definition: &mut BlockDefinition, id: usize) {
+    let old = definition.id;
+    if old == id {
+        return;
+    }
+    for inner_block in definition.inner_blocks.iter_mut() {
+        match inner_block {
+            InnerBlockDefinition::Inline(child) if child.id == id => change_id(child, old),
+            InnerBlockDefinition::Instance(instance) if instance.id == id => instance.id = old,
+            _ => {}
+        }
+    }
+    let ends = definition
+        .wires
+        .iter_mut()
+        .flat_map(|wire| wire.connections.iter_mut());
+    for end in ends {
+        if end.parent_block == old {
+            end.parent_block = id;
+        } else if end.parent_block == id {
+            end.parent_block = old;
+        }
+    }
+    definition.id = id;
+}
+/// The inner blocks and wires of `definition`, with the values of the connections inside the
+/// inner blocks set to zero and the ends of the wires sorted. The values are the state of the
+/// block rather than part of its file, and saved wires list their drivers first.
+fn structure(definition: &BlockDefinition) -> (Vec<InnerBlockDefinition>, Vec<WireDefinition>) {
+    let zero = ConnectionValues::Single(false);
+    let inner_blocks = definition
+        .inner_blocks
+        .iter()
+        .map(|inner_block| match inner_block {
+            InnerBlockDefinition::Inline(child) => {
+                let mut child = child.clone();
+                for connection in child.inputs.iter_mut().chain(child.outputs.iter_mut()) {
+                    connection.value = connection.value.with_values_of(zero);
+                }
+                (child.inner_blocks, child.wires) = structure(&child);
+                InnerBlockDefinition::Inline(child)
+            }
+            InnerBlockDefinition::Instance(_) => inner_block.clone(),
+        })
+        .collect();
+    let mut wires = definition.wires.clone();
+    for wire in wires.iter_mut() {
+        wire.connections.sort();
+    }
+    (inner_blocks, wires)
+}
+/// `value` if it differs from `template`, for [`BlockInstanceDefinition::from_changes`].
+fn changed<T: PartialEq + Clone>(template: &T, value: &T) -> Option<T> {
+    (value != template).then(|| value.clone())
+}
+fn override_values(
+    connections: &mut [ConnectionDefinition],
+    overrides: &[ConnectionDefinition],
+    instance_of: &str,
+) {
+    for value in overrides {
+        match connections.iter_mut().find(|c| c.id == value.id) {
+            Some(connection) => connection.value = value.value,
+            None => warn!(
+                "'{instance_of}' has no connection with id '{}' to override",
+                value.id
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LibraryError {
+    /// A referenced file is still loading, resolving has to be retried later.
+    Loading,
+    LoadFailed {
+        path: String,
+        reason: String,
+    },
+    /// The chain of files that lead back to where it started.
+    Cycle(Vec<String>),
+}
+impl Display for LibraryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LibraryError::Loading => write!(f, "block definitions are still loading"),
+            LibraryError::LoadFailed { path, reason } => {
+                write!(f, "could not load block definition '{path}': {reason}")
+            }
+            LibraryError::Cycle(chain) => {
+                write!(
+                    f,
+                    "block definitions include each other: {}",
+                    chain.join(" -> ")
+                )
+            }
+        }
+    }
+}
+
+/// Keeps the definitions referenced through [`BlockInstanceDefinition::instance_of`] loaded, by
+/// asset path.
+#[derive(Resource, Default)]
+pub struct BlockLibrary {
+    handles: HashMap<String, Handle<BlockDefinition>>,
+}
+
+impl BlockLibrary {
+    /// Whether the definition is one of the files referenced by the resolved definitions.
+    pub fn contains(&self, id: AssetId<BlockDefinition>) -> bool {
+        self.handles.values().any(|handle| handle.id() == id)
+    }
+
+    /// Replaces every instance inside `definition` (recursively) by the definition it refers to.
+    /// `path` is the file `definition` came from, so a file including itself is caught as well.
+    ///
+    /// Starts loading referenced files that are not loaded yet and returns
+    /// [`LibraryError::Loading`] until all of them are available.
+    pub fn resolve(
+        &mut self,
+        definition: &BlockDefinition,
+        path: String,
+        asset_server: &AssetServer,
+        assets: &Assets<BlockDefinition>,
+    ) -> Result<BlockDefinition, LibraryError> {
+        let mut definition = definition.clone();
+        self.resolve_inner_blocks(&mut definition, &mut vec![path], asset_server, assets)?;
+        Ok(definition)
+    }
+
+    fn resolve_inner_blocks(
+        &mut self,
+        definition: &mut BlockDefinition,
+        stack: &mut Vec<String>,
+        asset_server: &AssetServer,
+        assets: &Assets<BlockDefinition>,
+    ) -> Result<(), LibraryError> {
+        // keep going after a file that is still loading, so the whole tree starts loading at once
+        let mut loading = false;
+        for inner_block in definition.inner_blocks.iter_mut() {
+            let result = match inner_block {
+                InnerBlockDefinition::Inline(inline) => {
+                    self.resolve_inner_blocks(inline, stack, asset_server, assets)
+                }
+                InnerBlockDefinition::Instance(instance) => self
+                    .resolve_instance(instance, stack, asset_server, assets)
+                    .map(|resolved| *inner_block = InnerBlockDefinition::Inline(resolved)),
+            };
+            match result {
+                Err(LibraryError::Loading) => loading = true,
+                Err(error) => return Err(error),
+                Ok(()) => {}
+            }
+        }
+        if loading {
+            Err(LibraryError::Loading)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn resolve_instance(
+        &mut self,
+        instance: &BlockInstanceDefinition,
+        stack: &mut Vec<String>,
+        asset_server: &AssetServer,
+        assets: &Assets<BlockDefinition>,
+    ) -> Result<BlockDefinition, LibraryError> {
+        let path = instance.asset_path();
+        if stack.contains(&path) {
+            let mut chain = stack.clone();
+            chain.push(path);
+            return Err(LibraryError::Cycle(chain));
+        }
+        let handle = self
+            .handles
+            .entry(path.clone())
+            .or_insert_with(|| asset_server.load(path.clone()))
+            .clone();
+        let Some(definition) = assets.get(&handle) else {
+            return match asset_server.load_state(&handle) {
+                LoadState::Failed(error) => Err(LibraryError::LoadFailed {
+                    path,
+                    reason: error.to_string(),
+                }),
+                _ => Err(LibraryError::Loading),
+            };
+        };
+        // resolved before the overrides, so it can serve as the template of the instance
+        let mut definition = definition.clone();
+        stack.push(path);
+        let result = self.resolve_inner_blocks(&mut definition, stack, asset_server, assets);
+        stack.pop();
+        result.map(|()| instance.instantiate(definition))
+    }
+}
@@ -0,0 +1,228 @@
+use crate::logic_sim::bit_vector::{BitVector, MAX_BIT_WIDTH};
+use serde::{Deserialize, Serialize};
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogicLevel {
+    Low,
+    High,
+    /// Driven, but to an unknown value, e.g. by two drivers that disagree.
+    Unknown,
+    /// Not driven at all.
+    HighImpedance,
+}
+
+impl LogicLevel {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '0' => Some(LogicLevel::Low),
+            '1' => Some(LogicLevel::High),
+            'x' | 'X' => Some(LogicLevel::Unknown),
+            'z' | 'Z' => Some(LogicLevel::HighImpedance),
+            _ => None,
+        }
+    }
+    pub fn to_char(self) -> char {
+        match self {
+            LogicLevel::Low => '0',
+            LogicLevel::High => '1',
+            LogicLevel::Unknown => 'X',
+            LogicLevel::HighImpedance => 'Z',
+        }
+    }
+}
+
+/// A four-valued vector where every bit is 0, 1, X or Z.
+///
+/// Stored as two planes: `unknown` marks the X and Z bits, `value` holds the bit for known
+/// levels and tells X (1) and Z (0) apart for unknown ones.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct LogicVector {
+    value: BitVector,
+    unknown: BitVector,
+}
+
+impl LogicVector {
+    pub fn from_known(bits: BitVector) -> Self {
+        Self {
+            value: bits,
+            unknown: BitVector::zeros(bits.len()),
+        }
+    }
+    pub fn all_unknown(len: usize) -> Self {
+        let ones = !BitVector::zeros(len);
+        Self {
+            value: ones,
+            unknown: ones,
+        }
+    }
+    pub fn high_impedance(len: usize) -> Self {
+        Self {
+            value: BitVector::zeros(len),
+            unknown: !BitVector::zeros(len),
+        }
+    }
+    /// Builds a vector from the masks of its known bits, every other bit becomes X.
+    fn from_known_masks(zero: BitVector, one: BitVector) -> Self {
+        let unknown = !(zero | one);
+        Self {
+            value: one | unknown,
+            unknown,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.value.len()
+    }
+    pub fn get(&self, index: usize) -> LogicLevel {
+        match (self.unknown.get(index), self.value.get(index)) {
+            (false, false) => LogicLevel::Low,
+            (false, true) => LogicLevel::High,
+            (true, true) => LogicLevel::Unknown,
+            (true, false) => LogicLevel::HighImpedance,
+        }
+    }
+    pub fn set(&mut self, index: usize, level: LogicLevel) {
+        let (unknown, value) = match level {
+            LogicLevel::Low => (false, false),
+            LogicLevel::High => (false, true),
+            LogicLevel::Unknown => (true, true),
+            LogicLevel::HighImpedance => (true, false),
+        };
+        self.unknown.set(index, unknown);
+        self.value.set(index, value);
+    }
+    /// The bits of this vector, if none of them is X or Z.
+    pub fn known_bits(&self) -> Option<BitVector> {
+        if self.unknown == BitVector::zeros(self.len()) {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+    /// The bits of this vector with X and Z read as 0.
+    pub fn to_bit_vector_lossy(self) -> BitVector {
+        self.value & !self.unknown
+    }
+    fn zeros(&self) -> BitVector {
+        !(self.value | self.unknown)
+    }
+    fn ones(&self) -> BitVector {
+        self.value & !self.unknown
+    }
+
+    /// Reads undriven (Z) bits as X, the way a gate input sees them.
+    pub fn high_impedance_to_unknown(self) -> Self {
+        Self::from_known_masks(self.zeros(), self.ones())
+    }
+
+    /// Zero extends or truncates the vector to `len` bits.
+    pub fn resize(self, len: usize) -> Self {
+        self.map_planes(|plane| plane.resize(len))
+    }
+    /// Extends the vector to `len` bits with undriven (Z) bits.
+    pub fn extend_high_impedance(self, len: usize) -> Self {
+        let mut result = self.resize(len);
+        for i in self.len()..len {
+            result.set(i, LogicLevel::HighImpedance);
+        }
+        result
+    }
+    /// Applies a bit rearranging operation (shifts, rotations, extensions) to both planes.
+    pub fn map_planes(self, op: impl Fn(BitVector) -> BitVector) -> Self {
+        Self {
+            value: op(self.value),
+            unknown: op(self.unknown),
+        }
+    }
+
+    /// Combines two drivers of the same net, bit by bit: Z yields to the other driver, equal
+    /// levels stay and everything else becomes X. The narrower side counts as undriven above
+    /// its width.
+    pub fn resolve(self, other: Self) -> Self {
+        let len = self.len().max(other.len());
+        let a = self.extend_high_impedance(len);
+        let b = other.extend_high_impedance(len);
+        let a_floating = a.unknown & !a.value;
+        let b_floating = b.unknown & !b.value;
+        let take_b = a_floating;
+        let take_a = b_floating & !a_floating;
+        let both_driven = !(a_floating | b_floating);
+        let agree = both_driven & !(a.unknown | b.unknown) & !(a.value ^ b.value);
+        let conflict = both_driven & !agree;
+        Self {
+            unknown: (take_b & b.unknown) | (take_a & a.unknown) | conflict,
+            value: (take_b & b.value) | (take_a & a.value) | (agree & a.value) | conflict,
+        }
+    }
+}
+
+/// Gate semantics: a known 0 (for AND) or 1 (for OR) decides the result on its own, any
+/// other combination involving X or Z is X.
+impl BitAnd for LogicVector {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self::from_known_masks(self.zeros() | rhs.zeros(), self.ones() & rhs.ones())
+    }
+}
+impl BitOr for LogicVector {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self::from_known_masks(self.zeros() & rhs.zeros(), self.ones() | rhs.ones())
+    }
+}
+impl BitXor for LogicVector {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        let known = !(self.unknown | rhs.unknown);
+        let one = known & (self.value ^ rhs.value);
+        Self::from_known_masks(known & !one, one)
+    }
+}
+impl Not for LogicVector {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self::from_known_masks(self.ones(), self.zeros())
+    }
+}
+
+/// Parses a string of `0`, `1`, `X` and `Z`, most significant bit first. Underscores are ignored.
+impl TryFrom<String> for LogicVector {
+    type Error = String;
+
+    fn try_from(literal: String) -> Result<Self, Self::Error> {
+        let levels = literal
+            .chars()
+            .filter(|c| *c != '_')
+            .rev()
+            .map(|c| {
+                LogicLevel::from_char(c)
+                    .ok_or_else(|| format!("invalid logic level '{c}' in '{literal}'"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !(1..=MAX_BIT_WIDTH).contains(&levels.len()) {
+            return Err(format!(
+                "logic vector '{literal}' has to have 1 to {MAX_BIT_WIDTH} levels"
+            ));
+        }
+        let mut result = Self::from_known(BitVector::zeros(levels.len()));
+        for (i, level) in levels.into_iter().enumerate() {
+            result.set(i, level);
+        }
+        Ok(result)
+    }
+}
+/// The inverse of parsing, most significant bit first.
+impl From<LogicVector> for String {
+    fn from(levels: LogicVector) -> Self {
+        (0..levels.len())
+            .rev()
+            .map(|i| levels.get(i).to_char())
+            .collect()
+    }
+}
@@ -0,0 +1,344 @@
+use super::*;
+use crate::logic_sim::bit_vector::MAX_BIT_WIDTH;
+use crate::logic_sim::editor::keyboard_free;
+use crate::logic_sim::propagation::DirtyQueue;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext, LoadState};
+use bevy::utils::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Widest address a memory block uses, so a memory holds at most `2^MAX_ADDRESS_WIDTH` words.
+/// Only words that are not zero take up space, a large memory costs little until it is filled.
+pub const MAX_ADDRESS_WIDTH: usize = 20;
+
+/// The contents of a [`BlockKind::Ram`] or [`BlockKind::Rom`] block. The width of its first
+/// input sets the address width, the width of its first output the data width.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct Memory {
+    address_width: usize,
+    data_width: usize,
+    /// The words that are not zero by address, each `data_width` rounded up to whole `u64`s,
+    /// least significant first.
+    words: HashMap<usize, Vec<u64>>,
+    /// Whether the clock was low in the last step, to detect rising edges.
+    clock_low: bool,
+}
+
+impl Memory {
+    /// A memory filled with zeros. Address widths above [`MAX_ADDRESS_WIDTH`] are cut down to it.
+    pub fn new(address_width: usize, data_width: usize) -> Self {
+        let address_width = address_width.min(MAX_ADDRESS_WIDTH);
+        Self {
+            address_width,
+            data_width,
+            words: HashMap::new(),
+            clock_low: false,
+        }
+    }
+    /// Whether both memories have the same address and data width.
+    pub fn has_same_size(&self, other: &Memory) -> bool {
+        self.address_width == other.address_width && self.data_width == other.data_width
+    }
+    /// Number of words the memory holds.
+    pub fn depth(&self) -> usize {
+        1 << self.address_width
+    }
+    /// Addresses wrap around at the end of the memory.
+    pub fn read(&self, address: usize) -> BitVector {
+        match self.words.get(&(address % self.depth())) {
+            Some(words) => BitVector::from_words(self.data_width, words),
+            None => BitVector::zeros(self.data_width),
+        }
+    }
+    /// Addresses wrap around at the end of the memory, values are cut or zero extended to the
+    /// data width.
+    pub fn write(&mut self, address: usize, value: BitVector) {
+        let address = address % self.depth();
+        let value = value.resize(self.data_width);
+        if value == BitVector::zeros(self.data_width) {
+            self.words.remove(&address);
+        } else {
+            self.words.insert(address, value.words().to_vec());
+        }
+    }
+
+    /// Updates the memory for the given inputs (ordered by their index) and returns the value of
+    /// the data output.
+    ///
+    /// A RAM has the inputs address, data, write enable and clock and writes on the rising edge
+    /// of the clock while write enable is high. X and Z bits of the data are stored as 0.
+    /// A ROM only has the address. An address with X or Z bits reads as X.
+    pub fn step(&mut self, kind: BlockKind, inputs: &[ConnectionValues]) -> ConnectionValues {
+        let address = inputs
+            .first()
+            .and_then(|address| address.to_logic_vector().known_bits())
+            .map(|address| address.low_u128() as usize);
+        if kind == BlockKind::Ram {
+            let level = |index: usize| {
+                inputs
+                    .get(index)
+                    .map_or(LogicLevel::Low, |input| input.get_level(0))
+            };
+            let clock = level(3);
+            let write = self.clock_low && clock == LogicLevel::High && level(2) == LogicLevel::High;
+            self.clock_low = clock == LogicLevel::Low;
+            let data = inputs.get(1).map(|data| data.to_bit_vector());
+            if let (true, Some(address), Some(data)) = (write, address, data) {
+                self.write(address, data);
+            }
+        }
+        match address {
+            Some(address) => ConnectionValues::from_bit_vector(self.read(address)),
+            None => ConnectionValues::Logic(LogicVector::all_unknown(self.data_width)),
+        }
+    }
+
+    /// Overwrites the memory with the words of `image`. Words past the end of the memory are
+    /// dropped.
+    pub fn load_image(&mut self, image: &MemoryImage) {
+        let mut dropped = 0;
+        for (address, word) in image.words(self.data_width) {
+            if address < self.depth() {
+                self.write(address, word);
+            } else {
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            warn!(
+                "{dropped} words of the memory image do not fit into a memory of {} words",
+                self.depth()
+            );
+        }
+    }
+
+    /// The contents in hex, 8 words per line, each line starting with its address. Lines that
+    /// only hold zeros are left out.
+    pub fn hex_dump(&self) -> String {
+        let address_digits = self.address_width.div_ceil(4).max(1);
+        let mut starts: Vec<_> = self.words.keys().map(|address| address / 8 * 8).collect();
+        starts.sort_unstable();
+        starts.dedup();
+        starts
+            .into_iter()
+            .map(|start| {
+                let words: Vec<_> = (start..(start + 8).min(self.depth()))
+                    .map(|address| self.read(address).to_hex_string()[2..].to_string())
+                    .collect();
+                format!("{start:0address_digits$x}: {}", words.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// The initial contents of a memory block, loaded from the file referenced by
+/// [`BlockDefinition`]'s `contents`.
+#[derive(Asset, TypePath, Debug, Clone, PartialEq)]
+pub struct MemoryImage {
+    contents: MemoryImageContents,
+}
+#[derive(Debug, Clone, PartialEq)]
+enum MemoryImageContents {
+    /// From a `.hex` file, by address.
+    Words(Vec<(usize, BitVector)>),
+    /// From a `.bin` file, split into words once the data width is known.
+    Bytes(Vec<u8>),
+}
+
+impl MemoryImage {
+    /// Parses the text of a `.hex` file: hex words separated by whitespace, stored at
+    /// consecutive addresses starting at 0. `@` followed by a hex address continues at that
+    /// address, `//` and `#` start a comment that runs to the end of the line. Underscores
+    /// inside words are ignored.
+    pub fn parse_hex(text: &str) -> Result<Self, String> {
+        let mut words = vec![];
+        let mut address = 0;
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or_default();
+            let line = line.split('#').next().unwrap_or_default();
+            for token in line.split_whitespace() {
+                let error = |reason: String| format!("line {}: {reason}", line_index + 1);
+                if let Some(target) = token.strip_prefix('@') {
+                    address = usize::from_str_radix(target, 16)
+                        .map_err(|_| error(format!("invalid address '{token}'")))?;
+                    continue;
+                }
+                let word = BitVector::parse_literal(MAX_BIT_WIDTH, &format!("0x{token}"))
+                    .map_err(error)?;
+                words.push((address, word));
+                address += 1;
+            }
+        }
+        Ok(Self {
+            contents: MemoryImageContents::Words(words),
+        })
+    }
+    /// The raw bytes of a `.bin` file. Every word takes its data width rounded up to whole bytes,
+    /// least significant byte first.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            contents: MemoryImageContents::Bytes(bytes),
+        }
+    }
+
+    /// The words of the image by address, cut or zero extended to `data_width`.
+    pub fn words(&self, data_width: usize) -> Vec<(usize, BitVector)> {
+        match &self.contents {
+            MemoryImageContents::Words(words) => words
+                .iter()
+                .map(|(address, word)| (*address, word.resize(data_width)))
+                .collect(),
+            MemoryImageContents::Bytes(bytes) => bytes
+                .chunks(data_width.div_ceil(8))
+                .map(|chunk| {
+                    let mut words = [0u64; MAX_BIT_WIDTH / 64];
+                    for (i, byte) in chunk.iter().enumerate() {
+                        words[i / 8] |= u64::from(*byte) << (i % 8 * 8);
+                    }
+                    BitVector::from_words(data_width, &words)
+                })
+                .enumerate()
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MemoryImageError {
+    Io(std::io::Error),
+    Parse(String),
+}
+impl Display for MemoryImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryImageError::Io(error) => write!(f, "could not read memory image: {error}"),
+            MemoryImageError::Parse(reason) => write!(f, "invalid memory image: {reason}"),
+        }
+    }
+}
+impl std::error::Error for MemoryImageError {}
+
+#[derive(Default)]
+struct MemoryImageLoader;
+impl AssetLoader for MemoryImageLoader {
+    type Asset = MemoryImage;
+    type Settings = ();
+    type Error = MemoryImageError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = vec![];
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(MemoryImageError::Io)?;
+        if load_context
+            .path()
+            .extension()
+            .is_some_and(|extension| extension == "bin")
+        {
+            return Ok(MemoryImage::from_bytes(bytes));
+        }
+        let text = String::from_utf8(bytes).map_err(|e| MemoryImageError::Parse(e.to_string()))?;
+        MemoryImage::parse_hex(&text).map_err(MemoryImageError::Parse)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["hex", "bin"]
+    }
+}
+
+/// The file a [`Memory`] takes its contents from, applied once it is loaded and again whenever
+/// it changes on disk.
+#[derive(Component, Debug)]
+pub struct MemoryImageSource {
+    handle: Handle<MemoryImage>,
+    applied: bool,
+}
+impl MemoryImageSource {
+    pub fn new(handle: Handle<MemoryImage>) -> Self {
+        Self {
+            handle,
+            applied: false,
+        }
+    }
+    /// Keeps the image from overwriting the memory until it changes on disk.
+    pub fn mark_applied(&mut self) {
+        self.applied = true;
+    }
+    /// Asset path of the image.
+    pub fn path(&self) -> Option<String> {
+        self.handle.path().map(ToString::to_string)
+    }
+}
+
+pub struct MemoryPlugin;
+impl Plugin for MemoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<MemoryImage>()
+            .init_asset_loader::<MemoryImageLoader>()
+            .register_type::<Memory>()
+            .add_systems(
+                Update,
+                (
+                    apply_memory_images,
+                    log_memory_contents.run_if(keyboard_free),
+                )
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+fn apply_memory_images(
+    mut events: EventReader<AssetEvent<MemoryImage>>,
+    mut memories: Query<(&mut Memory, &mut MemoryImageSource, &Children, &Name)>,
+    images: Res<Assets<MemoryImage>>,
+    asset_server: Res<AssetServer>,
+    mut queue: ResMut<DirtyQueue>,
+) {
+    for event in events.read() {
+        if let AssetEvent::Modified { id } = event {
+            for (_, mut source, _, _) in memories.iter_mut() {
+                if source.handle.id() == *id {
+                    source.applied = false;
+                }
+            }
+        }
+    }
+    for (mut memory, mut source, children, name) in memories.iter_mut() {
+        if source.applied {
+            continue;
+        }
+        let Some(image) = images.get(&source.handle) else {
+            if let LoadState::Failed(error) = asset_server.load_state(&source.handle) {
+                error!("could not load the contents of '{name}': {error}");
+                source.applied = true;
+            }
+            continue;
+        };
+        memory.load_image(image);
+        source.applied = true;
+        // evaluate the block again, so the data output shows the new contents
+        for child in children.iter() {
+            queue.mark_connection(*child);
+        }
+    }
+}
+
+fn log_memory_contents(
+    input: Res<ButtonInput<KeyCode>>,
+    memories: Query<(&Memory, &BlockKind, &Name)>,
+) {
+    if !input.just_pressed(KeyCode::KeyM) {
+        return;
+    }
+    for (memory, kind, name) in memories.iter() {
+        info!("{kind:?} '{name}':\n{}", memory.hex_dump());
+    }
+}
@@ -1,15 +1,68 @@
 use crate::camera::Canvas;
+use crate::logic_sim::bit_vector::BitVector;
 use crate::logic_sim::block_label::{BlockLabelBundle, BlockLabelPlugin};
-use bevy::color::palettes::basic::{GREEN, RED, WHITE};
+use crate::logic_sim::bus::BitRanges;
+use crate::logic_sim::clock::ClockGenerator;
+use crate::logic_sim::display::{DisplayPlugin, DisplaySettings};
+use crate::logic_sim::editor::{EditorPlugin, EditorState};
+use crate::logic_sim::history::HistoryPlugin;
+use crate::logic_sim::input::{
+    on_click_connection, on_click_switch, on_press_button, on_release_button,
+};
+use crate::logic_sim::library::{
+    BlockLibrary, InnerBlockDefinition, LibraryError, LibraryInstance,
+};
+use crate::logic_sim::logic_vector::{LogicLevel, LogicVector};
+use crate::logic_sim::memory::{MAX_ADDRESS_WIDTH, Memory, MemoryImageSource, MemoryPlugin};
+use crate::logic_sim::primitives::BlockKind;
+use crate::logic_sim::propagation::{
+    DrivenWires, FeedingWires, OscillatingWires, PropagationDelay,
+};
+use crate::logic_sim::reload::ReloadPlugin;
+use crate::logic_sim::routing::{
+    JUNCTION_RADIUS, RoutingPlugin, WireRoute, WireWaypoints, route_wires,
+};
+use crate::logic_sim::save::SavePlugin;
+use crate::logic_sim::simulation::{SimulationPlugin, SimulationSettings};
+use crate::logic_sim::validation::{
+    DefinitionProblem, DefinitionProblems, WidthMismatches, definition_width_mismatches,
+    show_definition_problems, validate_definition,
+};
+use crate::logic_sim::wiring::WiringPlugin;
+use bevy::asset::LoadState;
+use bevy::color::palettes::basic::{BLUE, GREEN, RED, WHITE, YELLOW};
 use bevy::prelude::*;
 use bevy::text::TextBounds;
 use bevy_common_assets::json::JsonAssetPlugin;
-use serde::Deserialize;
-use std::ops::BitOr;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::ops::{BitAnd, BitOr, BitXor, Not, Range, Shl, Shr};
+pub mod bit_vector;
 pub mod block_label;
+pub mod bus;
+pub mod clock;
+pub mod display;
+pub mod editor;
+pub mod history;
+pub mod input;
+pub mod library;
+pub mod logic_vector;
+pub mod memory;
+pub mod primitives;
+pub mod propagation;
+pub mod reload;
+pub mod routing;
+pub mod save;
+pub mod sequential;
+pub mod simulation;
+pub mod validation;
+pub mod wiring;
 
 const CONNECTION_SCALE_FACTOR: f32 = 10.0;
+const CONNECTION_BIT_SIZE: f32 = 10.0;
+const MAX_BITS_PER_ROW: u32 = 32;
 const LABEL_SCALING_FACTOR: f32 = 0.2;
+const INNER_BLOCK_Z_OFFSET: f32 = 3.0;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 enum AppState {
@@ -24,44 +77,82 @@ struct Root;
 
 #[derive(Resource)]
 struct BlockDefinitionHandle(Handle<BlockDefinition>);
-#[derive(Deserialize, Asset, TypePath, Debug, Clone)]
+#[derive(Deserialize, Serialize, Asset, TypePath, Debug, Clone, PartialEq)]
 pub struct BlockDefinition {
     id: usize,
     pos: Vec2,
     size: IVec2,
     name: String,
     color: Color,
-    inner_blocks: Vec<BlockDefinition>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    kind: BlockKind,
+    /// Ticks it takes for a change of the inputs to show at the outputs of a primitive block.
+    #[serde(default, skip_serializing_if = "is_default")]
+    delay: u32,
+    /// The waveform of a [`BlockKind::Clock`] block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    clock: Option<ClockGenerator>,
+    /// Asset path of a `.hex` or `.bin` file with the initial contents of a
+    /// [`BlockKind::Ram`] or [`BlockKind::Rom`] block, see [`memory::MemoryImage`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contents: Option<String>,
+    /// How a display block shows its inputs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display: Option<DisplaySettings>,
+    /// The bits of the bus each output of a [`BlockKind::Splitter`] or each input of a
+    /// [`BlockKind::Merger`] stands for, end exclusive (`{"start": 4, "end": 8}` are the bits 4
+    /// to 7). Defaults to consecutive ranges starting at bit 0.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    bit_ranges: Vec<Range<usize>>,
+    inner_blocks: Vec<InnerBlockDefinition>,
     wires: Vec<WireDefinition>,
     inputs: Vec<ConnectionDefinition>,
     outputs: Vec<ConnectionDefinition>,
+    /// Set by [`BlockLibrary::resolve`] for blocks that are an instance of another file.
+    #[serde(skip)]
+    library_instance: Option<Box<LibraryInstance>>,
 }
-#[derive(Deserialize, Asset, TypePath, Debug, Clone)]
+#[derive(Deserialize, Serialize, Asset, TypePath, Debug, Clone, PartialEq)]
 pub struct WireDefinition {
     connections: Vec<ConnectionDefinitionRef>,
+    /// Ticks it takes for a change of the drivers to reach the sinks.
+    #[serde(default, skip_serializing_if = "is_default")]
+    delay: u32,
+    /// Points the wire is routed through, see [`WireWaypoints`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    waypoints: Vec<Vec2>,
 }
-#[derive(Deserialize, Asset, TypePath, Debug, Clone, Copy)]
+#[derive(Deserialize, Serialize, Asset, TypePath, Debug, Clone, Copy, PartialEq)]
 pub struct ConnectionDefinition {
     id: usize,
     value: ConnectionValues,
 }
-#[derive(Deserialize, Asset, TypePath, Debug, Clone, Copy)]
+#[derive(
+    Deserialize, Serialize, Asset, TypePath, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
 pub struct ConnectionDefinitionRef {
     parent_block: usize,
     id: usize,
 }
 
+/// Fields left at their default are not written, like they may be left out when reading.
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
 #[derive(Component, Debug)]
 pub struct BlockVisuals {
     size: IVec2,
     color: Color,
+    /// The name from the definition, number displays show their value in the label instead.
+    name: String,
 }
 #[derive(Component, Debug, Copy, Clone)]
 pub struct ConnectionReference(Entity);
 #[derive(Component, Debug, Copy, Clone)]
 pub struct BlockReference(Entity);
 #[derive(Component, Debug)]
-#[require(Transform, Mesh2d, MeshMaterial2d<ColorMaterial>)]
+#[require(Transform, Mesh2d, MeshMaterial2d<ColorMaterial>, PropagationDelay)]
 pub struct Block {
     id: usize,
     input_count: usize,
@@ -69,9 +160,21 @@ pub struct Block {
 }
 
 #[derive(Component, Debug)]
-#[require(Transform)]
+#[require(Transform, PropagationDelay, WireWaypoints, WireRoute)]
 pub struct Wire {
-    connections: Vec<ConnectionReference>,
+    /// Position in the wires of the [`BlockDefinition`] of the block containing the wire.
+    index: usize,
+    /// Connections that drive their value onto the wire: the inputs of the block containing the
+    /// wire and the outputs of its child blocks.
+    drivers: Vec<ConnectionReference>,
+    /// Connections that take the value of the wire: the outputs of the block containing the wire
+    /// and the inputs of its child blocks.
+    sinks: Vec<ConnectionReference>,
+}
+impl Wire {
+    fn connections(&self) -> impl Iterator<Item = &ConnectionReference> {
+        self.drivers.iter().chain(self.sinks.iter())
+    }
 }
 #[derive(Component, Debug)]
 pub struct InputConnection;
@@ -79,12 +182,22 @@ pub struct InputConnection;
 pub struct OutputConnection;
 
 #[derive(Component, Debug)]
-#[require(Transform, Mesh2d, MeshMaterial2d<ColorMaterial>)]
+#[require(Transform, Mesh2d, MeshMaterial2d<ColorMaterial>, DrivenWires, FeedingWires)]
 pub struct Connection {
+    /// The id from the [`ConnectionDefinition`].
+    id: usize,
     index: usize,
     values: ConnectionValues,
 }
-#[derive(Deserialize, Debug, Clone, Copy)]
+/// What a flip-flop, latch or register remembers, kept on the block entity next to its
+/// [`Connection`]s. The first output shows it, see [`BlockState::step`].
+#[derive(Component, Debug, Clone, Copy)]
+pub struct BlockState {
+    pub value: ConnectionValues,
+    /// The clock level of the last step, to detect rising edges.
+    clock: LogicLevel,
+}
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
 pub enum ConnectionValues {
     Single(bool),
     HalfByte(bool, bool, bool, bool),
@@ -94,6 +207,12 @@ pub enum ConnectionValues {
     X64(u64),
     X128(u128),
     X256(u128, u128),
+    /// Any width from 1 to [`bit_vector::MAX_BIT_WIDTH`] bits, for buses that do not match one of
+    /// the fixed widths above.
+    Bits(BitVector),
+    /// Four-valued bits that can also be unknown (X) or undriven (Z). Every operation involving
+    /// this variant follows the four-valued rules of [`LogicVector`].
+    Logic(LogicVector),
 }
 
 impl ConnectionValues {
@@ -107,11 +226,14 @@ impl ConnectionValues {
             ConnectionValues::X64(_) => 64,
             ConnectionValues::X128(_) => 128,
             ConnectionValues::X256(_, _) => 256,
+            ConnectionValues::Bits(b) => b.len(),
+            ConnectionValues::Logic(l) => l.len(),
         }
     }
     //region inner_x
+    // each step narrows only while the value fits the half, a 12 bit value ends up in a u16
     fn inner_u128(self) -> u128 {
-        if self.len() < 128 {
+        if self.len() <= 64 {
             return self.inner_u64() as u128;
         }
         match self {
@@ -123,10 +245,12 @@ impl ConnectionValues {
             | ConnectionValues::X64(_) => unreachable!(),
             ConnectionValues::X128(b) => b,
             ConnectionValues::X256(b, _) => b,
+            ConnectionValues::Bits(b) => b.low_u128(),
+            ConnectionValues::Logic(l) => l.to_bit_vector_lossy().low_u128(),
         }
     }
     fn inner_u64(self) -> u64 {
-        if self.len() < 64 {
+        if self.len() <= 32 {
             return self.inner_u32() as u64;
         }
         match self {
@@ -138,10 +262,12 @@ impl ConnectionValues {
             ConnectionValues::X64(b) => b,
             ConnectionValues::X128(b) => b as u64,
             ConnectionValues::X256(b, _) => b as u64,
+            ConnectionValues::Bits(b) => b.low_u128() as u64,
+            ConnectionValues::Logic(l) => l.to_bit_vector_lossy().low_u128() as u64,
         }
     }
     fn inner_u32(self) -> u32 {
-        if self.len() < 32 {
+        if self.len() <= 16 {
             return self.inner_u16() as u32;
         }
         match self {
@@ -153,10 +279,12 @@ impl ConnectionValues {
             ConnectionValues::X64(b) => b as u32,
             ConnectionValues::X128(b) => b as u32,
             ConnectionValues::X256(b, _) => b as u32,
+            ConnectionValues::Bits(b) => b.low_u128() as u32,
+            ConnectionValues::Logic(l) => l.to_bit_vector_lossy().low_u128() as u32,
         }
     }
     fn inner_u16(self) -> u16 {
-        if self.len() < 16 {
+        if self.len() <= 8 {
             return self.inner_u8() as u16;
         }
         match self {
@@ -168,6 +296,8 @@ impl ConnectionValues {
             ConnectionValues::X64(b) => b as u16,
             ConnectionValues::X128(b) => b as u16,
             ConnectionValues::X256(b, _) => b as u16,
+            ConnectionValues::Bits(b) => b.low_u128() as u16,
+            ConnectionValues::Logic(l) => l.to_bit_vector_lossy().low_u128() as u16,
         }
     }
     fn inner_u8(self) -> u8 {
@@ -182,6 +312,8 @@ impl ConnectionValues {
             ConnectionValues::X64(b) => b as u8,
             ConnectionValues::X128(b) => b as u8,
             ConnectionValues::X256(b, _) => b as u8,
+            ConnectionValues::Bits(b) => b.low_u128() as u8,
+            ConnectionValues::Logic(l) => l.to_bit_vector_lossy().low_u128() as u8,
         }
     }
     //endregion
@@ -236,13 +368,82 @@ impl ConnectionValues {
                 }
             }
             ConnectionValues::X256(val1, val2) => {
-                let val = if index < 128 { val1 } else { val2 };
+                let (val, index) = if index < 128 {
+                    (val1, index)
+                } else {
+                    (val2, index - 128)
+                };
                 if value {
                     *val |= 1 << index;
                 } else {
                     *val &= !(1 << index);
                 }
             }
+            ConnectionValues::Bits(bits) => bits.set(index, value),
+            ConnectionValues::Logic(levels) => levels.set(
+                index,
+                if value {
+                    LogicLevel::High
+                } else {
+                    LogicLevel::Low
+                },
+            ),
+        }
+    }
+    /// Like [`Self::set_by_index`], but also accepts X and Z, switching to
+    /// [`ConnectionValues::Logic`] if needed.
+    pub(crate) fn set_level(&mut self, index: usize, level: LogicLevel) {
+        match level {
+            LogicLevel::Low | LogicLevel::High => {
+                self.set_by_index(index, level == LogicLevel::High)
+            }
+            LogicLevel::Unknown | LogicLevel::HighImpedance => {
+                if index >= self.len() {
+                    warn!(
+                        "Tried writing out of bounds. Index: '{index}' ConnectionValues: '{self:?}'"
+                    );
+                    return;
+                }
+                let mut levels = self.to_logic_vector();
+                levels.set(index, level);
+                *self = ConnectionValues::Logic(levels);
+            }
+        }
+    }
+    pub(crate) fn to_bit_vector(self) -> BitVector {
+        match self {
+            ConnectionValues::X256(low, high) => BitVector::from_words(
+                256,
+                &[
+                    low as u64,
+                    (low >> 64) as u64,
+                    high as u64,
+                    (high >> 64) as u64,
+                ],
+            ),
+            ConnectionValues::Bits(bits) => bits,
+            ConnectionValues::Logic(levels) => levels.to_bit_vector_lossy(),
+            _ => BitVector::from_u128(self.len(), self.inner_u128()),
+        }
+    }
+    pub(crate) fn to_logic_vector(self) -> LogicVector {
+        match self {
+            ConnectionValues::Logic(levels) => levels,
+            _ => LogicVector::from_known(self.to_bit_vector()),
+        }
+    }
+    pub(crate) fn get_level(self, index: usize) -> LogicLevel {
+        match self {
+            ConnectionValues::Logic(levels) => levels.get(index),
+            _ if self.get_by_index(index) => LogicLevel::High,
+            _ => LogicLevel::Low,
+        }
+    }
+    /// Whether any bit is X or Z.
+    pub fn has_unknown(self) -> bool {
+        match self {
+            ConnectionValues::Logic(levels) => levels.known_bits().is_none(),
+            _ => false,
         }
     }
     pub(crate) fn get_by_index(self, index: usize) -> bool {
@@ -272,74 +473,216 @@ impl ConnectionValues {
                     (val2 >> (index - 128)) & 1 != 0
                 }
             }
+            ConnectionValues::Bits(bits) => bits.get(index),
+            ConnectionValues::Logic(levels) => levels.get(index) == LogicLevel::High,
         }
     }
 }
 
+impl ConnectionValues {
+    /// Picks the fixed width variant matching the width of `bits`, or [`ConnectionValues::Bits`]
+    /// if there is none.
+    pub(crate) fn from_bit_vector(bits: BitVector) -> Self {
+        let template = match bits.len() {
+            1 => ConnectionValues::Single(false),
+            4 => ConnectionValues::HalfByte(false, false, false, false),
+            8 => ConnectionValues::Byte(0),
+            16 => ConnectionValues::X16(0),
+            32 => ConnectionValues::X32(0),
+            64 => ConnectionValues::X64(0),
+            128 => ConnectionValues::X128(0),
+            256 => ConnectionValues::X256(0, 0),
+            _ => ConnectionValues::Bits(bits),
+        };
+        template.with_bit_vector(bits)
+    }
+    /// Keeps the variant of `self` but replaces its bits. `bits` has to have the same width.
+    fn with_bit_vector(self, bits: BitVector) -> Self {
+        debug_assert_eq!(self.len(), bits.len());
+        let low = bits.low_u128();
+        match self {
+            ConnectionValues::Single(_) => ConnectionValues::Single(bits.get(0)),
+            ConnectionValues::HalfByte(_, _, _, _) => {
+                ConnectionValues::HalfByte(bits.get(0), bits.get(1), bits.get(2), bits.get(3))
+            }
+            ConnectionValues::Byte(_) => ConnectionValues::Byte(low as u8),
+            ConnectionValues::X16(_) => ConnectionValues::X16(low as u16),
+            ConnectionValues::X32(_) => ConnectionValues::X32(low as u32),
+            ConnectionValues::X64(_) => ConnectionValues::X64(low as u64),
+            ConnectionValues::X128(_) => ConnectionValues::X128(low),
+            ConnectionValues::X256(_, _) => ConnectionValues::X256(low, (bits >> 128).low_u128()),
+            ConnectionValues::Bits(_) => ConnectionValues::Bits(bits),
+            ConnectionValues::Logic(_) => ConnectionValues::Logic(LogicVector::from_known(bits)),
+        }
+    }
+    /// Keeps the width and variant of `self` but takes the levels of `value`, truncating or zero
+    /// extending them as needed.
+    pub(crate) fn with_values_of(self, value: Self) -> Self {
+        self.with_logic_vector(value.to_logic_vector().resize(self.len()))
+    }
+    /// Like [`Self::with_bit_vector`], but switches to [`ConnectionValues::Logic`] if any of the
+    /// levels is X or Z.
+    fn with_logic_vector(self, levels: LogicVector) -> Self {
+        match levels.known_bits() {
+            Some(bits) => self.with_bit_vector(bits),
+            None => ConnectionValues::Logic(levels),
+        }
+    }
+    /// Applies a bit rearranging operation, to both planes if the value is four-valued.
+    fn map_bits(self, op: impl Fn(BitVector) -> BitVector) -> Self {
+        match self {
+            ConnectionValues::Logic(levels) => ConnectionValues::Logic(levels.map_planes(op)),
+            _ => self.with_bit_vector(op(self.to_bit_vector())),
+        }
+    }
+    /// Applies `op` to both sides after zero extending the narrower one. The result keeps the
+    /// variant of the wider side (of `rhs` if both have the same width).
+    fn widening_op(self, rhs: Self, op: impl FnOnce(BitVector, BitVector) -> BitVector) -> Self {
+        let template = if self.len() > rhs.len() { self } else { rhs };
+        let len = template.len();
+        let result = op(
+            self.to_bit_vector().resize(len),
+            rhs.to_bit_vector().resize(len),
+        );
+        template.with_bit_vector(result)
+    }
+    /// Like [`Self::widening_op`], with `logic_op` taking over as soon as one side is
+    /// four-valued.
+    fn widening_bitwise_op(
+        self,
+        rhs: Self,
+        op: impl FnOnce(BitVector, BitVector) -> BitVector,
+        logic_op: impl FnOnce(LogicVector, LogicVector) -> LogicVector,
+    ) -> Self {
+        if !matches!(self, ConnectionValues::Logic(_)) && !matches!(rhs, ConnectionValues::Logic(_))
+        {
+            return self.widening_op(rhs, op);
+        }
+        let template = if self.len() > rhs.len() { self } else { rhs };
+        let len = template.len();
+        let result = logic_op(
+            self.to_logic_vector().resize(len),
+            rhs.to_logic_vector().resize(len),
+        );
+        template.with_logic_vector(result)
+    }
+    /// Like [`Self::widening_op`], but a single X or Z bit on either side makes every bit of the
+    /// result X, since it could change any bit through the carries.
+    fn widening_arithmetic_op(
+        self,
+        rhs: Self,
+        op: impl FnOnce(BitVector, BitVector) -> BitVector,
+    ) -> Self {
+        if self.has_unknown() || rhs.has_unknown() {
+            return ConnectionValues::Logic(LogicVector::all_unknown(self.len().max(rhs.len())));
+        }
+        self.widening_op(rhs, op)
+    }
+
+    /// Combines two values driven onto the same wire: bits driven to different levels
+    /// become X instead of being OR-ed together, see [`LogicVector::resolve`].
+    pub fn resolve(self, rhs: Self) -> Self {
+        let template = if self.len() > rhs.len() { self } else { rhs };
+        template.with_logic_vector(self.to_logic_vector().resolve(rhs.to_logic_vector()))
+    }
+
+    pub fn wrapping_add(self, rhs: Self) -> Self {
+        self.widening_arithmetic_op(rhs, BitVector::wrapping_add)
+    }
+    pub fn wrapping_sub(self, rhs: Self) -> Self {
+        self.widening_arithmetic_op(rhs, BitVector::wrapping_sub)
+    }
+    pub fn wrapping_mul(self, rhs: Self) -> Self {
+        self.widening_arithmetic_op(rhs, BitVector::wrapping_mul)
+    }
+    pub fn arithmetic_shr(self, amount: usize) -> Self {
+        self.map_bits(|bits| bits.arithmetic_shr(amount))
+    }
+    pub fn rotate_left(self, amount: usize) -> Self {
+        self.map_bits(|bits| bits.rotate_left(amount))
+    }
+    pub fn rotate_right(self, amount: usize) -> Self {
+        self.map_bits(|bits| bits.rotate_right(amount))
+    }
+    pub fn zero_extend(self, len: usize) -> Self {
+        match self {
+            ConnectionValues::Logic(levels) => {
+                ConnectionValues::Logic(levels.map_planes(|bits| bits.zero_extend(len)))
+            }
+            _ => Self::from_bit_vector(self.to_bit_vector().zero_extend(len)),
+        }
+    }
+    pub fn sign_extend(self, len: usize) -> Self {
+        match self {
+            ConnectionValues::Logic(levels) => {
+                ConnectionValues::Logic(levels.map_planes(|bits| bits.sign_extend(len)))
+            }
+            _ => Self::from_bit_vector(self.to_bit_vector().sign_extend(len)),
+        }
+    }
+    /// X and Z bits compare as 0.
+    pub fn cmp_unsigned(self, rhs: Self) -> Ordering {
+        self.to_bit_vector().cmp_unsigned(&rhs.to_bit_vector())
+    }
+    /// X and Z bits compare as 0.
+    pub fn cmp_signed(self, rhs: Self) -> Ordering {
+        self.to_bit_vector().cmp_signed(&rhs.to_bit_vector())
+    }
+}
+
+/// Two values are equal if they have the same width and levels, regardless of the variant.
+impl PartialEq for ConnectionValues {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_logic_vector() == other.to_logic_vector()
+    }
+}
+impl Eq for ConnectionValues {}
+
 impl BitOr for ConnectionValues {
     type Output = Self;
 
     fn bitor(self, rhs: Self) -> Self::Output {
-        let (left, right);
-        if self.len() > rhs.len() {
-            left = self;
-            right = rhs;
-        } else {
-            left = rhs;
-            right = self;
-        }
-        assert!(left.len() >= right.len());
+        self.widening_bitwise_op(rhs, BitOr::bitor, BitOr::bitor)
+    }
+}
+impl BitAnd for ConnectionValues {
+    type Output = Self;
 
-        match left {
-            ConnectionValues::Single(left) => match right {
-                ConnectionValues::Single(right) => ConnectionValues::Single(left | right),
-                _ => unreachable!("The right side should be smaller or equal than the left side"),
-            },
-            ConnectionValues::HalfByte(left0, left1, left2, left3) => match right {
-                ConnectionValues::Single(right) => {
-                    ConnectionValues::HalfByte(left0 | right, left1, left2, left3)
-                }
-                ConnectionValues::HalfByte(right0, right1, right2, right3) => {
-                    ConnectionValues::HalfByte(
-                        left0 | right0,
-                        left1 | right1,
-                        left2 | right2,
-                        left3 | right3,
-                    )
-                }
-                _ => unreachable!("The right side should be smaller or equal than the left side"),
-            },
-            ConnectionValues::Byte(left) => {
-                let right = right.inner_u8();
-                ConnectionValues::Byte(left | right)
-            }
-            ConnectionValues::X16(left) => {
-                let right = right.inner_u16();
-                ConnectionValues::X16(left | right)
-            }
-            ConnectionValues::X32(left) => {
-                let right = right.inner_u32();
-                ConnectionValues::X32(left | right)
-            }
-            ConnectionValues::X64(left) => {
-                let right = right.inner_u64();
-                ConnectionValues::X64(left | right)
-            }
-            ConnectionValues::X128(left) => {
-                let right = right.inner_u128();
-                ConnectionValues::X128(left | right)
-            }
-            ConnectionValues::X256(left0, left1) => {
-                if let ConnectionValues::X256(right1, right2) = right {
-                    ConnectionValues::X256(left0 | right1, left1 | right2)
-                } else {
-                    let right = right.inner_u128();
-                    ConnectionValues::X256(left0 | right, left1)
-                }
-            }
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.widening_bitwise_op(rhs, BitAnd::bitand, BitAnd::bitand)
+    }
+}
+impl BitXor for ConnectionValues {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        self.widening_bitwise_op(rhs, BitXor::bitxor, BitXor::bitxor)
+    }
+}
+impl Not for ConnectionValues {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        match self {
+            ConnectionValues::Logic(levels) => ConnectionValues::Logic(!levels),
+            _ => self.with_bit_vector(!self.to_bit_vector()),
         }
     }
 }
+impl Shl<usize> for ConnectionValues {
+    type Output = Self;
+
+    fn shl(self, amount: usize) -> Self::Output {
+        self.map_bits(|bits| bits << amount)
+    }
+}
+impl Shr<usize> for ConnectionValues {
+    type Output = Self;
+
+    fn shr(self, amount: usize) -> Self::Output {
+        self.map_bits(|bits| bits >> amount)
+    }
+}
 pub struct LogicSimPlugin;
 impl Plugin for LogicSimPlugin {
     fn build(&self, app: &mut App) {
@@ -347,44 +690,95 @@ impl Plugin for LogicSimPlugin {
             //
             .add_plugins(JsonAssetPlugin::<BlockDefinition>::new(&["blockdef.json"]))
             .add_plugins(BlockLabelPlugin)
+            .add_plugins(SimulationPlugin)
+            .add_plugins(MemoryPlugin)
+            .add_plugins(DisplayPlugin)
+            .add_plugins(ReloadPlugin)
+            .add_plugins(SavePlugin)
+            .add_plugins(EditorPlugin)
+            .add_plugins(WiringPlugin)
+            .add_plugins(HistoryPlugin)
+            .add_plugins(RoutingPlugin)
             .add_systems(Startup, setup)
             .init_asset::<BlockDefinition>()
+            .init_resource::<BlockLibrary>()
+            .init_resource::<DefinitionProblems>()
             .init_state::<AppState>()
             .add_systems(
                 Update,
                 spawn_block_definition_from_asset.run_if(in_state(AppState::Loading)),
             )
-            .add_systems(Update, (draw_connections, draw_wires))
-            .add_systems(Update, update_connection_states);
+            .add_systems(Update, show_definition_problems)
+            .add_systems(
+                PostUpdate,
+                (draw_connections, draw_wires).after(route_wires),
+            );
+        // connections, switches and buttons are clicked through their meshes
+        if !app.is_plugin_added::<MeshPickingPlugin>() {
+            app.add_plugins(MeshPickingPlugin);
+        }
     }
 }
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     let block_def =
-        BlockDefinitionHandle(asset_server.load("logisim/blocks/sample1.blockdef.json"));
+        BlockDefinitionHandle(asset_server.load("logisim/blocks/full_adder.blockdef.json"));
     commands.insert_resource(block_def);
 }
 
 fn spawn_block_definition_from_asset(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    block: Res<BlockDefinitionHandle>,
+    block_handle: Res<BlockDefinitionHandle>,
     mut blocks: ResMut<Assets<BlockDefinition>>,
+    mut library: ResMut<BlockLibrary>,
     mut state: ResMut<NextState<AppState>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    spawned_blocks: Query<(Entity, &Block)>,
+    roots: Query<Entity, With<Root>>,
+    mut width_mismatches: ResMut<WidthMismatches>,
+    settings: Res<SimulationSettings>,
+    mut problems: ResMut<DefinitionProblems>,
 ) {
-    if let Some(block) = blocks.remove(block.0.id()) {
-        let spawned_block = spawned_blocks
-            .iter()
-            .find_map(|(e, b)| if b.id == block.id { Some(e) } else { None });
-        if let Some(e) = spawned_block {
-            commands.entity(e).despawn_recursive();
+    let path = block_handle
+        .0
+        .path()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    if let Some(definition) = blocks.get(&block_handle.0) {
+        let block = match library.resolve(definition, path.clone(), &asset_server, &blocks) {
+            Ok(block) => block,
+            Err(LibraryError::Loading) => return,
+            Err(error) => {
+                problems.file = path;
+                problems.problems = vec![DefinitionProblem::new("$", error.to_string())];
+                state.set(AppState::Running);
+                return;
+            }
+        };
+        let found = validate_definition(&block);
+        if !found.is_empty() {
+            problems.file = path;
+            problems.problems = found;
+            state.set(AppState::Running);
+            return;
+        }
+        if !problems.problems.is_empty() {
+            problems.problems.clear();
+        }
+        width_mismatches.update(definition_width_mismatches(&block), &settings);
+        // the definition stays loaded, so changes to the file can be picked up
+        for root in roots.iter() {
+            commands.entity(root).despawn_recursive();
         }
         commands.spawn(Root).with_children(|c| {
-            spawn_block_definition(c, &asset_server, &mut meshes, &mut materials, block);
+            spawn_block_definition(c, &asset_server, &mut meshes, &mut materials, block, 0.0);
         });
         state.set(AppState::Running)
+    } else if let LoadState::Failed(error) = asset_server.load_state(&block_handle.0) {
+        // e.g. invalid JSON, the error tells the line and column
+        problems.file = path;
+        problems.problems = vec![DefinitionProblem::new("$", error.to_string())];
+        state.set(AppState::Running);
     }
 }
 
@@ -394,14 +788,11 @@ fn spawn_block_definition(
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<ColorMaterial>>,
     block: BlockDefinition,
-) {
+    z: f32,
+) -> SpawnedBlock {
     let font = asset_server.load("fonts/arcane_nine.otf");
     let mesh = meshes.add(Rectangle::new(block.size.x as f32, block.size.y as f32));
     let block_material = materials.add(block.color);
-    let rectangle = meshes.add(Rectangle::new(
-        1.0 * CONNECTION_SCALE_FACTOR,
-        1.0 * CONNECTION_SCALE_FACTOR,
-    ));
     let connection_material = materials.add(Color::BLACK);
     let text_font = TextFont {
         font,
@@ -418,103 +809,282 @@ fn spawn_block_definition(
             input_count,
             output_count,
         },
+        block.kind,
+        PropagationDelay(block.delay),
         Mesh2d(mesh),
         MeshMaterial2d(block_material),
         BlockVisuals {
             size: block_size.as_ivec2(),
             color: block.color,
+            name: block.name.clone(),
         },
-        Transform::from_translation(block.pos.extend(0.0)),
+        Transform::from_translation(block.pos.extend(z)),
     ));
+    if block.kind.is_sequential() {
+        match block.outputs.first() {
+            Some(output) => {
+                block_id.insert(BlockState::new(output.value));
+            }
+            None => warn!(
+                "block '{}' of kind {:?} has no output to hold its state",
+                block.id, block.kind
+            ),
+        }
+    }
+    match block.kind {
+        BlockKind::Switch => {
+            block_id.observe(on_click_switch);
+        }
+        BlockKind::Button => {
+            block_id
+                .observe(on_press_button)
+                .observe(on_release_button::<Up>)
+                .observe(on_release_button::<Out>);
+        }
+        _ => {}
+    }
+    if block.kind.is_display() {
+        block_id.insert(block.display.unwrap_or_default());
+    } else if block.display.is_some() {
+        warn!(
+            "ignoring the display settings of block '{}', it is not a display but {:?}",
+            block.id, block.kind
+        );
+    }
+    if block.kind == BlockKind::Clock {
+        block_id.insert(block.clock.unwrap_or_default());
+    } else if block.clock.is_some() {
+        warn!(
+            "ignoring the clock settings of block '{}', it is not a clock but {:?}",
+            block.id, block.kind
+        );
+    }
+    if matches!(block.kind, BlockKind::Splitter | BlockKind::Merger) {
+        let parts = if block.kind == BlockKind::Splitter {
+            &block.outputs
+        } else {
+            &block.inputs
+        };
+        let ranges = if block.bit_ranges.is_empty() {
+            BitRanges::consecutive(parts.iter().map(|part| part.value.len()))
+        } else {
+            BitRanges(block.bit_ranges.clone())
+        };
+        if ranges.0.len() != parts.len() {
+            warn!(
+                "{:?} block '{}' has {} bit ranges for {} connections",
+                block.kind,
+                block.id,
+                ranges.0.len(),
+                parts.len()
+            );
+        }
+        block_id.insert(ranges);
+    } else if !block.bit_ranges.is_empty() {
+        warn!(
+            "ignoring the bit ranges of block '{}', it is not a splitter or merger but {:?}",
+            block.id, block.kind
+        );
+    }
+    if matches!(block.kind, BlockKind::Ram | BlockKind::Rom) {
+        match (block.inputs.first(), block.outputs.first()) {
+            (Some(address), Some(data)) => {
+                if address.value.len() > MAX_ADDRESS_WIDTH {
+                    warn!(
+                        "memory block '{}' only uses the lowest {MAX_ADDRESS_WIDTH} bits of its {} bit address",
+                        block.id,
+                        address.value.len()
+                    );
+                }
+                block_id.insert(Memory::new(address.value.len(), data.value.len()));
+                if let Some(path) = &block.contents {
+                    block_id.insert(MemoryImageSource::new(asset_server.load(path.clone())));
+                }
+            }
+            _ => warn!(
+                "memory block '{}' needs an address input and a data output",
+                block.id
+            ),
+        }
+    } else if block.contents.is_some() {
+        warn!(
+            "ignoring the contents of block '{}', it is not a memory but {:?}",
+            block.id, block.kind
+        );
+    }
+    if let Some(library_instance) = &block.library_instance {
+        block_id.insert(library_instance.as_ref().clone());
+    }
     block_id.with_child(BlockLabelBundle::new(block.name, block.size, text_font));
     let id = block_id.id();
-    let inputs = block.inputs.iter().enumerate().map(|(i, input)| {
-        let pos =
-            get_connection_pos_from_index(block_size, input_count, ConnectionPosition::Input, i);
-        (
-            input.id,
+    let inputs = block
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let pos = get_connection_pos_from_index(
+                block_size,
+                input_count,
+                ConnectionPosition::Input,
+                i,
+            );
             (
-                Name::new(format!("Input: {}:{}", i, id)),
-                Mesh2d(rectangle.clone()),
-                MeshMaterial2d(connection_material.clone()),
-                BlockReference(id),
-                Transform::from_translation(pos.extend(2.0)),
-                InputConnection,
-                Connection {
-                    index: i,
-                    values: input.value,
-                },
-            ),
-        )
-    });
-    let outputs = block.outputs.iter().enumerate().map(|(i, output)| {
-        let pos =
-            get_connection_pos_from_index(block_size, output_count, ConnectionPosition::Output, i);
-        (
-            output.id,
+                input.id,
+                (
+                    Name::new(format!("Input: {}:{}", i, id)),
+                    Mesh2d(connection_mesh(meshes, input.value.len())),
+                    MeshMaterial2d(connection_material.clone()),
+                    BlockReference(id),
+                    Transform::from_translation(pos.extend(2.0)),
+                    InputConnection,
+                    Connection {
+                        id: input.id,
+                        index: i,
+                        values: input.value,
+                    },
+                ),
+            )
+        })
+        .collect::<Vec<_>>();
+    let outputs = block
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(i, output)| {
+            let pos = get_connection_pos_from_index(
+                block_size,
+                output_count,
+                ConnectionPosition::Output,
+                i,
+            );
             (
-                Name::new(format!("Output: {}:{}", i, id)),
-                Mesh2d(rectangle.clone()),
-                MeshMaterial2d(connection_material.clone()),
-                BlockReference(id),
-                OutputConnection,
-                Transform::from_translation(pos.extend(1.0)),
-                Connection {
-                    index: i,
-                    values: output.value,
-                },
-            ),
-        )
-    });
+                output.id,
+                (
+                    Name::new(format!("Output: {}:{}", i, id)),
+                    Mesh2d(connection_mesh(meshes, output.value.len())),
+                    MeshMaterial2d(connection_material.clone()),
+                    BlockReference(id),
+                    OutputConnection,
+                    Transform::from_translation(pos.extend(1.0)),
+                    Connection {
+                        id: output.id,
+                        index: i,
+                        values: output.value,
+                    },
+                ),
+            )
+        })
+        .collect::<Vec<_>>();
 
+    let mut spawned = SpawnedBlock {
+        id: block.id,
+        entity: id,
+        inputs: vec![],
+        outputs: vec![],
+    };
     block_id.with_children(|x| {
         let inputs: Vec<_> = inputs
-            .map(|(id, con)| (id, ConnectionReference(x.spawn(con).id())))
+            .into_iter()
+            .map(|(id, con)| {
+                let connection = x.spawn(con).observe(on_click_connection).id();
+                (id, ConnectionReference(connection))
+            })
             .collect();
         let outputs: Vec<_> = outputs
-            .map(|(id, con)| (id, ConnectionReference(x.spawn(con).id())))
+            .into_iter()
+            .map(|(id, con)| {
+                let connection = x.spawn(con).observe(on_click_connection).id();
+                (id, ConnectionReference(connection))
+            })
             .collect();
 
-        let child_blocks = block.inner_blocks.iter().map(|block| {
-            spawn_block_definition(x, asset_server, meshes, materials, block.clone());
-        });
-
-        let wires = block
-            .wires
+        let child_blocks: Vec<SpawnedBlock> = block
+            .inner_blocks
             .iter()
-            .map(|wire| {
-                wire.connections
-                    .iter()
-                    .flat_map(|con| {
-                        if (con.parent_block != block.id) {
-                            warn!("get connections from sub blocks is not implemented yet");
-                            // todo!("get connections from sub blocks (not blocks outside the one containing the wire and only one level deep)");
-                            None
-                        } else if let Some((_, connection)) =
-                            inputs.iter().find(|(id, _)| *id == con.id)
-                        {
-                            Some(*connection)
-                        } else if let Some((_, connection)) =
-                            outputs.iter().find(|(id, _)| *id == con.id)
-                        {
-                            Some(*connection)
-                        } else {
-                            warn!(
-                                "could not find connection with id '{}' in block '{}",
-                                con.id, block.id
-                            );
-                            None
-                        }
-                    })
-                    .collect()
+            .filter_map(|inner_block| match inner_block {
+                InnerBlockDefinition::Inline(inner_block) => Some(spawn_block_definition(
+                    x,
+                    asset_server,
+                    meshes,
+                    materials,
+                    inner_block.clone(),
+                    INNER_BLOCK_Z_OFFSET,
+                )),
+                InnerBlockDefinition::Instance(_) => {
+                    warn!("skipping unresolved block instance inside block '{}'", block.id);
+                    None
+                }
             })
-            .enumerate()
-            .map(|(i, connections)| (Wire { connections }, Name::new(format!("Wire: {}", i))));
+            .collect();
 
-        for wire in wires {
-            x.spawn(wire);
+        for (i, wire) in block.wires.iter().enumerate() {
+            let mut drivers = vec![];
+            let mut sinks = vec![];
+            for con in wire.connections.iter() {
+                if con.parent_block == block.id {
+                    if let Some(connection) = find_connection(&inputs, con.id) {
+                        drivers.push(connection);
+                    } else if let Some(connection) = find_connection(&outputs, con.id) {
+                        sinks.push(connection);
+                    } else {
+                        warn!(
+                            "could not find connection with id '{}' in block '{}'",
+                            con.id, block.id
+                        );
+                    }
+                } else if let Some(child) = child_blocks.iter().find(|c| c.id == con.parent_block)
+                {
+                    // seen from the outside, a block's outputs drive and its inputs receive
+                    if let Some(connection) = find_connection(&child.outputs, con.id) {
+                        drivers.push(connection);
+                    } else if let Some(connection) = find_connection(&child.inputs, con.id) {
+                        sinks.push(connection);
+                    } else {
+                        warn!(
+                            "could not find connection with id '{}' in block '{}' inside block '{}'",
+                            con.id, child.id, block.id
+                        );
+                    }
+                } else {
+                    warn!(
+                        "could not find block with id '{}' inside block '{}' (wires can only reach the block itself and its direct children)",
+                        con.parent_block, block.id
+                    );
+                }
+            }
+            x.spawn((
+                Wire {
+                    index: i,
+                    drivers,
+                    sinks,
+                },
+                PropagationDelay(wire.delay),
+                WireWaypoints(wire.waypoints.clone()),
+                Name::new(format!("Wire: {}:{}", block.id, i)),
+            ));
         }
+
+        spawned.inputs = inputs;
+        spawned.outputs = outputs;
     });
+    spawned
+}
+
+/// The connections of a spawned block by their definition id, so the wires of the block
+/// containing it can refer to them.
+struct SpawnedBlock {
+    id: usize,
+    entity: Entity,
+    inputs: Vec<(usize, ConnectionReference)>,
+    outputs: Vec<(usize, ConnectionReference)>,
+}
+fn find_connection(
+    connections: &[(usize, ConnectionReference)],
+    id: usize,
+) -> Option<ConnectionReference> {
+    connections
+        .iter()
+        .find_map(|(connection_id, connection)| (*connection_id == id).then_some(*connection))
 }
 #[derive(Debug, Copy, Clone)]
 enum ConnectionPosition {
@@ -557,14 +1127,43 @@ fn draw_connections(
     }
 }
 
+/// Columns and rows of the grid the bits of a connection are laid out in by [`draw_connection`].
+fn connection_grid(size: usize) -> UVec2 {
+    let size = size as u32;
+    let rows = if size > 8 {
+        size.div_ceil(MAX_BITS_PER_ROW).max(2)
+    } else {
+        1
+    };
+    UVec2::new(size.div_ceil(rows), rows)
+}
+/// A rectangle covering the bits of a connection, so each of them can be clicked.
+fn connection_mesh(meshes: &mut Assets<Mesh>, size: usize) -> Handle<Mesh> {
+    meshes.add(Rectangle::from_size(
+        connection_grid(size).as_vec2() * CONNECTION_SCALE_FACTOR,
+    ))
+}
+/// The bit drawn at `local_pos` (relative to the center of the connection) by [`draw_connection`].
+fn bit_index_at(local_pos: Vec2, size: usize) -> Option<usize> {
+    let grid = connection_grid(size);
+    let cell = (local_pos / CONNECTION_BIT_SIZE + grid.as_vec2() / 2.0).floor();
+    if cell.x < 0.0 || cell.y < 0.0 || cell.x >= grid.x as f32 || cell.y >= grid.y as f32 {
+        return None;
+    }
+    // the lowest bit is drawn on the right
+    let index = cell.y as u32 * grid.x + (grid.x - 1 - cell.x as u32);
+    (index < size as u32).then_some(index as usize)
+}
+
 fn draw_connection(pos: Vec2, connection: &Connection, scale: Vec3, gizmos: &mut Gizmos) {
-    const CONNECTION_BIT_SIZE: f32 = 10.0;
     let connection_bit_size = CONNECTION_BIT_SIZE * scale.xy();
     let connection_bit_half_size_x = connection_bit_size.x * 0.5;
 
     let size = connection.values.len() as u32;
-    let rows = if size > 8 { 2 } else { 1 };
-    let columns = (size as f32 / rows as f32).ceil() as u32;
+    let UVec2 {
+        x: columns,
+        y: rows,
+    } = connection_grid(size as usize);
 
     let half_offset = Vec2::new(columns as f32, rows as f32) * (connection_bit_size / 2.0);
     let half_one_size = connection_bit_size / 2.0;
@@ -578,8 +1177,12 @@ fn draw_connection(pos: Vec2, connection: &Connection, scale: Vec3, gizmos: &mut
             let pos = pos + Vec2::new((columns - x - 1) as f32, (y) as f32) * connection_bit_size
                 - half_offset
                 + half_one_size;
-            let value = connection.values.get_by_index(index as usize);
-            let color = if value { GREEN } else { RED };
+            let color = match connection.values.get_level(index as usize) {
+                LogicLevel::Low => RED,
+                LogicLevel::High => GREEN,
+                LogicLevel::Unknown => YELLOW,
+                LogicLevel::HighImpedance => BLUE,
+            };
             gizmos.circle_2d(pos, connection_bit_half_size_x, color);
         }
     }
@@ -590,59 +1193,25 @@ fn draw_connection(pos: Vec2, connection: &Connection, scale: Vec3, gizmos: &mut
     );
 }
 fn draw_wires(
-    wires: Query<&Wire>,
-    connections: Query<&GlobalTransform, With<Connection>>,
+    wires: Query<(Entity, &WireRoute)>,
+    oscillating: Res<OscillatingWires>,
+    width_mismatches: Res<WidthMismatches>,
     mut gizmos: Gizmos,
 ) {
-    for wire in wires.iter() {
-        let connection_positions: Vec<Vec2> = wire
-            .connections
-            .iter()
-            .filter_map(|connection| {
-                connections
-                    .get(connection.0)
-                    .map(|transform| transform.translation().xy())
-                    .ok()
-            })
-            .collect();
-        let average_pos = connection_positions
-            .iter()
-            .fold(Vec2::ZERO, |sum, pos| sum + pos)
-            / connection_positions.len() as f32;
-        for connection_pos in connection_positions {
-            gizmos.line_2d(average_pos, connection_pos, WHITE);
+    for (entity, route) in wires.iter() {
+        let color = if oscillating.0.contains(&entity) {
+            RED
+        } else if width_mismatches.wires.contains(&entity) {
+            YELLOW
+        } else {
+            WHITE
+        };
+        for (start, end) in route.segments.iter() {
+            gizmos.line_2d(*start, *end, color);
         }
-    }
-}
-
-fn update_connection_states(
-    wires: Query<&Wire>,
-    mut connections: Query<(
-        &mut Connection,
-        Option<&InputConnection>,
-        Option<&OutputConnection>,
-    )>,
-) {
-    for wire in wires.iter() {
-        let input_value: ConnectionValues = wire
-            .connections
-            .iter()
-            .filter_map(|connection| {
-                let conn = connections.get(connection.0).unwrap();
-                if conn.1.is_some() {
-                    Some(conn.0.values)
-                } else {
-                    None
-                }
-            })
-            .fold(ConnectionValues::Single(false), BitOr::bitor);
-        for output in wire.connections.iter() {
-            if let Ok((mut output, _, output_marker)) = connections.get_mut(output.0) {
-                if output_marker.is_none() {
-                    continue;
-                }
-                output.values = input_value;
-            }
+        for junction in route.junctions.iter() {
+            gizmos.circle_2d(*junction, JUNCTION_RADIUS, color);
+            gizmos.circle_2d(*junction, JUNCTION_RADIUS / 2.0, color);
         }
     }
 }
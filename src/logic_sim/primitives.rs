@@ -0,0 +1,122 @@
+use super::*;
+use std::ops::{BitAnd, BitOr, BitXor};
+
+/// What a block computes. [`BlockKind::Composite`] blocks only forward values through their
+/// wires, every other kind derives its outputs from its inputs on each simulation step.
+#[derive(Component, Deserialize, Serialize, Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum BlockKind {
+    #[default]
+    Composite,
+    And,
+    Or,
+    Not,
+    Xor,
+    Nand,
+    Nor,
+    Xnor,
+    /// Passes its input through, turning Z into X like a real buffer.
+    Buf,
+    /// Inputs: D, clock, enable, reset. Stores D on the rising edge of the clock.
+    DFlipFlop,
+    /// Inputs: J, K, clock, enable, reset.
+    JkFlipFlop,
+    /// Inputs: T, clock, enable, reset. Inverts its state on a rising edge while T is high.
+    TFlipFlop,
+    /// Inputs: set, reset, enable, async reset. Level sensitive, setting and resetting at the
+    /// same time makes the state X.
+    SrLatch,
+    /// Inputs: D, enable, reset. Follows D while enabled.
+    DLatch,
+    /// A [`BlockKind::DFlipFlop`] as wide as its first output.
+    Register,
+    /// Has no inputs, its outputs follow a [`ClockGenerator`].
+    Clock,
+    /// Inputs: address, data, write enable, clock. Output: data, see [`Memory::step`].
+    Ram,
+    /// Input: address. Output: data, see [`Memory::step`].
+    Rom,
+    /// Has no inputs and keeps the values of its outputs, clicking it toggles them.
+    Switch,
+    /// Has no inputs, its outputs are high while it is pressed.
+    Button,
+    /// Lights up while its first input is high.
+    Led,
+    /// Shows the segments a to g (bits 0 to 6) and the decimal point (bit 7) of its first input.
+    SevenSegment,
+    /// Shows its first input as a number, formatted as set in its [`DisplaySettings`].
+    NumberDisplay,
+    /// Shows the bits of all of its inputs as a grid of LEDs, see [`DisplaySettings::columns`].
+    LedMatrix,
+    /// Input: the bus. Each output takes its bits of the bus from [`BitRanges`].
+    Splitter,
+    /// Output: the bus. Each input goes into its bits of the bus from [`BitRanges`].
+    Merger,
+}
+
+impl BlockKind {
+    /// The value every output of the block takes for the given inputs (ordered by their index).
+    /// `None` if the block does not compute anything itself, is sequential or has no inputs.
+    ///
+    /// [`BlockKind::Not`] and [`BlockKind::Buf`] only look at their first input.
+    pub fn evaluate(self, inputs: &[ConnectionValues]) -> Option<ConnectionValues> {
+        let (&first, rest) = inputs.split_first()?;
+        let rest = rest.iter().copied();
+        let value = match self {
+            BlockKind::Composite
+            | BlockKind::Clock
+            | BlockKind::Ram
+            | BlockKind::Rom
+            | BlockKind::Switch
+            | BlockKind::Button
+            | BlockKind::Led
+            | BlockKind::SevenSegment
+            | BlockKind::NumberDisplay
+            | BlockKind::LedMatrix
+            | BlockKind::Splitter
+            | BlockKind::Merger => return None,
+            BlockKind::And => rest.fold(first, BitAnd::bitand),
+            BlockKind::Or => rest.fold(first, BitOr::bitor),
+            BlockKind::Xor => rest.fold(first, BitXor::bitxor),
+            BlockKind::Nand => !rest.fold(first, BitAnd::bitand),
+            BlockKind::Nor => !rest.fold(first, BitOr::bitor),
+            BlockKind::Xnor => !rest.fold(first, BitXor::bitxor),
+            BlockKind::Not => !first,
+            BlockKind::Buf => match first {
+                ConnectionValues::Logic(levels) => {
+                    ConnectionValues::Logic(levels.high_impedance_to_unknown())
+                }
+                _ => first,
+            },
+            BlockKind::DFlipFlop
+            | BlockKind::JkFlipFlop
+            | BlockKind::TFlipFlop
+            | BlockKind::SrLatch
+            | BlockKind::DLatch
+            | BlockKind::Register => return None,
+        };
+        Some(value)
+    }
+    /// Whether the block only shows its inputs, see [`DisplaySettings`].
+    pub fn is_display(self) -> bool {
+        matches!(
+            self,
+            BlockKind::Led
+                | BlockKind::SevenSegment
+                | BlockKind::NumberDisplay
+                | BlockKind::LedMatrix
+        )
+    }
+    /// Whether the block holds a [`BlockState`] and is evaluated by [`BlockState::step`] instead
+    /// of [`BlockKind::evaluate`].
+    pub fn is_sequential(self) -> bool {
+        matches!(
+            self,
+            BlockKind::DFlipFlop
+                | BlockKind::JkFlipFlop
+                | BlockKind::TFlipFlop
+                | BlockKind::SrLatch
+                | BlockKind::DLatch
+                | BlockKind::Register
+        )
+    }
+}
@@ -0,0 +1,481 @@
+use super::*;
+use crate::logic_sim::simulation::{SimulationMode, SimulationSettings, SimulationState};
+use bevy::utils::{HashMap, HashSet};
+use std::collections::BinaryHeap;
+
+/// The wires a connection drives its value onto, kept up to date by [`index_wires`].
+#[derive(Component, Debug, Default)]
+pub struct DrivenWires(Vec<Entity>);
+
+/// The wires a connection takes its value from, kept up to date by [`index_wires`]. A sink on
+/// several wires gets the drivers of all of them resolved together.
+#[derive(Component, Debug, Default)]
+pub struct FeedingWires(Vec<Entity>);
+
+/// Ticks it takes for a change to get through a primitive block or along a wire, see
+/// [`BlockDefinition`] and [`WireDefinition`].
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PropagationDelay(pub u32);
+
+/// A value that reaches a connection at a later tick.
+#[derive(Debug)]
+struct ScheduledEvent {
+    tick: u64,
+    /// Keeps events for the same tick in the order they were scheduled.
+    sequence: u64,
+    connection: Entity,
+    value: ConnectionValues,
+}
+impl Eq for ScheduledEvent {}
+impl PartialEq for ScheduledEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl PartialOrd for ScheduledEvent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+/// Reversed, so the earliest event is on top of the [`BinaryHeap`].
+impl Ord for ScheduledEvent {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.tick, other.sequence).cmp(&(self.tick, self.sequence))
+    }
+}
+
+/// Everything that changed since the last tick. Only the wires and blocks downstream of these get
+/// evaluated, so an idle circuit costs nothing.
+///
+/// Changes passing a [`PropagationDelay`] wait here, ordered by the tick they take effect in.
+#[derive(Resource, Debug, Default)]
+pub struct DirtyQueue {
+    connections: HashSet<Entity>,
+    wires: HashSet<Entity>,
+    scheduled: BinaryHeap<ScheduledEvent>,
+    next_sequence: u64,
+    /// The last value scheduled for each connection and its sequence number.
+    latest_scheduled: HashMap<Entity, (u64, ConnectionValues)>,
+}
+impl DirtyQueue {
+    pub fn mark_connection(&mut self, connection: Entity) {
+        self.connections.insert(connection);
+    }
+    pub fn mark_wire(&mut self, wire: Entity) {
+        self.wires.insert(wire);
+    }
+    /// Whether nothing is left to do in the current tick. Scheduled events do not count.
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty() && self.wires.is_empty()
+    }
+    pub fn has_scheduled(&self) -> bool {
+        !self.scheduled.is_empty()
+    }
+    /// Sets `connection` to `value` at the start of `tick`. Nothing is scheduled if the
+    /// connection would end up with the value it has anyway, so a steady input does not flood
+    /// the queue.
+    fn schedule(
+        &mut self,
+        tick: u64,
+        connection: Entity,
+        value: ConnectionValues,
+        current: ConnectionValues,
+    ) {
+        let latest = self
+            .latest_scheduled
+            .get(&connection)
+            .map_or(current, |(_, value)| *value);
+        if latest == value {
+            return;
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.latest_scheduled.insert(connection, (sequence, value));
+        self.scheduled.push(ScheduledEvent {
+            tick,
+            sequence,
+            connection,
+            value,
+        });
+    }
+    /// The next event that is due at `tick`.
+    fn pop_due(&mut self, tick: u64) -> Option<ScheduledEvent> {
+        if self.scheduled.peek()?.tick > tick {
+            return None;
+        }
+        let event = self.scheduled.pop()?;
+        if self
+            .latest_scheduled
+            .get(&event.connection)
+            .is_some_and(|(sequence, _)| *sequence == event.sequence)
+        {
+            self.latest_scheduled.remove(&event.connection);
+        }
+        Some(event)
+    }
+}
+
+type ConnectionQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Connection,
+        &'static DrivenWires,
+        &'static BlockReference,
+        Option<&'static InputConnection>,
+        Option<&'static OutputConnection>,
+        &'static FeedingWires,
+    ),
+>;
+type WireQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Wire,
+        &'static PropagationDelay,
+        Option<&'static Name>,
+    ),
+>;
+type BlockQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static BlockKind,
+        &'static PropagationDelay,
+        &'static Children,
+        Option<&'static mut BlockState>,
+        Option<&'static mut Memory>,
+        Option<&'static BitRanges>,
+    ),
+>;
+
+/// Rebuilds [`DrivenWires`] and [`FeedingWires`] whenever a wire is added, changed or removed.
+pub(super) fn index_wires(
+    changed_wires: Query<Entity, Changed<Wire>>,
+    mut removed_wires: RemovedComponents<Wire>,
+    wires: Query<(Entity, &Wire)>,
+    mut indices: Query<(&mut DrivenWires, &mut FeedingWires)>,
+    mut queue: ResMut<DirtyQueue>,
+) {
+    let removed = removed_wires.read().count();
+    if changed_wires.is_empty() && removed == 0 {
+        return;
+    }
+    for (mut driven, mut feeding) in indices.iter_mut() {
+        driven.0.clear();
+        feeding.0.clear();
+    }
+    for (entity, wire) in wires.iter() {
+        for driver in wire.drivers.iter() {
+            if let Ok((mut driven, _)) = indices.get_mut(driver.0) {
+                driven.0.push(entity);
+            }
+        }
+        for sink in wire.sinks.iter() {
+            if let Ok((_, mut feeding)) = indices.get_mut(sink.0) {
+                feeding.0.push(entity);
+            }
+        }
+    }
+    for wire in changed_wires.iter() {
+        queue.mark_wire(wire);
+    }
+}
+
+/// Picks up values changed outside of the simulation, e.g. newly spawned connections.
+/// The simulation itself writes without triggering change detection and queues what it changes.
+pub(super) fn queue_changed_connections(
+    changed: Query<Entity, Changed<Connection>>,
+    mut queue: ResMut<DirtyQueue>,
+) {
+    for connection in changed.iter() {
+        queue.mark_connection(connection);
+    }
+}
+
+/// The wires that still changed when [`propagate`] gave up on reaching a stable state, i.e. the
+/// wires of a combinational loop that never settles (such as a ring oscillator).
+#[derive(Resource, Debug, Default)]
+pub struct OscillatingWires(pub Vec<Entity>);
+
+/// Applies the scheduled events that are due, then moves the queued changes through the circuit
+/// until nothing changes anymore, wave by wave: the wires driven by changed connections take
+/// their new values, then the primitive blocks whose inputs changed get evaluated. Results
+/// passing a [`PropagationDelay`] are scheduled for a later tick instead.
+///
+/// Within a wave new values are computed from the state before any of them are written, so the
+/// result does not depend on the order in which wires and blocks are visited.
+///
+/// If the circuit is still changing after [`SimulationSettings::max_waves_per_step`] waves, the
+/// wires that kept changing are reported and the simulation pauses.
+pub(super) fn propagate(
+    wires: WireQuery,
+    mut blocks: BlockQuery,
+    mut connections: ConnectionQuery,
+    mut queue: ResMut<DirtyQueue>,
+    mut simulation: ResMut<SimulationState>,
+    mut settings: ResMut<SimulationSettings>,
+    mut oscillating: ResMut<OscillatingWires>,
+) {
+    while let Some(event) = queue.pop_due(simulation.tick) {
+        if write_connection(
+            event.connection,
+            event.value,
+            &mut connections,
+            &mut simulation,
+        ) {
+            queue.mark_connection(event.connection);
+        }
+    }
+
+    let max_waves = settings.max_waves_per_step.max(1);
+    // the last wave in which each wire changed one of its sinks
+    let mut last_changes = HashMap::new();
+    let mut wave = 0;
+    while !queue.is_empty() {
+        if wave == max_waves {
+            // parts of the circuit that settle do so early, whatever still changed during the
+            // second half is part of the loop
+            let mut changing: Vec<_> = last_changes
+                .into_iter()
+                .filter(|(_, last_change)| *last_change >= max_waves / 2)
+                .map(|(wire, _)| wire)
+                .collect();
+            changing.sort();
+            let wire_names: Vec<String> = changing
+                .iter()
+                .map(|wire| match wires.get(*wire) {
+                    Ok((_, _, Some(name))) => name.to_string(),
+                    _ => format!("{wire}"),
+                })
+                .collect();
+            error!(
+                "circuit did not settle within {max_waves} waves in tick {}, these wires keep changing: {}",
+                simulation.tick,
+                wire_names.join(", ")
+            );
+            settings.mode = SimulationMode::Paused;
+            oscillating.0 = changing;
+            return;
+        }
+        propagate_wave(
+            wave,
+            &wires,
+            &mut blocks,
+            &mut connections,
+            &mut queue,
+            &mut simulation,
+            &mut last_changes,
+        );
+        wave += 1;
+    }
+    if !oscillating.0.is_empty() {
+        oscillating.0.clear();
+    }
+}
+
+fn propagate_wave(
+    wave: u32,
+    wires: &WireQuery,
+    blocks: &mut BlockQuery,
+    connections: &mut ConnectionQuery,
+    queue: &mut DirtyQueue,
+    simulation: &mut SimulationState,
+    last_changes: &mut HashMap<Entity, u32>,
+) {
+    let dirty_connections = std::mem::take(&mut queue.connections);
+    let mut dirty_wires = std::mem::take(&mut queue.wires);
+    let mut dirty_blocks = HashSet::new();
+    for connection in dirty_connections {
+        mark_downstream(
+            connection,
+            connections,
+            blocks,
+            &mut dirty_wires,
+            &mut dirty_blocks,
+        );
+    }
+
+    // every sink is resolved from all of its wires, so the order the wires come in does not
+    // matter
+    let current: &ConnectionQuery = connections;
+    let updates: Vec<_> = dirty_wires
+        .iter()
+        .filter_map(|entity| Some((*entity, wires.get(*entity).ok()?)))
+        .flat_map(|(entity, (wire, delay, _))| {
+            wire.sinks.iter().filter_map(move |sink| {
+                let value = resolve_sink(sink.0, wires, current)?;
+                Some((entity, *delay, sink.0, value))
+            })
+        })
+        .collect();
+    for (wire, delay, connection, value) in updates {
+        if delay.0 > 0 {
+            schedule(connection, value, delay, connections, queue, simulation);
+        } else if write_connection(connection, value, connections, simulation) {
+            last_changes.insert(wire, wave);
+            queue.mark_connection(connection);
+            mark_primitive_block(connection, connections, blocks, &mut dirty_blocks);
+        }
+    }
+
+    let mut updates = vec![];
+    for block in dirty_blocks {
+        let Ok((kind, delay, children, state, memory, ranges)) = blocks.get_mut(block) else {
+            continue;
+        };
+        let outputs = evaluate_block(*kind, state, memory, ranges, children, connections);
+        updates.extend(
+            outputs
+                .into_iter()
+                .map(|(connection, value)| (*delay, connection, value)),
+        );
+    }
+    for (delay, connection, value) in updates {
+        if delay.0 > 0 {
+            schedule(connection, value, delay, connections, queue, simulation);
+        } else if write_connection(connection, value, connections, simulation) {
+            queue.mark_connection(connection);
+        }
+    }
+}
+
+fn mark_downstream(
+    connection: Entity,
+    connections: &ConnectionQuery,
+    blocks: &BlockQuery,
+    dirty_wires: &mut HashSet<Entity>,
+    dirty_blocks: &mut HashSet<Entity>,
+) {
+    if let Ok((_, driven_wires, _, _, _, _)) = connections.get(connection) {
+        dirty_wires.extend(driven_wires.0.iter().copied());
+    }
+    mark_primitive_block(connection, connections, blocks, dirty_blocks);
+}
+
+fn mark_primitive_block(
+    connection: Entity,
+    connections: &ConnectionQuery,
+    blocks: &BlockQuery,
+    dirty_blocks: &mut HashSet<Entity>,
+) {
+    let Ok((_, _, block, Some(_), _, _)) = connections.get(connection) else {
+        return;
+    };
+    if matches!(blocks.get(block.0), Ok((kind, _, _, _, _, _)) if *kind != BlockKind::Composite) {
+        dirty_blocks.insert(block.0);
+    }
+}
+
+/// The new value of `sink`: the drivers of every wire it is on resolved together, cut or zero
+/// extended to the width of the sink. Without any driver the sink floats (Z).
+fn resolve_sink(
+    sink: Entity,
+    wires: &WireQuery,
+    connections: &ConnectionQuery,
+) -> Option<ConnectionValues> {
+    let (connection, _, _, _, _, feeding) = connections.get(sink).ok()?;
+    let input_value: Option<ConnectionValues> = feeding
+        .0
+        .iter()
+        .filter_map(|wire| wires.get(*wire).ok())
+        .flat_map(|(wire, _, _)| wire.drivers.iter())
+        .filter_map(|driver| connections.get(driver.0).ok())
+        .map(|(driver, _, _, _, _, _)| driver.values)
+        .reduce(ConnectionValues::resolve);
+    Some(match input_value {
+        Some(value) => connection.values.with_values_of(value),
+        None => ConnectionValues::Logic(LogicVector::high_impedance(connection.values.len())),
+    })
+}
+
+/// The new value of every output of a primitive block, computed from its inputs. Sequential
+/// blocks update their state, their first output shows it and any other output its inverse.
+/// Memory blocks show the addressed word on every output.
+fn evaluate_block(
+    kind: BlockKind,
+    state: Option<Mut<BlockState>>,
+    memory: Option<Mut<Memory>>,
+    ranges: Option<&BitRanges>,
+    children: &Children,
+    connections: &ConnectionQuery,
+) -> Vec<(Entity, ConnectionValues)> {
+    let mut inputs: Vec<_> = children
+        .iter()
+        .filter_map(|child| match connections.get(*child) {
+            Ok((connection, _, _, Some(_), _, _)) => Some((connection.index, connection.values)),
+            _ => None,
+        })
+        .collect();
+    inputs.sort_by_key(|(index, _)| *index);
+    let inputs: Vec<_> = inputs.into_iter().map(|(_, values)| values).collect();
+    if let Some(ranges) = ranges {
+        // every output of a splitter or merger has its own value
+        return children
+            .iter()
+            .filter_map(|child| match connections.get(*child) {
+                Ok((output, _, _, _, Some(_), _)) => {
+                    let value = ranges.output_value(kind, &inputs, output.index, output.values)?;
+                    Some((*child, value))
+                }
+                _ => None,
+            })
+            .collect();
+    }
+    let (first, rest) = match (state, memory) {
+        (Some(mut state), _) => {
+            let value = state.step(kind, &inputs);
+            (value, !value)
+        }
+        (None, Some(mut memory)) => {
+            let value = memory.step(kind, &inputs);
+            (value, value)
+        }
+        (None, None) => match kind.evaluate(&inputs) {
+            Some(value) => (value, value),
+            None => return vec![],
+        },
+    };
+    children
+        .iter()
+        .filter_map(|child| match connections.get(*child) {
+            Ok((output, _, _, _, Some(_), _)) => {
+                let value = if output.index == 0 { first } else { rest };
+                Some((*child, output.values.with_values_of(value)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn schedule(
+    connection: Entity,
+    value: ConnectionValues,
+    delay: PropagationDelay,
+    connections: &ConnectionQuery,
+    queue: &mut DirtyQueue,
+    simulation: &SimulationState,
+) {
+    if let Ok((current, _, _, _, _, _)) = connections.get(connection) {
+        let tick = simulation.tick + u64::from(delay.0);
+        queue.schedule(tick, connection, value, current.values);
+    }
+}
+
+/// Returns whether the value actually changed.
+fn write_connection(
+    connection: Entity,
+    value: ConnectionValues,
+    connections: &mut ConnectionQuery,
+    simulation: &mut SimulationState,
+) -> bool {
+    let Ok((mut connection, _, _, _, _, _)) = connections.get_mut(connection) else {
+        return false;
+    };
+    if connection.values == value {
+        return false;
+    }
+    connection.bypass_change_detection().values = value;
+    simulation.mark_changed();
+    true
+}
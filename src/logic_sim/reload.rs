@@ -0,0 +1,162 @@
+use super::*;
+use crate::logic_sim::memory::{Memory, MemoryImageSource};
+use bevy::utils::HashMap;
+
+/// Respawns the circuit whenever its `.blockdef.json` file or any file it includes changes on
+/// disk, keeping the values of connections, the state of flip-flops and registers and the
+/// contents of RAMs wherever the ids of the block and connection still exist.
+pub struct ReloadPlugin;
+impl Plugin for ReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PreservedState>().add_systems(
+            Update,
+            // restoring first, so the state saved in a frame is not put back into the old circuit
+            (
+                restore_preserved_state,
+                watch_block_definitions.run_if(block_definitions_changed),
+            )
+                .chain()
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+/// The state of the circuit from before a reload, by the ids of the blocks from the root block
+/// down to the block itself.
+#[derive(Resource, Debug, Default)]
+pub(super) struct PreservedState {
+    blocks: HashMap<Vec<usize>, PreservedBlock>,
+}
+#[derive(Debug)]
+struct PreservedBlock {
+    /// Values by connection id.
+    connections: HashMap<usize, ConnectionValues>,
+    state: Option<BlockState>,
+    memory: Option<Memory>,
+}
+
+pub(super) type BlockTreeQuery<'w, 's> = Query<'w, 's, (&'static Block, Option<&'static Parent>)>;
+
+/// The ids of the blocks from the root block down to `block`.
+pub(super) fn block_path(block: Entity, blocks: &BlockTreeQuery) -> Vec<usize> {
+    let mut path = vec![];
+    let mut current = Some(block);
+    while let Some(Ok((block, parent))) = current.map(|entity| blocks.get(entity)) {
+        path.push(block.id);
+        current = parent.map(Parent::get);
+    }
+    path.reverse();
+    path
+}
+
+type SavedStateQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static BlockKind,
+        &'static Children,
+        Option<&'static BlockState>,
+        Option<&'static Memory>,
+    ),
+>;
+type RestoredStateQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Children,
+        Option<&'static mut BlockState>,
+        Option<&'static mut Memory>,
+        Option<&'static mut MemoryImageSource>,
+    ),
+    With<Block>,
+>;
+
+/// Whether the file of the circuit or any file it includes changed on disk.
+fn block_definitions_changed(
+    mut events: EventReader<AssetEvent<BlockDefinition>>,
+    block_handle: Res<BlockDefinitionHandle>,
+    library: Res<BlockLibrary>,
+) -> bool {
+    events.read().any(|event| match event {
+        AssetEvent::Modified { id } => *id == block_handle.0.id() || library.contains(*id),
+        _ => false,
+    })
+}
+
+pub(super) fn watch_block_definitions(
+    blocks: BlockTreeQuery,
+    saved: SavedStateQuery,
+    connections: Query<&Connection>,
+    mut preserved: ResMut<PreservedState>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    info!("block definitions changed on disk, reloading the circuit");
+    preserved.blocks = saved
+        .iter()
+        .map(|(entity, kind, children, block_state, memory)| {
+            let connections = children
+                .iter()
+                .filter_map(|child| connections.get(*child).ok())
+                .map(|connection| (connection.id, connection.values))
+                .collect();
+            let block = PreservedBlock {
+                connections,
+                state: block_state.copied(),
+                // ROMs take their contents from their image again, it may have been changed too
+                memory: memory.filter(|_| *kind == BlockKind::Ram).cloned(),
+            };
+            (block_path(entity, &blocks), block)
+        })
+        .collect();
+    state.set(AppState::Loading);
+}
+
+/// Puts the state saved by [`watch_block_definitions`] into the respawned circuit, once.
+/// Values whose width changed are left at their new defaults.
+pub(super) fn restore_preserved_state(
+    mut preserved: ResMut<PreservedState>,
+    blocks: BlockTreeQuery,
+    mut restored: RestoredStateQuery,
+    mut connections: Query<&mut Connection>,
+) {
+    if preserved.blocks.is_empty() {
+        return;
+    }
+    let mut saved = std::mem::take(&mut preserved.blocks);
+    for (entity, children, block_state, memory, image_source) in restored.iter_mut() {
+        let Some(block) = saved.remove(&block_path(entity, &blocks)) else {
+            continue;
+        };
+        for child in children.iter() {
+            let Ok(mut connection) = connections.get_mut(*child) else {
+                continue;
+            };
+            match block.connections.get(&connection.id) {
+                Some(values) if values.len() == connection.values.len() => {
+                    connection.values = *values;
+                }
+                _ => {}
+            }
+        }
+        match (block_state, block.state) {
+            (Some(mut block_state), Some(state))
+                if state.value.len() == block_state.value.len() =>
+            {
+                *block_state = state;
+            }
+            _ => {}
+        }
+        match (memory, block.memory) {
+            (Some(mut memory), Some(saved_memory)) if memory.has_same_size(&saved_memory) => {
+                *memory = saved_memory;
+                // the kept contents win over the initial contents from the image
+                if let Some(mut image_source) = image_source {
+                    image_source.mark_applied();
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use super::*;
+use bevy::utils::HashMap;
+use std::collections::BinaryHeap;
+
+/// Space kept between wires and the blocks they go around, also the length of the straight piece
+/// a wire leaves its connection with.
+pub const ROUTE_MARGIN: f32 = 10.0;
+/// Extra length a bend counts as, so routes take as few turns as they can.
+const BEND_COST: f32 = 20.0;
+/// Size of the dots drawn where a net branches.
+pub const JUNCTION_RADIUS: f32 = 3.0;
+
+/// Routes wires with horizontal and vertical lines around the blocks next to them, through the
+/// waypoints of the wire if it has any. Dots mark where a net branches.
+pub struct RoutingPlugin;
+impl Plugin for RoutingPlugin {
+    fn build(&self, app: &mut App) {
+        // after the transforms of moved blocks reached their connections, so routes are never
+        // a frame behind
+        app.add_systems(
+            PostUpdate,
+            route_wires.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Points a wire goes through, in the coordinates of the block containing it. The first part of
+/// the route runs from one waypoint to the next, the connections of the wire join it where it is
+/// closest to them.
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct WireWaypoints(pub Vec<Vec2>);
+
+/// Where a wire meets one of its connections: the position of the connection and the direction
+/// the wire leaves it in, away from the block it sits on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RouteEnd {
+    pub position: Vec2,
+    pub exit: Vec2,
+}
+
+/// The lines a wire is drawn with and the points where its net branches, in world coordinates.
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct WireRoute {
+    pub segments: Vec<(Vec2, Vec2)>,
+    pub junctions: Vec<Vec2>,
+    /// What the route was made from, it is only made again when this changes.
+    source: Option<RouteSource>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RouteSource {
+    ends: Vec<RouteEnd>,
+    waypoints: Vec<Vec2>,
+    obstacles: Vec<Rect>,
+}
+
+impl WireRoute {
+    /// Routes the wire again if its ends, its waypoints or the blocks in its way changed.
+    pub fn update(&mut self, ends: Vec<RouteEnd>, waypoints: Vec<Vec2>, obstacles: Vec<Rect>) {
+        let source = RouteSource {
+            ends,
+            waypoints,
+            obstacles,
+        };
+        if self.source.as_ref() == Some(&source) {
+            return;
+        }
+        *self = route_wire(&source.ends, &source.waypoints, &source.obstacles);
+        self.source = Some(source);
+    }
+}
+
+/// Connects the ends through the waypoints, staying out of the obstacles. Without waypoints the
+/// first end starts the net, usually a driver. The other ends are joined one by one, the one
+/// closest to the net first.
+pub fn route_wire(ends: &[RouteEnd], waypoints: &[Vec2], obstacles: &[Rect]) -> WireRoute {
+    let mut segments = vec![];
+    let mut unjoined = vec![];
+    for end in ends {
+        let stub = end.position + end.exit * ROUTE_MARGIN;
+        segments.push((end.position, stub));
+        unjoined.push(stub);
+    }
+    // lines the remaining ends can join, a single point is a line of no length
+    let mut net = vec![];
+    match waypoints {
+        [] if unjoined.is_empty() => {}
+        [] => {
+            let first = unjoined.remove(0);
+            net.push((first, first));
+        }
+        [waypoint] => net.push((*waypoint, *waypoint)),
+        _ => {
+            for pair in waypoints.windows(2) {
+                push_path(&mut net, &route_path(pair[0], pair[1], obstacles));
+            }
+        }
+    }
+    while !unjoined.is_empty() {
+        let Some((index, target)) = unjoined
+            .iter()
+            .enumerate()
+            .filter_map(|(index, stub)| Some((index, closest_on_net(*stub, &net)?)))
+            .min_by(|(a, target_a), (b, target_b)| {
+                manhattan(unjoined[*a], *target_a).total_cmp(&manhattan(unjoined[*b], *target_b))
+            })
+        else {
+            break;
+        };
+        let stub = unjoined.remove(index);
+        push_path(&mut net, &route_path(stub, target, obstacles));
+    }
+    segments.extend(net.into_iter().filter(|(start, end)| start != end));
+    WireRoute {
+        junctions: junctions(&segments),
+        segments,
+        source: None,
+    }
+}
+
+/// The point of the net closest to `point`.
+fn closest_on_net(point: Vec2, net: &[(Vec2, Vec2)]) -> Option<Vec2> {
+    net.iter()
+        .map(|(start, end)| closest_on_segment(point, *start, *end))
+        .min_by(|a, b| manhattan(point, *a).total_cmp(&manhattan(point, *b)))
+}
+
+fn closest_on_segment(point: Vec2, start: Vec2, end: Vec2) -> Vec2 {
+    let line = end - start;
+    let t = if line.length_squared() > 0.0 {
+        ((point - start).dot(line) / line.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    start + line * t
+}
+
+fn manhattan(a: Vec2, b: Vec2) -> f32 {
+    (a.x - b.x).abs() + (a.y - b.y).abs()
+}
+
+fn push_path(segments: &mut Vec<(Vec2, Vec2)>, path: &[Vec2]) {
+    segments.extend(path.windows(2).map(|pair| (pair[0], pair[1])));
+}
+
+/// Whether the line from `start` to `end` passes through the inside of `obstacle`. Lines along
+/// its border do not.
+fn crosses(obstacle: &Rect, start: Vec2, end: Vec2) -> bool {
+    let low = start.min(end);
+    let high = start.max(end);
+    low.x < obstacle.max.x
+        && high.x > obstacle.min.x
+        && low.y < obstacle.max.y
+        && high.y > obstacle.min.y
+}
+
+/// A step of the search in [`route_path`], ordered so the cheapest comes out of the heap first.
+#[derive(Debug)]
+struct Step {
+    cost: f32,
+    state: usize,
+}
+impl PartialEq for Step {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Step {}
+impl PartialOrd for Step {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Step {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// The shortest path of horizontal and vertical lines from `from` to `to` that stays out of the
+/// obstacles, with as few bends as it can. The lines run along the obstacles at
+/// [`ROUTE_MARGIN`]. Obstacles `from` or `to` lie in are ignored, if there is no way around the
+/// others the path goes straight through them.
+pub fn route_path(from: Vec2, to: Vec2, obstacles: &[Rect]) -> Vec<Vec2> {
+    if from == to {
+        return vec![from];
+    }
+    let obstacles: Vec<Rect> = obstacles
+        .iter()
+        .filter(|obstacle| !crosses(obstacle, from, from) && !crosses(obstacle, to, to))
+        .copied()
+        .collect();
+    // the lines the path can run on: through both ends and along every obstacle
+    let mut xs = vec![from.x, to.x];
+    let mut ys = vec![from.y, to.y];
+    for obstacle in obstacles.iter() {
+        xs.extend([obstacle.min.x - ROUTE_MARGIN, obstacle.max.x + ROUTE_MARGIN]);
+        ys.extend([obstacle.min.y - ROUTE_MARGIN, obstacle.max.y + ROUTE_MARGIN]);
+    }
+    for lines in [&mut xs, &mut ys] {
+        lines.sort_by(f32::total_cmp);
+        lines.dedup();
+    }
+    let column = |x: f32| xs.iter().position(|line| *line == x);
+    let row = |y: f32| ys.iter().position(|line| *line == y);
+    let (Some(start_x), Some(start_y), Some(goal_x), Some(goal_y)) =
+        (column(from.x), row(from.y), column(to.x), row(to.y))
+    else {
+        return vec![from, Vec2::new(to.x, from.y), to];
+    };
+    // a state is a crossing of the lines together with the direction it was reached from
+    let point = |x: usize, y: usize| Vec2::new(xs[x], ys[y]);
+    let state =
+        |x: usize, y: usize, direction: usize| (y * xs.len() + x) * DIRECTIONS.len() + direction;
+    let unpack = |state: usize| {
+        let crossing = state / DIRECTIONS.len();
+        (
+            crossing % xs.len(),
+            crossing / xs.len(),
+            state % DIRECTIONS.len(),
+        )
+    };
+    let mut costs = vec![f32::INFINITY; xs.len() * ys.len() * DIRECTIONS.len()];
+    let mut previous = vec![None; costs.len()];
+    let mut heap = BinaryHeap::new();
+    for direction in 0..DIRECTIONS.len() {
+        let start = state(start_x, start_y, direction);
+        costs[start] = 0.0;
+        heap.push(Step {
+            cost: 0.0,
+            state: start,
+        });
+    }
+    let mut goal = None;
+    while let Some(Step {
+        cost,
+        state: current,
+    }) = heap.pop()
+    {
+        if cost > costs[current] {
+            continue;
+        }
+        let (x, y, direction) = unpack(current);
+        if (x, y) == (goal_x, goal_y) {
+            goal = Some(current);
+            break;
+        }
+        for (next_direction, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+            let (Some(next_x), Some(next_y)) =
+                (x.checked_add_signed(*dx), y.checked_add_signed(*dy))
+            else {
+                continue;
+            };
+            if next_x >= xs.len() || next_y >= ys.len() {
+                continue;
+            }
+            let (start, end) = (point(x, y), point(next_x, next_y));
+            if obstacles
+                .iter()
+                .any(|obstacle| crosses(obstacle, start, end))
+            {
+                continue;
+            }
+            let bend = if next_direction == direction {
+                0.0
+            } else {
+                BEND_COST
+            };
+            let next_cost = cost + start.distance(end) + bend;
+            let next = state(next_x, next_y, next_direction);
+            if next_cost < costs[next] {
+                costs[next] = next_cost;
+                previous[next] = Some(current);
+                heap.push(Step {
+                    cost: next_cost,
+                    state: next,
+                });
+            }
+        }
+    }
+    let Some(goal) = goal else {
+        return vec![from, Vec2::new(to.x, from.y), to];
+    };
+    let mut path = vec![];
+    let mut current = Some(goal);
+    while let Some(state) = current {
+        let (x, y, _) = unpack(state);
+        path.push(point(x, y));
+        current = previous[state];
+    }
+    path.reverse();
+    simplify(path)
+}
+
+/// Leaves out points that do not start a new line.
+fn simplify(path: Vec<Vec2>) -> Vec<Vec2> {
+    let mut simplified: Vec<Vec2> = vec![];
+    for point in path {
+        if simplified.last() == Some(&point) {
+            continue;
+        }
+        if let [.., before, last] = simplified[..] {
+            let straight = (before.x == last.x && last.x == point.x)
+                || (before.y == last.y && last.y == point.y);
+            if straight {
+                simplified.pop();
+            }
+        }
+        simplified.push(point);
+    }
+    simplified
+}
+
+/// Points where three or more lines meet, counting a line passing through a point twice.
+fn junctions(segments: &[(Vec2, Vec2)]) -> Vec<Vec2> {
+    let mut points: Vec<Vec2> = segments
+        .iter()
+        .flat_map(|(start, end)| [*start, *end])
+        .collect();
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    points
+        .into_iter()
+        .filter(|point| {
+            let lines: usize = segments
+                .iter()
+                .map(|(start, end)| {
+                    if start == point || end == point {
+                        1
+                    } else if closest_on_segment(*point, *start, *end).distance(*point) < 0.01 {
+                        2
+                    } else {
+                        0
+                    }
+                })
+                .sum();
+            lines >= 3
+        })
+        .collect()
+}
+
+/// Where `point` goes among `waypoints` so the line through them gets the least longer.
+pub fn waypoint_insert_index(waypoints: &[Vec2], point: Vec2) -> usize {
+    let added = |index: usize| match (
+        index.checked_sub(1).map(|i| waypoints[i]),
+        waypoints.get(index),
+    ) {
+        (Some(before), Some(after)) => {
+            before.distance(point) + point.distance(*after) - before.distance(*after)
+        }
+        (Some(before), None) => before.distance(point),
+        (None, Some(after)) => point.distance(*after),
+        (None, None) => 0.0,
+    };
+    (0..=waypoints.len())
+        .min_by(|a, b| added(*a).total_cmp(&added(*b)))
+        .unwrap_or_default()
+}
+
+type RoutedWireQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Wire,
+        &'static Parent,
+        &'static WireWaypoints,
+        &'static GlobalTransform,
+        &'static mut WireRoute,
+    ),
+>;
+
+pub(super) fn route_wires(
+    mut wires: RoutedWireQuery,
+    connections: Query<(&GlobalTransform, &BlockReference, Has<InputConnection>)>,
+    blocks: Query<(&BlockVisuals, &GlobalTransform, &Parent), With<Block>>,
+) {
+    let mut obstacles: HashMap<Entity, Vec<Rect>> = HashMap::new();
+    for (visuals, transform, parent) in blocks.iter() {
+        let size = visuals.size.as_vec2() * transform.scale().xy();
+        obstacles
+            .entry(parent.get())
+            .or_default()
+            .push(Rect::from_center_size(transform.translation().xy(), size));
+    }
+    for (wire, parent, waypoints, transform, mut route) in wires.iter_mut() {
+        let container = parent.get();
+        let ends = wire
+            .connections()
+            .filter_map(|connection| {
+                let (connection, owner, is_input) = connections.get(connection.0).ok()?;
+                // inputs sit on the left of a block and outputs on the right, wires leave the
+                // blocks inside the container outwards and the container itself inwards
+                let outwards = if is_input { -Vec2::X } else { Vec2::X };
+                Some(RouteEnd {
+                    position: connection.translation().xy(),
+                    exit: if owner.0 == container {
+                        -outwards
+                    } else {
+                        outwards
+                    },
+                })
+            })
+            .collect();
+        let waypoints = waypoints
+            .0
+            .iter()
+            .map(|waypoint| transform.transform_point(waypoint.extend(0.0)).xy())
+            .collect();
+        let obstacles = obstacles.get(&container).cloned().unwrap_or_default();
+        route.update(ends, waypoints, obstacles);
+    }
+}
@@ -0,0 +1,240 @@
+use super::*;
+use crate::logic_sim::editor::keyboard_free;
+use crate::logic_sim::library::restore_instances;
+use crate::logic_sim::memory::MemoryImageSource;
+use bevy::asset::io::file::FileAssetReader;
+use bevy::ecs::system::SystemParam;
+
+/// Folder the asset server loads from, the default of [`AssetPlugin::file_path`].
+const ASSET_FOLDER: &str = "assets";
+
+/// Writes the circuit as it is, edits and connection values included, back to the
+/// `.blockdef.json` file it was loaded from with Ctrl+S. Blocks that are still instances of a
+/// library file are written as instances again. Like any other change of the file, saving
+/// reloads the circuit.
+pub struct SavePlugin;
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            save_circuit.run_if(
+                in_state(AppState::Running)
+                    .and(save_requested)
+                    .and(keyboard_free),
+            ),
+        );
+    }
+}
+
+fn save_requested(input: Res<ButtonInput<KeyCode>>) -> bool {
+    input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        && input.just_pressed(KeyCode::KeyS)
+}
+
+type SavedBlockQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Block,
+        &'static BlockKind,
+        &'static BlockVisuals,
+        &'static Transform,
+        &'static PropagationDelay,
+        &'static Children,
+        Option<&'static ClockGenerator>,
+        Option<&'static DisplaySettings>,
+        Option<&'static BitRanges>,
+        Option<&'static MemoryImageSource>,
+        Option<&'static LibraryInstance>,
+    ),
+>;
+type SavedConnectionQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Connection,
+        &'static BlockReference,
+        Has<InputConnection>,
+    ),
+>;
+type SavedWireQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Wire,
+        &'static PropagationDelay,
+        &'static WireWaypoints,
+    ),
+>;
+
+/// Turns spawned blocks and wires back into their definitions.
+#[derive(SystemParam)]
+pub(super) struct CircuitDefinitions<'w, 's> {
+    blocks: SavedBlockQuery<'w, 's>,
+    connections: SavedConnectionQuery<'w, 's>,
+    wires: SavedWireQuery<'w, 's>,
+}
+impl CircuitDefinitions<'_, '_> {
+    /// The definition [`spawn_block_definition`] would spawn `block` and everything inside it
+    /// from. Connections keep their current values.
+    pub(super) fn block(&self, block: Entity) -> Option<BlockDefinition> {
+        block_definition(block, &self.blocks, &self.connections, &self.wires)
+    }
+    pub(super) fn wire(&self, wire: Entity) -> Option<WireDefinition> {
+        let (wire, delay, waypoints) = self.wires.get(wire).ok()?;
+        Some(wire_definition(
+            wire,
+            delay,
+            waypoints,
+            &self.blocks,
+            &self.connections,
+        ))
+    }
+}
+
+fn save_circuit(
+    block_handle: Res<BlockDefinitionHandle>,
+    problems: Res<DefinitionProblems>,
+    roots: Query<&Children, With<Root>>,
+    circuit: CircuitDefinitions,
+) {
+    let Some(path) = block_handle.0.path() else {
+        warn!("the circuit was not loaded from a file and cannot be saved");
+        return;
+    };
+    // the circuit on screen is the one from before the file broke, it would overwrite the fix
+    if !problems.problems.is_empty() {
+        warn!("not saving over '{path}' while it has problems");
+        return;
+    }
+    let Some(mut definition) = roots
+        .iter()
+        .flat_map(|children| children.iter())
+        .find_map(|top| circuit.block(*top))
+    else {
+        warn!("there is no circuit to save");
+        return;
+    };
+    restore_instances(&mut definition);
+    let file = FileAssetReader::get_base_path()
+        .join(ASSET_FOLDER)
+        .join(path.path());
+    let json = match serde_json::to_string_pretty(&definition) {
+        Ok(json) => json,
+        Err(error) => {
+            error!("could not save the circuit: {error}");
+            return;
+        }
+    };
+    match std::fs::write(&file, json) {
+        Ok(()) => info!("saved the circuit to '{}'", file.display()),
+        Err(error) => error!(
+            "could not save the circuit to '{}': {error}",
+            file.display()
+        ),
+    }
+}
+
+fn block_definition(
+    entity: Entity,
+    blocks: &SavedBlockQuery,
+    connections: &SavedConnectionQuery,
+    wires: &SavedWireQuery,
+) -> Option<BlockDefinition> {
+    let (
+        block,
+        kind,
+        visuals,
+        transform,
+        delay,
+        children,
+        clock,
+        display,
+        bit_ranges,
+        image_source,
+        library_instance,
+    ) = blocks.get(entity).ok()?;
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    let mut inner_blocks = vec![];
+    let mut block_wires = vec![];
+    // in the order they were spawned in, so inner blocks keep their order
+    for child in children.iter() {
+        if let Ok((connection, _, is_input)) = connections.get(*child) {
+            let connection_definition = ConnectionDefinition {
+                id: connection.id,
+                value: connection.values,
+            };
+            let side = if is_input { &mut inputs } else { &mut outputs };
+            side.push((connection.index, connection_definition));
+        } else if let Ok((wire, wire_delay, waypoints)) = wires.get(*child) {
+            block_wires.push((
+                wire.index,
+                wire_definition(wire, wire_delay, waypoints, blocks, connections),
+            ));
+        } else if let Some(inner_block) = block_definition(*child, blocks, connections, wires) {
+            inner_blocks.push(InnerBlockDefinition::Inline(inner_block));
+        }
+    }
+    let inputs = by_index(inputs);
+    let outputs = by_index(outputs);
+    // ranges that would be the default anyway are left out
+    let parts = if *kind == BlockKind::Splitter {
+        &outputs
+    } else {
+        &inputs
+    };
+    let bit_ranges = bit_ranges
+        .filter(|ranges| **ranges != BitRanges::consecutive(parts.iter().map(|p| p.value.len())))
+        .map(|ranges| ranges.0.clone())
+        .unwrap_or_default();
+    Some(BlockDefinition {
+        id: block.id,
+        pos: transform.translation.xy(),
+        size: visuals.size,
+        name: visuals.name.clone(),
+        color: visuals.color,
+        kind: *kind,
+        delay: delay.0,
+        clock: clock.copied(),
+        contents: image_source.and_then(MemoryImageSource::path),
+        display: display.copied(),
+        bit_ranges,
+        inner_blocks,
+        wires: by_index(block_wires),
+        inputs,
+        outputs,
+        library_instance: library_instance.cloned().map(Box::new),
+    })
+}
+
+fn wire_definition(
+    wire: &Wire,
+    delay: &PropagationDelay,
+    waypoints: &WireWaypoints,
+    blocks: &SavedBlockQuery,
+    connections: &SavedConnectionQuery,
+) -> WireDefinition {
+    let connections = wire
+        .connections()
+        .filter_map(|connection| {
+            let (connection, owner, _) = connections.get(connection.0).ok()?;
+            let (block, ..) = blocks.get(owner.0).ok()?;
+            Some(ConnectionDefinitionRef {
+                parent_block: block.id,
+                id: connection.id,
+            })
+        })
+        .collect();
+    WireDefinition {
+        connections,
+        delay: delay.0,
+        waypoints: waypoints.0.clone(),
+    }
+}
+
+/// The items ordered by the index they were spawned with.
+fn by_index<T>(mut items: Vec<(usize, T)>) -> Vec<T> {
+    items.sort_by_key(|(index, _)| *index);
+    items.into_iter().map(|(_, item)| item).collect()
+}
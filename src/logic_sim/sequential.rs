@@ -0,0 +1,88 @@
+use super::*;
+
+impl BlockState {
+    /// A state holding `value` until the block is clocked, enabled or reset.
+    pub fn new(value: ConnectionValues) -> Self {
+        Self {
+            value,
+            // a clock that is high from the start does not count as a rising edge
+            clock: LogicLevel::Unknown,
+        }
+    }
+
+    /// Updates the state for the given inputs (ordered by their index, see [`BlockKind`] for what
+    /// they mean) and returns the new state.
+    ///
+    /// Enable and reset are optional: a block without them is always enabled and never reset.
+    /// Only the lowest bit of the clock, enable, reset and control inputs counts.
+    pub fn step(&mut self, kind: BlockKind, inputs: &[ConnectionValues]) -> ConnectionValues {
+        let (control_inputs, clocked) = match kind {
+            BlockKind::DFlipFlop | BlockKind::TFlipFlop | BlockKind::Register => (1, true),
+            BlockKind::JkFlipFlop => (2, true),
+            BlockKind::SrLatch => (2, false),
+            BlockKind::DLatch => (1, false),
+            _ => return self.value,
+        };
+        let level = |index: usize, default: LogicLevel| {
+            inputs
+                .get(index)
+                .map_or(default, |input| input.get_level(0))
+        };
+        let enable_index = control_inputs + usize::from(clocked);
+        let enable = level(enable_index, LogicLevel::High);
+        let reset = level(enable_index + 1, LogicLevel::Low);
+
+        let active = if clocked {
+            let clock = level(control_inputs, LogicLevel::Low);
+            let rising_edge = self.clock == LogicLevel::Low && clock == LogicLevel::High;
+            self.clock = clock;
+            rising_edge
+        } else {
+            true
+        };
+        if reset == LogicLevel::High {
+            self.value = self.zeros();
+            return self.value;
+        }
+        if !active || enable != LogicLevel::High {
+            return self.value;
+        }
+
+        let value = self.value;
+        self.value = match kind {
+            BlockKind::DFlipFlop | BlockKind::Register | BlockKind::DLatch => {
+                match inputs.first() {
+                    Some(data) => value.with_values_of(*data),
+                    None => value,
+                }
+            }
+            BlockKind::TFlipFlop => match level(0, LogicLevel::Low) {
+                LogicLevel::Low => value,
+                LogicLevel::High => !value,
+                _ => self.unknown(),
+            },
+            BlockKind::JkFlipFlop => match (level(0, LogicLevel::Low), level(1, LogicLevel::Low)) {
+                (LogicLevel::Low, LogicLevel::Low) => value,
+                (LogicLevel::High, LogicLevel::Low) => !self.zeros(),
+                (LogicLevel::Low, LogicLevel::High) => self.zeros(),
+                (LogicLevel::High, LogicLevel::High) => !value,
+                _ => self.unknown(),
+            },
+            BlockKind::SrLatch => match (level(0, LogicLevel::Low), level(1, LogicLevel::Low)) {
+                (LogicLevel::Low, LogicLevel::Low) => value,
+                (LogicLevel::High, LogicLevel::Low) => !self.zeros(),
+                (LogicLevel::Low, LogicLevel::High) => self.zeros(),
+                _ => self.unknown(),
+            },
+            _ => value,
+        };
+        self.value
+    }
+
+    fn zeros(&self) -> ConnectionValues {
+        self.value.with_values_of(ConnectionValues::Single(false))
+    }
+    fn unknown(&self) -> ConnectionValues {
+        ConnectionValues::Logic(LogicVector::all_unknown(self.value.len()))
+    }
+}
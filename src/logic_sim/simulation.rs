@@ -0,0 +1,186 @@
+use super::*;
+use crate::logic_sim::clock::{ClockGenerator, drive_clocks};
+use crate::logic_sim::editor::keyboard_free;
+use crate::logic_sim::propagation::{
+    DirtyQueue, OscillatingWires, index_wires, propagate, queue_changed_connections,
+};
+use crate::logic_sim::validation::{WidthMismatches, check_wire_widths};
+use bevy::ecs::schedule::ScheduleLabel;
+
+/// Evaluates the circuit by one step. Runs a number of times per frame that depends on the
+/// configured tick rate, so the circuit behaves the same at every frame rate.
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimulationTick;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationMode {
+    Running,
+    Paused,
+    /// Runs ticks as fast as possible until one tick changes nothing and no clock changes on
+    /// the next one, then pauses. Running clocks make it stop between two of their edges.
+    RunUntilStable,
+}
+
+#[derive(Resource, Debug)]
+pub struct SimulationSettings {
+    pub ticks_per_second: f64,
+    /// Upper bound for the ticks run in a single frame, so a slow frame does not make the next
+    /// one even slower.
+    pub max_ticks_per_frame: u32,
+    /// Upper bound for the waves of changes [`propagate`] moves through the circuit in a single
+    /// tick before it reports the circuit as oscillating.
+    pub max_waves_per_step: u32,
+    /// Keeps the simulation from running while any wire connects different widths, see
+    /// [`WidthMismatches`]. Otherwise values are cut or zero extended to the width of each sink.
+    pub refuse_width_mismatches: bool,
+    pub mode: SimulationMode,
+}
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            ticks_per_second: 60.0,
+            max_ticks_per_frame: 1000,
+            max_waves_per_step: 1000,
+            refuse_width_mismatches: false,
+            mode: SimulationMode::Running,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct SimulationState {
+    /// Number of ticks run since the start.
+    pub tick: u64,
+    /// Fractional ticks carried over to the next frame.
+    accumulated_ticks: f64,
+    /// Single steps requested while paused.
+    pending_steps: u32,
+    /// Whether any connection got a new value during the current tick.
+    changed: bool,
+}
+impl SimulationState {
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+    }
+    pub fn step(&mut self) {
+        self.pending_steps += 1;
+    }
+}
+
+pub struct SimulationPlugin;
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_schedule(SimulationTick)
+            .init_resource::<SimulationSettings>()
+            .init_resource::<SimulationState>()
+            .init_resource::<DirtyQueue>()
+            .init_resource::<OscillatingWires>()
+            .init_resource::<WidthMismatches>()
+            .add_systems(
+                SimulationTick,
+                (
+                    index_wires,
+                    drive_clocks,
+                    queue_changed_connections,
+                    propagate,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    check_wire_widths,
+                    handle_simulation_controls.run_if(keyboard_free),
+                    run_simulation,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+fn run_simulation(world: &mut World) {
+    let delta = world.resource::<Time>().delta_secs_f64();
+    let (ticks, mode) = {
+        let settings = world.resource::<SimulationSettings>();
+        if world
+            .resource::<WidthMismatches>()
+            .blocks_simulation(settings)
+        {
+            return;
+        }
+        let (mode, rate, max_ticks) = (
+            settings.mode,
+            settings.ticks_per_second,
+            settings.max_ticks_per_frame,
+        );
+        let mut state = world.resource_mut::<SimulationState>();
+        let ticks = match mode {
+            SimulationMode::Paused => std::mem::take(&mut state.pending_steps).min(max_ticks),
+            SimulationMode::RunUntilStable => max_ticks,
+            SimulationMode::Running => {
+                state.accumulated_ticks += delta * rate;
+                let ticks = state.accumulated_ticks.floor();
+                state.accumulated_ticks -= ticks;
+                (ticks as u32).min(max_ticks)
+            }
+        };
+        (ticks, mode)
+    };
+    for _ in 0..ticks {
+        world.resource_mut::<SimulationState>().changed = false;
+        world.run_schedule(SimulationTick);
+        let mut state = world.resource_mut::<SimulationState>();
+        state.tick += 1;
+        let (changed, tick) = (state.changed, state.tick);
+        // changes still on their way through a delay are going to change something later, and
+        // so is a clock edge on the next tick
+        let stable = !changed
+            && !world.resource::<DirtyQueue>().has_scheduled()
+            && !world
+                .query::<&ClockGenerator>()
+                .iter(world)
+                .any(|clock| clock.edge_at(tick));
+        let mut settings = world.resource_mut::<SimulationSettings>();
+        // an oscillating circuit paused the simulation, the remaining ticks would only run into
+        // the same oscillation again
+        if mode != SimulationMode::Paused && settings.mode == SimulationMode::Paused {
+            break;
+        }
+        if settings.mode == SimulationMode::RunUntilStable && stable {
+            settings.mode = SimulationMode::Paused;
+            info!("Circuit is stable after tick {tick}");
+            break;
+        }
+    }
+}
+
+fn handle_simulation_controls(
+    input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<SimulationSettings>,
+    mut state: ResMut<SimulationState>,
+) {
+    if input.just_pressed(KeyCode::Space) {
+        settings.mode = match settings.mode {
+            SimulationMode::Running => SimulationMode::Paused,
+            SimulationMode::Paused | SimulationMode::RunUntilStable => SimulationMode::Running,
+        };
+        info!("Simulation mode: {:?}", settings.mode);
+    }
+    if input.just_pressed(KeyCode::Period) {
+        settings.mode = SimulationMode::Paused;
+        state.step();
+    }
+    if input.just_pressed(KeyCode::Enter) {
+        settings.mode = SimulationMode::RunUntilStable;
+        info!("Simulation mode: {:?}", settings.mode);
+    }
+    if input.just_pressed(KeyCode::BracketRight) {
+        settings.ticks_per_second *= 2.0;
+        info!("Simulation speed: {} ticks/s", settings.ticks_per_second);
+    }
+    if input.just_pressed(KeyCode::BracketLeft) {
+        settings.ticks_per_second = (settings.ticks_per_second / 2.0).max(0.25);
+        info!("Simulation speed: {} ticks/s", settings.ticks_per_second);
+    }
+}
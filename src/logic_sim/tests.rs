@@ -1,3 +1,71 @@
 use super::*;
+use crate::logic_sim::editor::KeyboardCapture;
+use crate::logic_sim::memory::MemoryImage;
+use bevy::ecs::system::RunSystemOnce;
+use bevy::state::app::StatesPlugin;
 
+mod bus_tests;
+mod clock_tests;
 mod connection_values_tests;
+mod display_tests;
+mod editor_tests;
+mod history_tests;
+mod input_tests;
+mod library_tests;
+mod memory_tests;
+mod primitives_tests;
+mod propagation_tests;
+mod reload_tests;
+mod routing_tests;
+mod save_tests;
+mod sequential_tests;
+mod validation_tests;
+mod wiring_tests;
+
+/// An app with the assets and resources spawning and simulating a circuit needs, without a
+/// window or rendering.
+fn circuit_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        StatesPlugin,
+        SimulationPlugin,
+    ))
+    .init_state::<AppState>()
+    .init_asset::<Mesh>()
+    .init_asset::<ColorMaterial>()
+    .init_asset::<Font>()
+    .init_asset::<MemoryImage>()
+    // keyboard shortcuts check for egui, which is not there
+    .init_resource::<KeyboardCapture>();
+    app
+}
+
+/// Spawns `definition` under a new [`Root`], returns the entity of its block.
+fn spawn_circuit(app: &mut App, definition: BlockDefinition) -> Entity {
+    app.world_mut()
+        .run_system_once(
+            move |mut commands: Commands,
+                  asset_server: Res<AssetServer>,
+                  mut meshes: ResMut<Assets<Mesh>>,
+                  mut materials: ResMut<Assets<ColorMaterial>>| {
+                let mut block = None;
+                commands.spawn(Root).with_children(|parent| {
+                    let spawned = spawn_block_definition(
+                        parent,
+                        &asset_server,
+                        &mut meshes,
+                        &mut materials,
+                        definition.clone(),
+                        0.0,
+                    );
+                    block = Some(spawned.entity);
+                });
+                block.unwrap()
+            },
+        )
+        .unwrap()
+}
@@ -0,0 +1,71 @@
+use super::*;
+use crate::logic_sim::bus::BitRanges;
+
+const HALF_BYTE: ConnectionValues = ConnectionValues::HalfByte(false, false, false, false);
+
+#[test]
+fn test_consecutive_ranges() {
+    assert_eq!(
+        BitRanges::consecutive([4, 1, 3]),
+        BitRanges(vec![0..4, 4..5, 5..8])
+    );
+}
+
+#[test]
+fn test_split_bus() {
+    let ranges = BitRanges(vec![0..4, 4..8]);
+    let inputs = [ConnectionValues::X16(0xa5c3)];
+    assert_eq!(
+        ranges.output_value(BlockKind::Splitter, &inputs, 0, HALF_BYTE),
+        Some(ConnectionValues::HalfByte(true, true, false, false))
+    );
+    assert_eq!(
+        ranges.output_value(BlockKind::Splitter, &inputs, 1, HALF_BYTE),
+        Some(ConnectionValues::HalfByte(false, false, true, true))
+    );
+    assert_eq!(
+        ranges.output_value(BlockKind::Splitter, &inputs, 2, HALF_BYTE),
+        None
+    );
+}
+
+#[test]
+fn test_merge_bus() {
+    let ranges = BitRanges::consecutive([4, 4]);
+    let inputs = [
+        ConnectionValues::HalfByte(true, false, true, false),
+        ConnectionValues::HalfByte(false, false, false, true),
+    ];
+    assert_eq!(
+        ranges.output_value(BlockKind::Merger, &inputs, 0, ConnectionValues::Byte(0xff)),
+        Some(ConnectionValues::Byte(0x85))
+    );
+    // bits not covered by any range are 0
+    let ranges = BitRanges(vec![2..3, 6..7]);
+    let inputs = [
+        ConnectionValues::Single(true),
+        ConnectionValues::Single(true),
+    ];
+    assert_eq!(
+        ranges.output_value(BlockKind::Merger, &inputs, 0, ConnectionValues::Byte(0xff)),
+        Some(ConnectionValues::Byte(0x44))
+    );
+}
+
+#[test]
+fn test_split_keeps_unknown_bits() {
+    let bus = ConnectionValues::Logic("XZ10_0110".to_string().try_into().unwrap());
+    let value = BitRanges::consecutive([4, 4])
+        .output_value(BlockKind::Splitter, &[bus], 1, HALF_BYTE)
+        .unwrap();
+    let levels: Vec<_> = (0..4).map(|i| value.get_level(i)).collect();
+    assert_eq!(
+        levels,
+        [
+            LogicLevel::Low,
+            LogicLevel::High,
+            LogicLevel::HighImpedance,
+            LogicLevel::Unknown
+        ]
+    );
+}
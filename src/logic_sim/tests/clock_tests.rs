@@ -0,0 +1,72 @@
+use crate::logic_sim::clock::ClockGenerator;
+
+fn wave(clock: ClockGenerator, ticks: u64) -> String {
+    (0..ticks)
+        .map(|tick| if clock.level_at(tick) { '1' } else { '0' })
+        .collect()
+}
+
+#[test]
+fn test_default_clock_toggles_every_tick() {
+    assert_eq!(wave(ClockGenerator::default(), 6), "101010");
+}
+
+#[test]
+fn test_duty_cycle_and_phase() {
+    let clock = ClockGenerator {
+        period: 4,
+        duty_cycle: 0.25,
+        ..Default::default()
+    };
+    assert_eq!(wave(clock, 8), "10001000");
+    let clock = ClockGenerator { phase: 1, ..clock };
+    assert_eq!(wave(clock, 8), "01000100");
+    let clock = ClockGenerator {
+        duty_cycle: 0.75,
+        phase: 6,
+        ..clock
+    };
+    assert_eq!(wave(clock, 8), "10111011");
+}
+
+#[test]
+fn test_disabled_clock_stays_low() {
+    let clock = ClockGenerator {
+        enabled: false,
+        ..Default::default()
+    };
+    assert_eq!(wave(clock, 4), "0000");
+}
+
+#[test]
+fn test_edges() {
+    let clock = ClockGenerator {
+        period: 4,
+        duty_cycle: 0.25,
+        ..Default::default()
+    };
+    let edges: Vec<u64> = (0..9).filter(|tick| clock.edge_at(*tick)).collect();
+    assert_eq!(edges, [1, 4, 5, 8]);
+    let clock = ClockGenerator {
+        enabled: false,
+        ..clock
+    };
+    assert!(!(0..9).any(|tick| clock.edge_at(tick)));
+}
+
+#[test]
+fn test_deserialize_defaults() {
+    let clock: ClockGenerator = serde_json::from_str(r#"{"period": 10}"#).unwrap();
+    assert_eq!(
+        clock,
+        ClockGenerator {
+            period: 10,
+            ..Default::default()
+        }
+    );
+    let clock: ClockGenerator =
+        serde_json::from_str(r#"{"period": 10, "duty_cycle": 0.3, "start_enabled": false}"#)
+            .unwrap();
+    assert!(!clock.enabled);
+    assert_eq!(clock.duty_cycle, 0.3);
+}
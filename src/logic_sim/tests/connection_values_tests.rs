@@ -1,5 +1,63 @@
 use super::*;
 const INPUT: u128 = 0b1010_0110_1110_0010_1001_0110_1000_0101_1010_0110_1110_0010_1001_0110_1000_0101_1010_0110_1110_0010_1001_0110_1000_0101_1010_0110_1110_0010_1001_0110_1000_0101;
 
+/// Small xorshift generator, so the property tests cover many values while staying reproducible.
+struct Rng(u64);
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn next_u128(&mut self) -> u128 {
+        self.next() as u128 | (self.next() as u128) << 64
+    }
+    fn next_bool(&mut self) -> bool {
+        self.next() & 1 != 0
+    }
+    /// One random value of every variant, plus [`ConnectionValues::Bits`] and
+    /// [`ConnectionValues::Logic`] in a few odd widths.
+    fn next_values(&mut self) -> Vec<ConnectionValues> {
+        let mut values = vec![
+            ConnectionValues::Single(self.next_bool()),
+            ConnectionValues::HalfByte(
+                self.next_bool(),
+                self.next_bool(),
+                self.next_bool(),
+                self.next_bool(),
+            ),
+            ConnectionValues::Byte(self.next() as u8),
+            ConnectionValues::X16(self.next() as u16),
+            ConnectionValues::X32(self.next() as u32),
+            ConnectionValues::X64(self.next()),
+            ConnectionValues::X128(self.next_u128()),
+            ConnectionValues::X256(self.next_u128(), self.next_u128()),
+        ];
+        for len in [1, 3, 12, 24, 100, 129, 256, 1000, 1024] {
+            let words: Vec<u64> = (0..16).map(|_| self.next()).collect();
+            values.push(ConnectionValues::Bits(BitVector::from_words(len, &words)));
+        }
+        for len in [1, 4, 12, 300] {
+            let mut levels = LogicVector::high_impedance(len);
+            for i in 0..len {
+                let level = match self.next() % 4 {
+                    0 => LogicLevel::Low,
+                    1 => LogicLevel::High,
+                    2 => LogicLevel::Unknown,
+                    _ => LogicLevel::HighImpedance,
+                };
+                levels.set(i, level);
+            }
+            values.push(ConnectionValues::Logic(levels));
+        }
+        values
+    }
+}
+mod bit_or;
+mod deserialize;
 mod get_by_index;
 mod len;
+mod logic;
+mod operators;
+mod set_by_index;
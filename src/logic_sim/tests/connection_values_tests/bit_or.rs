@@ -0,0 +1,37 @@
+use super::*;
+
+#[test]
+fn test_commutative() {
+    let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+    for _ in 0..20 {
+        let left = rng.next_values();
+        let right = rng.next_values();
+        for a in &left {
+            for b in &right {
+                assert_eq!(*a | *b, *b | *a, "{a:?} | {b:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_respects_width() {
+    let mut rng = Rng(0x8CB9_2BA7_2F3D_8DD7);
+    for _ in 0..20 {
+        let left = rng.next_values();
+        let right = rng.next_values();
+        for a in &left {
+            for b in &right {
+                let result = *a | *b;
+                assert_eq!(result.len(), a.len().max(b.len()), "{a:?} | {b:?}");
+                for i in 0..result.len() {
+                    assert_eq!(
+                        result.get_by_index(i),
+                        a.get_by_index(i) | b.get_by_index(i),
+                        "{a:?} | {b:?} at {i}"
+                    );
+                }
+            }
+        }
+    }
+}
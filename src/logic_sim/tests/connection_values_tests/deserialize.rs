@@ -0,0 +1,60 @@
+use super::*;
+fn parse(json: &str) -> Result<ConnectionValues, serde_json::Error> {
+    serde_json::from_str(json)
+}
+#[test]
+fn test_fixed_widths() {
+    assert_eq!(parse(r#"{"Single": true}"#).unwrap().len(), 1);
+    assert_eq!(parse(r#"{"X16": 3395}"#).unwrap().len(), 16);
+}
+#[test]
+fn test_bits_number() {
+    let val = parse(r#"{"Bits": {"width": 3, "value": 5}}"#).unwrap();
+    assert_eq!(val.len(), 3);
+    assert_eq!(val.get_by_index(0), true);
+    assert_eq!(val.get_by_index(1), false);
+    assert_eq!(val.get_by_index(2), true);
+}
+#[test]
+fn test_bits_default_value() {
+    let val = parse(r#"{"Bits": {"width": 12}}"#).unwrap();
+    assert_eq!(val.len(), 12);
+    assert!((0..12).all(|i| !val.get_by_index(i)));
+}
+#[test]
+fn test_bits_hex_and_binary() {
+    let val = parse(r#"{"Bits": {"width": 24, "value": "0xFF_00A5"}}"#).unwrap();
+    assert_eq!(val.to_bit_vector(), BitVector::from_u128(24, 0xFF00A5));
+
+    let val = parse(r#"{"Bits": {"width": 12, "value": "0b1010_0000_0001"}}"#).unwrap();
+    assert_eq!(
+        val.to_bit_vector(),
+        BitVector::from_u128(12, 0b1010_0000_0001)
+    );
+
+    let val = parse(r#"{"Bits": {"width": 1024, "value": "0x8000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001"}}"#).unwrap();
+    assert_eq!(val.len(), 1024);
+    assert_eq!(val.get_by_index(0), true);
+    assert_eq!(val.get_by_index(1023), true);
+    assert!((1..1023).all(|i| !val.get_by_index(i)));
+}
+#[test]
+fn test_bits_invalid() {
+    assert!(parse(r#"{"Bits": {"width": 0}}"#).is_err());
+    assert!(parse(r#"{"Bits": {"width": 1025}}"#).is_err());
+    assert!(parse(r#"{"Bits": {"width": 3, "value": 8}}"#).is_err());
+    assert!(parse(r#"{"Bits": {"width": 4, "value": "0x1F"}}"#).is_err());
+    assert!(parse(r#"{"Bits": {"width": 4, "value": "0b102"}}"#).is_err());
+    assert!(parse(r#"{"Bits": {"width": 4, "value": "15"}}"#).is_err());
+}
+#[test]
+fn test_serialize_round_trip() {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    for value in rng.next_values() {
+        let json = serde_json::to_string(&value).unwrap();
+        let parsed = parse(&json).unwrap();
+        assert_eq!(parsed, value, "{json}");
+        // the same variant, not just the same levels
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+}
@@ -260,3 +260,47 @@ fn test_x256() {
     assert_eq!(val.get_by_index(256), false); // Out of bounds
     check_get_by_index(val);
 }
+
+#[test]
+fn test_bits() {
+    let val = ConnectionValues::Bits(BitVector::from_u128(3, INPUT)); // 101
+    check_get_by_index(val);
+    assert_eq!(val.get_by_index(0), true);
+    assert_eq!(val.get_by_index(1), false);
+    assert_eq!(val.get_by_index(2), true);
+    assert_eq!(val.get_by_index(3), false); // Out of bounds
+
+    let val = ConnectionValues::Bits(BitVector::from_u128(12, INPUT)); // 0110_1000_0101
+    check_get_by_index(val);
+    assert_eq!(val.get_by_index(7), true);
+    assert_eq!(val.get_by_index(9), true);
+    assert_eq!(val.get_by_index(10), true);
+    assert_eq!(val.get_by_index(11), false);
+    assert_eq!(val.get_by_index(12), false); // Out of bounds
+
+    check_get_by_index(ConnectionValues::Bits(BitVector::from_u128(24, INPUT)));
+    let low = INPUT as u64;
+    let high = (INPUT >> 64) as u64;
+    check_get_by_index(ConnectionValues::Bits(BitVector::from_words(
+        200,
+        &[low, high, low, high],
+    )));
+}
+
+#[test]
+fn test_bits_1024() {
+    let words: Vec<u64> = (0..16)
+        .map(|i| {
+            if i % 2 == 0 {
+                INPUT as u64
+            } else {
+                (INPUT >> 64) as u64
+            }
+        })
+        .collect();
+    let val = ConnectionValues::Bits(BitVector::from_words(1024, &words));
+    for i in 0..1024 {
+        assert_eq!(val.get_by_index(i), (INPUT & (1 << (i % 128))) != 0);
+    }
+    assert_eq!(val.get_by_index(1024), false); // Out of bounds
+}
@@ -24,3 +24,15 @@ fn test_xx() {
     assert_eq!(ConnectionValues::X128(INPUT).len(), 128);
     assert_eq!(ConnectionValues::X256(INPUT, INPUT).len(), 256);
 }
+
+#[test]
+fn test_bits() {
+    assert_eq!(ConnectionValues::Bits(BitVector::zeros(1)).len(), 1);
+    assert_eq!(ConnectionValues::Bits(BitVector::zeros(3)).len(), 3);
+    assert_eq!(ConnectionValues::Bits(BitVector::zeros(12)).len(), 12);
+    assert_eq!(
+        ConnectionValues::Bits(BitVector::from_u128(24, INPUT)).len(),
+        24
+    );
+    assert_eq!(ConnectionValues::Bits(BitVector::zeros(1024)).len(), 1024);
+}
@@ -0,0 +1,94 @@
+use super::*;
+fn logic(levels: &str) -> ConnectionValues {
+    ConnectionValues::Logic(LogicVector::try_from(levels.to_string()).unwrap())
+}
+
+#[test]
+fn test_deserialize() {
+    let val: ConnectionValues = serde_json::from_str(r#"{"Logic": "10XZ"}"#).unwrap();
+    assert_eq!(val.len(), 4);
+    assert_eq!(val.get_level(0), LogicLevel::HighImpedance);
+    assert_eq!(val.get_level(1), LogicLevel::Unknown);
+    assert_eq!(val.get_level(2), LogicLevel::Low);
+    assert_eq!(val.get_level(3), LogicLevel::High);
+    assert!(serde_json::from_str::<ConnectionValues>(r#"{"Logic": "10A"}"#).is_err());
+    assert!(serde_json::from_str::<ConnectionValues>(r#"{"Logic": ""}"#).is_err());
+}
+
+#[test]
+fn test_known_levels_equal_two_valued() {
+    assert_eq!(
+        logic("1001"),
+        ConnectionValues::HalfByte(true, false, false, true)
+    );
+    assert_ne!(
+        logic("100X"),
+        ConnectionValues::HalfByte(false, false, false, true)
+    );
+    assert!(!logic("1001").has_unknown());
+    assert!(logic("1Z01").has_unknown());
+}
+
+#[test]
+fn test_resolve() {
+    let a = ConnectionValues::HalfByte(true, true, false, false);
+    let b = ConnectionValues::HalfByte(true, false, true, false);
+    assert_eq!(a.resolve(b), logic("0XX1"));
+    assert_eq!(a.resolve(a), a);
+    assert!(matches!(a.resolve(a), ConnectionValues::HalfByte(..)));
+
+    // undriven bits yield to the other driver
+    assert_eq!(logic("ZZ10").resolve(logic("01ZZ")), logic("0110"));
+    assert_eq!(logic("ZZZZ").resolve(logic("ZZZZ")), logic("ZZZZ"));
+    assert_eq!(logic("X1Z0").resolve(logic("ZZZZ")), logic("X1Z0"));
+    assert_eq!(logic("X1Z0").resolve(logic("1111")), logic("X11X"));
+
+    // the narrower driver does not drive the upper bits
+    assert_eq!(
+        ConnectionValues::Single(true).resolve(ConnectionValues::HalfByte(true, false, true, true)),
+        ConnectionValues::HalfByte(true, false, true, true)
+    );
+    assert_eq!(
+        ConnectionValues::Single(false).resolve(logic("ZZZ1")),
+        logic("ZZZX")
+    );
+}
+
+#[test]
+fn test_gates() {
+    let a = logic("01XZ01XZ01XZ01XZ");
+    let b = logic("00001111XXXXZZZZ");
+    assert_eq!(a & b, logic("000001XX0XXX0XXX"));
+    assert_eq!(a | b, logic("01XX1111X1XXX1XX"));
+    assert_eq!(a ^ b, logic("01XX10XXXXXXXXXX"));
+    assert_eq!(!a, logic("10XX10XX10XX10XX"));
+}
+
+#[test]
+fn test_arithmetic_with_unknown_is_unknown() {
+    let result = logic("000X").wrapping_add(ConnectionValues::Byte(1));
+    assert_eq!(result, logic("XXXXXXXX"));
+    let result = logic("0001").wrapping_add(ConnectionValues::HalfByte(true, false, false, false));
+    assert_eq!(result, logic("0010"));
+}
+
+#[test]
+fn test_rearranging_keeps_levels() {
+    assert_eq!(logic("X1Z0") << 1, logic("1Z00"));
+    assert_eq!(logic("X1Z0") >> 1, logic("0X1Z"));
+    assert_eq!(logic("X1Z0").arithmetic_shr(2), logic("XXX1"));
+    assert_eq!(logic("X1Z0").rotate_left(1), logic("1Z0X"));
+    assert_eq!(logic("Z1").sign_extend(4), logic("ZZZ1"));
+    assert_eq!(logic("Z1").zero_extend(4), logic("00Z1"));
+}
+
+#[test]
+fn test_set_level() {
+    let mut val = ConnectionValues::Byte(0);
+    val.set_level(3, LogicLevel::Unknown);
+    assert_eq!(val, logic("0000X000"));
+    val.set_level(3, LogicLevel::High);
+    assert_eq!(val, ConnectionValues::Byte(0b1000));
+    val.set_level(8, LogicLevel::HighImpedance); // Out of bounds
+    assert_eq!(val.len(), 8);
+}
@@ -0,0 +1,148 @@
+use super::*;
+
+fn mask(len: usize) -> u128 {
+    if len == 128 {
+        u128::MAX
+    } else {
+        (1 << len) - 1
+    }
+}
+fn bits(len: usize, value: u128) -> ConnectionValues {
+    ConnectionValues::Bits(BitVector::from_u128(len, value))
+}
+fn to_i128(len: usize, value: u128) -> i128 {
+    ((value << (128 - len)) as i128) >> (128 - len)
+}
+
+#[test]
+fn test_against_u128_reference() {
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    for len in 1..=128 {
+        for _ in 0..20 {
+            let a = rng.next_u128() & mask(len);
+            let b = rng.next_u128() & mask(len);
+            let amount = rng.next() as usize % (len + 2);
+            let (x, y) = (bits(len, a), bits(len, b));
+
+            assert_eq!(x & y, bits(len, a & b));
+            assert_eq!(x | y, bits(len, a | b));
+            assert_eq!(x ^ y, bits(len, a ^ b));
+            assert_eq!(!x, bits(len, !a & mask(len)));
+            assert_eq!(x.wrapping_add(y), bits(len, a.wrapping_add(b) & mask(len)));
+            assert_eq!(x.wrapping_sub(y), bits(len, a.wrapping_sub(b) & mask(len)));
+            assert_eq!(x.wrapping_mul(y), bits(len, a.wrapping_mul(b) & mask(len)));
+            assert_eq!(x.cmp_unsigned(y), a.cmp(&b));
+            assert_eq!(x.cmp_signed(y), to_i128(len, a).cmp(&to_i128(len, b)));
+
+            let shl = if amount >= len {
+                0
+            } else {
+                (a << amount) & mask(len)
+            };
+            let shr = if amount >= len { 0 } else { a >> amount };
+            let sar = to_i128(len, a) >> amount.min(len - 1);
+            assert_eq!(x << amount, bits(len, shl));
+            assert_eq!(x >> amount, bits(len, shr));
+            assert_eq!(x.arithmetic_shr(amount), bits(len, sar as u128 & mask(len)));
+
+            let rotation = amount % len;
+            let rotated = if rotation == 0 {
+                a
+            } else {
+                ((a << rotation) | (a >> (len - rotation))) & mask(len)
+            };
+            assert_eq!(x.rotate_left(amount), bits(len, rotated));
+            assert_eq!(x.rotate_left(amount).rotate_right(amount), x);
+        }
+    }
+}
+
+#[test]
+fn test_wide_arithmetic_carries_across_words() {
+    let all_ones = !ConnectionValues::Bits(BitVector::zeros(1024));
+    let one = ConnectionValues::Bits(BitVector::from_u128(1024, 1));
+    let zero = ConnectionValues::Bits(BitVector::zeros(1024));
+    assert_eq!(all_ones.wrapping_add(one), zero);
+    assert_eq!(zero.wrapping_sub(one), all_ones);
+    assert_eq!(all_ones.wrapping_mul(all_ones), one);
+
+    let high_bit = one << 1023;
+    assert!(high_bit.get_by_index(1023));
+    assert_eq!(high_bit >> 1023, one);
+    assert_eq!(high_bit.arithmetic_shr(1023), all_ones);
+    assert_eq!(high_bit.rotate_left(1), one);
+    assert_eq!(high_bit.cmp_signed(zero), Ordering::Less);
+    assert_eq!(high_bit.cmp_unsigned(zero), Ordering::Greater);
+}
+
+#[test]
+fn test_widening_keeps_wider_variant() {
+    let result =
+        ConnectionValues::HalfByte(true, false, false, true) & ConnectionValues::Byte(0xFF);
+    assert!(matches!(result, ConnectionValues::Byte(0b1001)));
+
+    let result = ConnectionValues::X16(0xFF00) ^ ConnectionValues::Single(true);
+    assert!(matches!(result, ConnectionValues::X16(0xFF01)));
+
+    let result = bits(12, 0xFFF).wrapping_add(ConnectionValues::Single(true));
+    assert_eq!(result, bits(12, 0));
+
+    let result = ConnectionValues::X256(u128::MAX, 0).wrapping_add(ConnectionValues::Byte(1));
+    assert!(matches!(result, ConnectionValues::X256(0, 1)));
+
+    let result = !ConnectionValues::HalfByte(true, false, false, true);
+    assert!(matches!(
+        result,
+        ConnectionValues::HalfByte(false, true, true, false)
+    ));
+}
+
+#[test]
+fn test_extension() {
+    let value = ConnectionValues::HalfByte(false, true, false, true); // 0b1010
+    assert!(matches!(
+        value.zero_extend(8),
+        ConnectionValues::Byte(0b0000_1010)
+    ));
+    assert!(matches!(
+        value.sign_extend(8),
+        ConnectionValues::Byte(0b1111_1010)
+    ));
+    assert_eq!(value.sign_extend(12), bits(12, 0xFFA));
+    assert_eq!(
+        ConnectionValues::Byte(0x7F).sign_extend(16),
+        ConnectionValues::X16(0x7F)
+    );
+    assert_eq!(
+        ConnectionValues::Byte(0x80).sign_extend(256),
+        ConnectionValues::X256(u128::MAX - 0x7F, u128::MAX)
+    );
+}
+
+#[test]
+fn test_inner_integers_keep_every_bit() {
+    let mut rng = Rng(0x61C8_8646_80B5_83EB);
+    for len in 1..=128 {
+        let a = rng.next_u128() & mask(len);
+        let x = bits(len, a);
+        assert_eq!(x.inner_u128(), a, "{len} bits");
+        if len <= 64 {
+            assert_eq!(x.inner_u64(), a as u64, "{len} bits");
+        }
+        if len <= 32 {
+            assert_eq!(x.inner_u32(), a as u32, "{len} bits");
+        }
+        if len <= 16 {
+            assert_eq!(x.inner_u16(), a as u16, "{len} bits");
+        }
+        if len <= 8 {
+            assert_eq!(x.inner_u8(), a as u8, "{len} bits");
+        }
+    }
+    // bits 8 to 11 do not fit a u8
+    assert_eq!(bits(12, 0xF01).inner_u128(), 0xF01);
+    assert_eq!(
+        bits(12, 0xF01) | ConnectionValues::X16(0x10),
+        bits(16, 0xF11)
+    );
+}
@@ -0,0 +1,49 @@
+use super::*;
+
+#[test]
+fn test_round_trip() {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..4 {
+        for original in rng.next_values() {
+            for index in 0..original.len() {
+                for value in [true, false] {
+                    let mut val = original;
+                    val.set_by_index(index, value);
+                    assert_eq!(val.get_by_index(index), value, "{original:?} index {index}");
+                    assert_eq!(val.len(), original.len());
+                    for other in 0..original.len() {
+                        if other != index {
+                            assert_eq!(
+                                val.get_by_index(other),
+                                original.get_by_index(other),
+                                "setting {index} of {original:?} changed {other}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_out_of_bounds_is_ignored() {
+    let mut rng = Rng(0x1234_5678_9ABC_DEF1);
+    for original in rng.next_values() {
+        let mut val = original;
+        val.set_by_index(val.len(), true);
+        val.set_by_index(usize::MAX, true);
+        assert_eq!(val, original);
+    }
+}
+
+#[test]
+fn test_x256_high_half() {
+    let mut val = ConnectionValues::X256(0, 0);
+    val.set_by_index(128, true);
+    assert!(matches!(val, ConnectionValues::X256(0, 1)));
+    val.set_by_index(255, true);
+    assert!(matches!(val, ConnectionValues::X256(0, high) if high == 1 | 1 << 127));
+    val.set_by_index(128, false);
+    assert!(matches!(val, ConnectionValues::X256(0, high) if high == 1 << 127));
+}
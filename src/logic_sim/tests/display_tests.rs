@@ -0,0 +1,45 @@
+use super::*;
+use crate::logic_sim::display::{NumberFormat, format_value};
+
+fn logic(levels: &str) -> ConnectionValues {
+    ConnectionValues::Logic(levels.to_string().try_into().unwrap())
+}
+
+#[test]
+fn test_format_known_values() {
+    let value = ConnectionValues::Byte(0xf6);
+    assert_eq!(format_value(value, NumberFormat::Binary), "11110110");
+    assert_eq!(format_value(value, NumberFormat::Hex), "0xf6");
+    assert_eq!(format_value(value, NumberFormat::Unsigned), "246");
+    assert_eq!(format_value(value, NumberFormat::Signed), "-10");
+    assert_eq!(
+        format_value(ConnectionValues::Byte(0x80), NumberFormat::Signed),
+        "-128"
+    );
+    assert_eq!(
+        format_value(ConnectionValues::Byte(0), NumberFormat::Signed),
+        "0"
+    );
+}
+
+#[test]
+fn test_format_unknown_values() {
+    let value = logic("10X1_0Z00");
+    assert_eq!(format_value(value, NumberFormat::Binary), "10X10Z00");
+    assert_eq!(format_value(value, NumberFormat::Hex), "0xXZ");
+    assert_eq!(format_value(logic("Z_0001"), NumberFormat::Hex), "0xZ1");
+    assert_eq!(format_value(value, NumberFormat::Unsigned), "X");
+    assert_eq!(format_value(value, NumberFormat::Signed), "X");
+}
+
+#[test]
+fn test_decimal_of_wide_values() {
+    let max = !BitVector::zeros(128);
+    assert_eq!(max.to_decimal_string(), u128::MAX.to_string());
+    let value = ConnectionValues::Bits(!BitVector::zeros(200));
+    assert_eq!(format_value(value, NumberFormat::Signed), "-1");
+    assert_eq!(
+        format_value(value, NumberFormat::Unsigned),
+        "1606938044258990275541962092341162602522202993782792835301375"
+    );
+}
@@ -0,0 +1,115 @@
+use super::*;
+use crate::logic_sim::editor::{EditorPlugin, EditorState};
+use crate::logic_sim::history::EditHistory;
+use bevy::picking::backend::HitData;
+use bevy::picking::pointer::{Location, PointerId};
+use bevy::render::camera::{ManualTextureViewHandle, NormalizedRenderTarget};
+
+fn editor_app() -> App {
+    let mut app = circuit_app();
+    app.init_asset::<Shader>()
+        .add_plugins((bevy::gizmos::GizmoPlugin, EditorPlugin))
+        .init_resource::<ButtonInput<MouseButton>>()
+        .init_resource::<ButtonInput<KeyCode>>()
+        .init_resource::<bevy::picking::focus::HoverMap>()
+        .init_resource::<EditHistory>();
+    // without a window there is no cursor, dragged blocks stay where they are
+    app.world_mut()
+        .spawn((Camera::default(), GlobalTransform::default()));
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::Running);
+    app.world_mut().resource_mut::<EditorState>().enabled = true;
+    app.update();
+    app
+}
+fn block_count(app: &mut App) -> usize {
+    app.world_mut().query::<&Block>().iter(app.world()).count()
+}
+/// Runs a frame with the left button pressed in it or not, the button is up again after it.
+fn frame(app: &mut App, press: bool) {
+    let mut mouse = app.world_mut().resource_mut::<ButtonInput<MouseButton>>();
+    if press {
+        mouse.press(MouseButton::Left);
+    }
+    app.update();
+    let mut mouse = app.world_mut().resource_mut::<ButtonInput<MouseButton>>();
+    mouse.release(MouseButton::Left);
+    mouse.clear();
+}
+
+/// Spawns an empty circuit and takes a block from the palette, which is left following the
+/// cursor.
+fn take_from_palette(app: &mut App) -> Entity {
+    let definition: BlockDefinition = serde_json::from_str(
+        r#"{
+            "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [], "outputs": [], "inner_blocks": [], "wires": []
+        }"#,
+    )
+    .unwrap();
+    let outer = spawn_circuit(app, definition);
+    let entry = app
+        .world_mut()
+        .query_filtered::<Entity, With<Button>>()
+        .iter(app.world())
+        .next()
+        .unwrap();
+
+    // the press on the palette spawns the block without placing it
+    *app.world_mut().get_mut::<Interaction>(entry).unwrap() = Interaction::Pressed;
+    frame(app, true);
+    assert_eq!(block_count(app), 2);
+    assert_eq!(app.world().resource::<EditHistory>().undo_count(), 0);
+    *app.world_mut().get_mut::<Interaction>(entry).unwrap() = Interaction::None;
+    frame(app, false);
+    assert_eq!(app.world().resource::<EditHistory>().undo_count(), 0);
+    app.world_mut()
+        .query_filtered::<(Entity, &Parent), With<Block>>()
+        .iter(app.world())
+        .find(|(_, parent)| parent.get() == outer)
+        .map(|(block, _)| block)
+        .unwrap()
+}
+
+#[test]
+fn test_place_from_palette() {
+    let mut app = editor_app();
+    take_from_palette(&mut app);
+
+    // the next press places it
+    frame(&mut app, true);
+    assert_eq!(block_count(&mut app), 2);
+    assert_eq!(app.world().resource::<EditHistory>().undo_count(), 1);
+    frame(&mut app, true);
+    assert_eq!(app.world().resource::<EditHistory>().undo_count(), 1);
+}
+
+#[test]
+fn test_place_with_press_on_block() {
+    let mut app = editor_app();
+    let block = take_from_palette(&mut app);
+
+    // the block is under the cursor, the press placing it is picked on the block as well
+    let camera = app
+        .world_mut()
+        .query_filtered::<Entity, With<Camera>>()
+        .single(app.world());
+    let location = Location {
+        target: NormalizedRenderTarget::TextureView(ManualTextureViewHandle(0)),
+        position: Vec2::ZERO,
+    };
+    let hit = HitData::new(camera, 0.0, None, None);
+    let press = Down {
+        button: PointerButton::Primary,
+        hit,
+    };
+    app.world_mut().trigger_targets(
+        Pointer::new(block, PointerId::Mouse, location, press),
+        block,
+    );
+    frame(&mut app, true);
+    frame(&mut app, false);
+    assert_eq!(app.world().resource::<EditHistory>().undo_count(), 1);
+}
@@ -0,0 +1,206 @@
+use super::*;
+use crate::logic_sim::history::{EditCommand, EditHistory, HistoryPlugin, MAX_HISTORY};
+use crate::logic_sim::save::CircuitDefinitions;
+
+fn moved(block: usize, from: f32, to: f32) -> EditCommand {
+    EditCommand::MoveBlock {
+        block: vec![1, block],
+        from: Vec2::new(from, 0.0),
+        to: Vec2::new(to, 0.0),
+    }
+}
+fn set(id: usize, from: bool, to: bool) -> EditCommand {
+    EditCommand::SetConnection {
+        block: vec![1],
+        id,
+        from: Box::new(ConnectionValues::Single(from)),
+        to: Box::new(ConnectionValues::Single(to)),
+    }
+}
+
+#[test]
+fn test_inverse() {
+    assert_eq!(moved(2, 0.0, 5.0).inverse(), moved(2, 5.0, 0.0));
+    assert_eq!(set(3, false, true).inverse(), set(3, true, false));
+    let wire: WireDefinition =
+        serde_json::from_str(r#"{"connections": [{"parent_block": 1, "id": 1}]}"#).unwrap();
+    let added = EditCommand::AddWire {
+        block: vec![1],
+        index: 4,
+        wire: wire.clone(),
+    };
+    let removed = EditCommand::RemoveWire {
+        block: vec![1],
+        index: 4,
+        wire,
+    };
+    assert_eq!(added.inverse(), removed);
+    assert_eq!(removed.inverse(), added);
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut history = EditHistory::default();
+    // recorded edits were already made, performed ones still have to be applied
+    history.record(vec![moved(2, 0.0, 5.0)]);
+    assert_eq!(history.take_pending(), []);
+    history.perform(vec![set(1, false, true), set(2, false, true)]);
+    assert_eq!(
+        history.take_pending(),
+        [set(1, false, true), set(2, false, true)]
+    );
+
+    // undoing takes the commands of an edit back last to first
+    assert!(history.undo());
+    assert_eq!(
+        history.take_pending(),
+        [set(2, true, false), set(1, true, false)]
+    );
+    assert!(history.undo());
+    assert_eq!(history.take_pending(), [moved(2, 5.0, 0.0)]);
+    assert!(!history.undo());
+    assert_eq!(history.take_pending(), []);
+
+    assert!(history.redo());
+    assert_eq!(history.take_pending(), [moved(2, 0.0, 5.0)]);
+    assert_eq!((history.undo_count(), history.redo_count()), (1, 1));
+
+    // a new edit replaces what was undone
+    history.record(vec![moved(3, 1.0, 2.0)]);
+    assert!(!history.redo());
+    assert_eq!((history.undo_count(), history.redo_count()), (2, 0));
+    history.record(vec![]);
+    assert_eq!(history.undo_count(), 2);
+}
+
+#[test]
+fn test_history_is_bounded() {
+    let mut history = EditHistory::default();
+    for i in 0..MAX_HISTORY + 10 {
+        history.record(vec![moved(2, i as f32, i as f32 + 1.0)]);
+    }
+    assert_eq!(history.undo_count(), MAX_HISTORY);
+    for _ in 0..MAX_HISTORY {
+        assert!(history.undo());
+    }
+    assert!(!history.undo());
+    // the oldest edits were dropped
+    let pending = history.take_pending();
+    assert_eq!(pending.last(), Some(&moved(2, 11.0, 10.0)));
+}
+
+fn history_app() -> (App, Entity) {
+    let mut app = circuit_app();
+    app.add_plugins(HistoryPlugin)
+        .init_resource::<ButtonInput<KeyCode>>();
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::Running);
+    app.update();
+    let block = spawn_circuit(
+        &mut app,
+        parse(
+            r#"{
+                "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+                "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                "inputs": [{"id": 1, "value": {"Single": false}}],
+                "outputs": [{"id": 2, "value": {"Single": false}}],
+                "inner_blocks": [{
+                    "id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "AND", "kind": "And",
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "inputs": [{"id": 1, "value": {"Single": false}}, {"id": 2, "value": {"Single": false}}],
+                    "outputs": [{"id": 3, "value": {"Single": false}}],
+                    "inner_blocks": [], "wires": []
+                }],
+                "wires": [
+                    {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}]},
+                    {"connections": [{"parent_block": 2, "id": 3}, {"parent_block": 1, "id": 2}]}
+                ]
+            }"#,
+        ),
+    );
+    (app, block)
+}
+fn parse<T: serde::de::DeserializeOwned>(json: &str) -> T {
+    serde_json::from_str(json).unwrap()
+}
+fn circuit(app: &mut App, block: Entity) -> BlockDefinition {
+    app.world_mut()
+        .run_system_once(move |circuit: CircuitDefinitions| circuit.block(block))
+        .unwrap()
+        .unwrap()
+}
+/// Performs `edit`, undoes and redoes it, checking the circuit after each step.
+fn assert_edit(app: &mut App, block: Entity, edit: Vec<EditCommand>, expected: &BlockDefinition) {
+    let before = circuit(app, block);
+    app.world_mut().resource_mut::<EditHistory>().perform(edit);
+    app.update();
+    assert_eq!(&circuit(app, block), expected);
+    assert!(app.world_mut().resource_mut::<EditHistory>().undo());
+    app.update();
+    assert_eq!(circuit(app, block), before);
+    assert!(app.world_mut().resource_mut::<EditHistory>().redo());
+    app.update();
+    assert_eq!(&circuit(app, block), expected);
+}
+
+#[test]
+fn test_apply_edits() {
+    let (mut app, block) = history_app();
+    let mut expected = circuit(&mut app, block);
+
+    let not: BlockDefinition = parse(
+        r#"{
+            "id": 3, "pos": [60.0, 0.0], "size": [50, 50], "name": "NOT", "kind": "Not",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"Single": false}}],
+            "outputs": [{"id": 2, "value": {"Single": true}}],
+            "inner_blocks": [], "wires": []
+        }"#,
+    );
+    expected
+        .inner_blocks
+        .push(InnerBlockDefinition::Inline(not.clone()));
+    let add_block = EditCommand::AddBlock {
+        parent: vec![1],
+        definition: Box::new(not),
+    };
+    assert_edit(&mut app, block, vec![add_block], &expected);
+
+    // the ends are split into drivers and sinks, the taken index is replaced by a free one
+    let wire: WireDefinition = parse(
+        r#"{"connections": [{"parent_block": 3, "id": 1}, {"parent_block": 1, "id": 1}], "delay": 2}"#,
+    );
+    expected.wires.push(parse(
+        r#"{"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 3, "id": 1}], "delay": 2}"#,
+    ));
+    let add_wire = EditCommand::AddWire {
+        block: vec![1],
+        index: 0,
+        wire,
+    };
+    assert_edit(&mut app, block, vec![add_wire], &expected);
+
+    // a removed wire comes back at its index, the block inside is found by its path
+    let wire = expected.wires.remove(0);
+    let remove_wire = EditCommand::RemoveWire {
+        block: vec![1],
+        index: 0,
+        wire,
+    };
+    expected.inner_blocks[1] = InnerBlockDefinition::Inline(parse(
+        r#"{
+            "id": 3, "pos": [80.0, 10.0], "size": [50, 50], "name": "NOT", "kind": "Not",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"Single": false}}],
+            "outputs": [{"id": 2, "value": {"Single": true}}],
+            "inner_blocks": [], "wires": []
+        }"#,
+    ));
+    let move_block = EditCommand::MoveBlock {
+        block: vec![1, 3],
+        from: Vec2::new(60.0, 0.0),
+        to: Vec2::new(80.0, 10.0),
+    };
+    assert_edit(&mut app, block, vec![remove_wire, move_block], &expected);
+}
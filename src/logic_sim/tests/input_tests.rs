@@ -0,0 +1,146 @@
+use super::*;
+use crate::logic_sim::editor::EditorState;
+use crate::logic_sim::history::{EditCommand, EditHistory};
+use crate::logic_sim::input::toggle_all;
+use crate::logic_sim::logic_vector::LogicVector;
+use bevy::picking::backend::HitData;
+use bevy::picking::pointer::{Location, PointerId};
+use bevy::render::camera::{ManualTextureViewHandle, NormalizedRenderTarget};
+use std::time::Duration;
+
+#[test]
+fn test_toggle_all() {
+    assert_eq!(
+        toggle_all(ConnectionValues::Single(false)),
+        ConnectionValues::Single(true)
+    );
+    assert_eq!(
+        toggle_all(ConnectionValues::Single(true)),
+        ConnectionValues::Single(false)
+    );
+    // anything but all high turns all high
+    assert_eq!(
+        toggle_all(ConnectionValues::X16(0x00ff)),
+        ConnectionValues::X16(0xffff)
+    );
+    assert_eq!(
+        toggle_all(ConnectionValues::X16(0xffff)),
+        ConnectionValues::X16(0)
+    );
+    let floating = toggle_all(ConnectionValues::Logic(LogicVector::high_impedance(3)));
+    assert_eq!(floating.len(), 3);
+    assert!((0..3).all(|i| floating.get_level(i) == LogicLevel::High));
+}
+
+#[test]
+fn test_bit_index_at() {
+    assert_eq!(bit_index_at(Vec2::ZERO, 1), Some(0));
+    assert_eq!(bit_index_at(Vec2::new(6.0, 0.0), 1), None);
+    assert_eq!(bit_index_at(Vec2::new(0.0, -6.0), 1), None);
+    // the lowest bit is on the right
+    assert_eq!(bit_index_at(Vec2::new(15.0, 0.0), 4), Some(0));
+    assert_eq!(bit_index_at(Vec2::new(-15.0, 0.0), 4), Some(3));
+    // more than 8 bits take two rows, the second one is not filled
+    assert_eq!(bit_index_at(Vec2::new(20.0, -5.0), 9), Some(0));
+    assert_eq!(bit_index_at(Vec2::new(-15.0, 5.0), 9), Some(8));
+    assert_eq!(bit_index_at(Vec2::new(-20.0, 5.0), 9), None);
+}
+
+/// An outer block whose input 1 drives the input of a buffer, outside of the editor.
+fn input_app() -> App {
+    let mut app = circuit_app();
+    app.init_resource::<EditorState>()
+        .init_resource::<ButtonInput<KeyCode>>()
+        .init_resource::<EditHistory>();
+    let definition: BlockDefinition = serde_json::from_str(
+        r#"{
+            "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"Single": false}}],
+            "outputs": [],
+            "inner_blocks": [{
+                "id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "BUF", "kind": "Buf",
+                "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                "inputs": [{"id": 1, "value": {"Single": false}}],
+                "outputs": [{"id": 2, "value": {"Single": false}}],
+                "inner_blocks": [], "wires": []
+            }],
+            "wires": [
+                {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    spawn_circuit(&mut app, definition);
+    app.update();
+    app
+}
+/// The input with `id` of the block with `block_id`.
+fn input(app: &mut App, block_id: usize, id: usize) -> Entity {
+    let world = app.world_mut();
+    let mut inputs =
+        world.query_filtered::<(Entity, &Connection, &BlockReference), With<InputConnection>>();
+    let mut blocks = world.query::<&Block>();
+    inputs
+        .iter(world)
+        .find(|(_, connection, owner)| {
+            connection.id == id && blocks.get(world, owner.0).unwrap().id == block_id
+        })
+        .map(|(entity, ..)| entity)
+        .unwrap()
+}
+/// Clicks the middle of `connection`.
+fn click(app: &mut App, connection: Entity) {
+    let position = app
+        .world()
+        .get::<GlobalTransform>(connection)
+        .unwrap()
+        .translation();
+    let location = Location {
+        target: NormalizedRenderTarget::TextureView(ManualTextureViewHandle(0)),
+        position: Vec2::ZERO,
+    };
+    let click = Click {
+        button: PointerButton::Primary,
+        hit: HitData::new(Entity::PLACEHOLDER, 0.0, Some(position), None),
+        duration: Duration::ZERO,
+    };
+    app.world_mut().trigger_targets(
+        Pointer::new(connection, PointerId::Mouse, location, click),
+        connection,
+    );
+}
+fn values(app: &App, connection: Entity) -> ConnectionValues {
+    app.world().get::<Connection>(connection).unwrap().values
+}
+
+#[test]
+fn test_click_records_history() {
+    let mut app = input_app();
+    let input = input(&mut app, 1, 1);
+    click(&mut app, input);
+    assert_eq!(values(&app, input), ConnectionValues::Single(true));
+    let mut history = app.world_mut().resource_mut::<EditHistory>();
+    assert_eq!(history.undo_count(), 1);
+    // the click is already done, it is only recorded for undo
+    assert!(history.take_pending().is_empty());
+    history.undo();
+    assert_eq!(
+        history.take_pending(),
+        [EditCommand::SetConnection {
+            block: vec![1],
+            id: 1,
+            from: Box::new(ConnectionValues::Single(true)),
+            to: Box::new(ConnectionValues::Single(false)),
+        }]
+    );
+}
+
+#[test]
+fn test_driven_sink_is_left_alone() {
+    let mut app = input_app();
+    let sink = input(&mut app, 2, 1);
+    click(&mut app, sink);
+    assert_eq!(values(&app, sink), ConnectionValues::Single(false));
+    assert_eq!(app.world().resource::<EditHistory>().undo_count(), 0);
+}
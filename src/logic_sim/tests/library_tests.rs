@@ -0,0 +1,236 @@
+use super::*;
+use crate::logic_sim::library::{
+    BlockInstanceDefinition, BlockLibrary, LibraryError, restore_instances,
+};
+
+pub(super) const HALF_ADDER: &str = r#"{
+    "id": 1, "pos": [0.0, 0.0], "size": [200, 160], "name": "Half Adder",
+    "color": {"Srgba": {"red": 0.3, "green": 0.3, "blue": 0.3, "alpha": 1.0}},
+    "inputs": [{"id": 1, "value": {"Single": false}}, {"id": 2, "value": {"Single": false}}],
+    "outputs": [{"id": 3, "value": {"Single": false}}, {"id": 4, "value": {"Single": false}}],
+    "inner_blocks": [
+        {
+            "id": 2, "pos": [0.0, 40.0], "size": [50, 50], "name": "XOR", "kind": "Xor",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 1.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"Single": false}}, {"id": 2, "value": {"Single": false}}],
+            "outputs": [{"id": 3, "value": {"Single": false}}],
+            "inner_blocks": [], "wires": []
+        },
+        {
+            "id": 3, "pos": [0.0, -40.0], "size": [50, 50], "name": "AND", "kind": "And",
+            "color": {"Srgba": {"red": 1.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"Single": false}}, {"id": 2, "value": {"Single": false}}],
+            "outputs": [{"id": 3, "value": {"Single": false}}],
+            "inner_blocks": [], "wires": []
+        }
+    ],
+    "wires": [
+        {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}, {"parent_block": 3, "id": 1}]},
+        {"connections": [{"parent_block": 1, "id": 2}, {"parent_block": 2, "id": 2}, {"parent_block": 3, "id": 2}]},
+        {"connections": [{"parent_block": 2, "id": 3}, {"parent_block": 1, "id": 3}]},
+        {"connections": [{"parent_block": 3, "id": 3}, {"parent_block": 1, "id": 4}]}
+    ]
+}"#;
+
+pub(super) fn parse(json: &str) -> BlockDefinition {
+    serde_json::from_str(json).unwrap()
+}
+
+/// The half adder as an instance inside another block, resolved like [`BlockLibrary::resolve`]
+/// does.
+pub(super) fn resolved_half_adder(instance: &str) -> (BlockDefinition, BlockInstanceDefinition) {
+    let InnerBlockDefinition::Instance(instance) = serde_json::from_str(instance).unwrap() else {
+        panic!("not an instance: {instance}");
+    };
+    let mut outer = parse(
+        r#"{
+            "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [], "outputs": [], "inner_blocks": [], "wires": []
+        }"#,
+    );
+    let resolved = instance.instantiate(parse(HALF_ADDER));
+    outer
+        .inner_blocks
+        .push(InnerBlockDefinition::Inline(resolved));
+    (outer, instance)
+}
+pub(super) fn inner_block(definition: &mut BlockDefinition) -> &mut BlockDefinition {
+    match &mut definition.inner_blocks[0] {
+        InnerBlockDefinition::Inline(inner_block) => inner_block,
+        InnerBlockDefinition::Instance(_) => panic!("the inner block is an instance"),
+    }
+}
+
+#[test]
+fn test_instance_ids() {
+    // the XOR inside the file has the id of the instance, it takes the id of the file instead
+    let (mut outer, _) =
+        resolved_half_adder(r#"{"instance_of": "half_adder", "id": 2, "pos": [10.0, 20.0]}"#);
+    let half_adder = inner_block(&mut outer);
+    assert_eq!(half_adder.id, 2);
+    assert_eq!(inner_block(half_adder).id, 1);
+    let wire: WireDefinition = serde_json::from_str(
+        r#"{"connections": [{"parent_block": 2, "id": 1}, {"parent_block": 1, "id": 1}, {"parent_block": 3, "id": 1}]}"#,
+    )
+    .unwrap();
+    assert_eq!(half_adder.wires[0], wire);
+    // and is still an instance of the file
+    restore_instances(&mut outer);
+    assert!(matches!(
+        outer.inner_blocks[0],
+        InnerBlockDefinition::Instance(_)
+    ));
+}
+
+#[test]
+fn test_inner_block_errors() {
+    let outer = |inner_block: &str| {
+        format!(
+            r#"{{
+    "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+    "color": {{"Srgba": {{"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}}}},
+    "inputs": [], "outputs": [], "wires": [],
+    "inner_blocks": [
+        {inner_block}
+    ]
+}}"#
+        )
+    };
+    let error = |inner_block: &str| {
+        serde_json::from_str::<BlockDefinition>(&outer(inner_block))
+            .unwrap_err()
+            .to_string()
+    };
+    // the error of the entry itself rather than that no variant matched
+    assert!(
+        error(
+            r#"{"id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "AND", "kind": "Andd",
+            "color": {"Srgba": {"red": 1.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [], "outputs": [], "inner_blocks": [], "wires": []}"#
+        )
+        .starts_with("unknown variant `Andd`")
+    );
+    assert!(
+        error(r#"{"id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "AND"}"#)
+            .starts_with("missing field `color` at line 7")
+    );
+    assert!(
+        error(r#"{"instance_of": "half_adder", "id": 2, "pos": [0.0, "x"]}"#)
+            .starts_with("invalid type: string \"x\", expected f32 at line 6")
+    );
+
+    // values wider than 64 bits make it through
+    let definition = parse(&outer(
+        r#"{"id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "Wide",
+            "color": {"Srgba": {"red": 1.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"X128": 340282366920938463463374607431768211455}}],
+            "outputs": [], "inner_blocks": [], "wires": []}"#,
+    ));
+    let InnerBlockDefinition::Inline(inner_block) = &definition.inner_blocks[0] else {
+        panic!("not written out in place");
+    };
+    assert_eq!(
+        inner_block.inputs[0].value,
+        ConnectionValues::X128(u128::MAX)
+    );
+}
+
+/// An empty block with the given inner blocks.
+fn including(inner_blocks: &str) -> BlockDefinition {
+    parse(&format!(
+        r#"{{
+            "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+            "color": {{"Srgba": {{"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}}}},
+            "inputs": [], "outputs": [], "wires": [], "inner_blocks": [{inner_blocks}]
+        }}"#
+    ))
+}
+/// Resolves `definition` from the file at `path`, with `files` already loaded, waiting for the
+/// ones that are not.
+fn resolve(
+    definition: BlockDefinition,
+    path: &str,
+    files: Vec<(&str, BlockDefinition)>,
+) -> Result<BlockDefinition, LibraryError> {
+    let mut app = circuit_app();
+    app.init_asset::<BlockDefinition>()
+        .init_resource::<BlockLibrary>();
+    // the handles keep the files loaded
+    let _handles: Vec<Handle<BlockDefinition>> = files
+        .into_iter()
+        .map(|(path, file)| {
+            let handle = app.world().resource::<AssetServer>().load(path);
+            let mut assets = app.world_mut().resource_mut::<Assets<BlockDefinition>>();
+            assets.insert(&handle, file);
+            handle
+        })
+        .collect();
+    let path = path.to_string();
+    for _ in 0..1000 {
+        let definition = definition.clone();
+        let path = path.clone();
+        let result = app
+            .world_mut()
+            .run_system_once(
+                move |mut library: ResMut<BlockLibrary>,
+                      asset_server: Res<AssetServer>,
+                      assets: Res<Assets<BlockDefinition>>| {
+                    library.resolve(&definition, path.clone(), &asset_server, &assets)
+                },
+            )
+            .unwrap();
+        if !matches!(result, Err(LibraryError::Loading)) {
+            return result;
+        }
+        app.update();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    panic!("the block definitions never finished loading");
+}
+
+#[test]
+fn test_cycles() {
+    let a = "logisim/library/a.blockdef.json";
+    let b = "logisim/library/b.blockdef.json";
+    let chain = |result: Result<BlockDefinition, LibraryError>| match result {
+        Err(LibraryError::Cycle(chain)) => chain,
+        other => panic!("no cycle: {other:?}"),
+    };
+    let a_in_a = including(r#"{"instance_of": "a", "id": 2, "pos": [0.0, 0.0]}"#);
+    assert_eq!(chain(resolve(a_in_a, a, vec![])), [a, a]);
+
+    let b_in_a = including(r#"{"instance_of": "b", "id": 2, "pos": [0.0, 0.0]}"#);
+    let a_in_b = including(r#"{"instance_of": "a", "id": 2, "pos": [0.0, 0.0]}"#);
+    let error = resolve(b_in_a, a, vec![(b, a_in_b)]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!("block definitions include each other: {a} -> {b} -> {a}")
+    );
+}
+
+#[test]
+fn test_load_failed() {
+    let outer = including(r#"{"instance_of": "missing", "id": 2, "pos": [0.0, 0.0]}"#);
+    match resolve(outer, "logisim/blocks/outer.blockdef.json", vec![]) {
+        Err(LibraryError::LoadFailed { path, .. }) => {
+            assert_eq!(path, "logisim/library/missing.blockdef.json");
+        }
+        other => panic!("the file did not fail to load: {other:?}"),
+    }
+
+    // files that are there resolve
+    let resolved = resolve(
+        including(r#"{"instance_of": "half_adder", "id": 2, "pos": [0.0, 0.0]}"#),
+        "logisim/blocks/outer.blockdef.json",
+        vec![(
+            "logisim/library/half_adder.blockdef.json",
+            parse(HALF_ADDER),
+        )],
+    )
+    .unwrap();
+    assert!(matches!(
+        &resolved.inner_blocks[0],
+        InnerBlockDefinition::Inline(half_adder) if half_adder.name == "Half Adder"
+    ));
+}
@@ -0,0 +1,119 @@
+use super::*;
+use crate::logic_sim::bit_vector::MAX_BIT_WIDTH;
+use crate::logic_sim::memory::{MAX_ADDRESS_WIDTH, Memory, MemoryImage};
+use crate::logic_sim::primitives::BlockKind;
+
+const LOW: ConnectionValues = ConnectionValues::Single(false);
+const HIGH: ConnectionValues = ConnectionValues::Single(true);
+
+fn bits(len: usize, value: u128) -> BitVector {
+    BitVector::from_u128(len, value)
+}
+
+#[test]
+fn test_parse_hex() {
+    let image = MemoryImage::parse_hex(
+        "// program\n\
+         12 34 # first two words\n\
+         @10 ab_cd\n\
+         ff",
+    )
+    .unwrap();
+    assert_eq!(
+        image.words(8),
+        vec![
+            (0, bits(8, 0x12)),
+            (1, bits(8, 0x34)),
+            (0x10, bits(8, 0xcd)),
+            (0x11, bits(8, 0xff)),
+        ]
+    );
+    assert!(
+        MemoryImage::parse_hex("12\n@zz")
+            .unwrap_err()
+            .contains("line 2")
+    );
+    assert!(MemoryImage::parse_hex("1g").is_err());
+}
+
+#[test]
+fn test_bytes_are_split_into_words() {
+    let image = MemoryImage::from_bytes(vec![0x34, 0x12, 0x78, 0x56, 0x01]);
+    assert_eq!(
+        image.words(12),
+        vec![
+            (0, bits(12, 0x234)),
+            (1, bits(12, 0x678)),
+            (2, bits(12, 0x01))
+        ]
+    );
+    assert_eq!(image.words(4).len(), 5);
+}
+
+#[test]
+fn test_rom_reads_the_addressed_word() {
+    let mut rom = Memory::new(4, 8);
+    rom.load_image(&MemoryImage::parse_hex("00 11 22 33").unwrap());
+    assert_eq!(
+        rom.step(
+            BlockKind::Rom,
+            &[ConnectionValues::HalfByte(true, true, false, false)]
+        ),
+        ConnectionValues::Byte(0x33)
+    );
+    assert!(
+        rom.step(
+            BlockKind::Rom,
+            &[ConnectionValues::Logic(
+                "00X1".to_string().try_into().unwrap()
+            )]
+        )
+        .has_unknown()
+    );
+}
+
+#[test]
+fn test_ram_writes_on_rising_edge_with_write_enable() {
+    let mut ram = Memory::new(8, 16);
+    let address = ConnectionValues::Byte(0x42);
+    let data = ConnectionValues::X16(0xbeef);
+    let mut step =
+        |write_enable, clock| ram.step(BlockKind::Ram, &[address, data, write_enable, clock]);
+    assert_eq!(step(HIGH, LOW), ConnectionValues::X16(0));
+    assert_eq!(step(LOW, HIGH), ConnectionValues::X16(0));
+    assert_eq!(step(HIGH, HIGH), ConnectionValues::X16(0));
+    assert_eq!(step(HIGH, LOW), ConnectionValues::X16(0));
+    assert_eq!(step(HIGH, HIGH), ConnectionValues::X16(0xbeef));
+    assert_eq!(ram.read(0x42), bits(16, 0xbeef));
+    assert_eq!(ram.read(0x142), bits(16, 0xbeef));
+    assert_eq!(
+        ram.hex_dump(),
+        "40: 0000 0000 beef 0000 0000 0000 0000 0000"
+    );
+}
+
+#[test]
+fn test_wide_words() {
+    let mut ram = Memory::new(2, 100);
+    ram.write(3, bits(128, u128::MAX));
+    assert_eq!(ram.read(3), !BitVector::zeros(100));
+    assert_eq!(ram.read(2), BitVector::zeros(100));
+}
+
+#[test]
+fn test_large_memory_holds_only_written_words() {
+    let mut ram = Memory::new(MAX_ADDRESS_WIDTH, MAX_BIT_WIDTH);
+    assert_eq!(ram.depth(), 1 << MAX_ADDRESS_WIDTH);
+    let last = ram.depth() - 1;
+    ram.write(last, !BitVector::zeros(MAX_BIT_WIDTH));
+    ram.write(9, bits(8, 0x5a));
+    let copy = ram.clone();
+    assert_eq!(copy.read(last), !BitVector::zeros(MAX_BIT_WIDTH));
+    assert_eq!(copy.read(9), bits(MAX_BIT_WIDTH, 0x5a));
+    assert_eq!(copy.hex_dump().lines().count(), 2);
+
+    // writing zero frees the word again
+    ram.write(last, BitVector::zeros(MAX_BIT_WIDTH));
+    ram.write(9, BitVector::zeros(8));
+    assert_eq!(ram.hex_dump(), "");
+}
@@ -0,0 +1,76 @@
+use super::*;
+use crate::logic_sim::primitives::BlockKind;
+
+fn nibble(value: u8) -> ConnectionValues {
+    ConnectionValues::HalfByte(
+        value & 1 != 0,
+        value & 2 != 0,
+        value & 4 != 0,
+        value & 8 != 0,
+    )
+}
+
+#[test]
+fn test_two_input_gates() {
+    let inputs = [nibble(0b0011), nibble(0b0101)];
+    assert_eq!(BlockKind::And.evaluate(&inputs), Some(nibble(0b0001)));
+    assert_eq!(BlockKind::Or.evaluate(&inputs), Some(nibble(0b0111)));
+    assert_eq!(BlockKind::Xor.evaluate(&inputs), Some(nibble(0b0110)));
+    assert_eq!(BlockKind::Nand.evaluate(&inputs), Some(nibble(0b1110)));
+    assert_eq!(BlockKind::Nor.evaluate(&inputs), Some(nibble(0b1000)));
+    assert_eq!(BlockKind::Xnor.evaluate(&inputs), Some(nibble(0b1001)));
+}
+
+#[test]
+fn test_many_inputs() {
+    let inputs = [
+        ConnectionValues::Single(true),
+        ConnectionValues::Single(true),
+        ConnectionValues::Single(true),
+    ];
+    assert_eq!(
+        BlockKind::And.evaluate(&inputs),
+        Some(ConnectionValues::Single(true))
+    );
+    assert_eq!(
+        BlockKind::Xor.evaluate(&inputs),
+        Some(ConnectionValues::Single(true))
+    );
+    assert_eq!(
+        BlockKind::Nor.evaluate(&inputs),
+        Some(ConnectionValues::Single(false))
+    );
+}
+
+#[test]
+fn test_single_input_gates() {
+    let inputs = [nibble(0b0011), nibble(0b1111)];
+    assert_eq!(BlockKind::Not.evaluate(&inputs), Some(nibble(0b1100)));
+    assert_eq!(BlockKind::Buf.evaluate(&inputs), Some(nibble(0b0011)));
+
+    let floating = ConnectionValues::Logic(LogicVector::high_impedance(2));
+    assert_eq!(
+        BlockKind::Buf.evaluate(&[floating]),
+        Some(ConnectionValues::Logic(LogicVector::all_unknown(2)))
+    );
+}
+
+#[test]
+fn test_no_evaluation() {
+    assert_eq!(BlockKind::Composite.evaluate(&[nibble(1)]), None);
+    assert_eq!(BlockKind::And.evaluate(&[]), None);
+}
+
+#[test]
+fn test_outputs_keep_their_width() {
+    let output = ConnectionValues::X16(0);
+    assert_eq!(
+        output.with_values_of(nibble(0b1001)),
+        ConnectionValues::X16(0b1001)
+    );
+    let output = ConnectionValues::Single(false);
+    assert_eq!(
+        output.with_values_of(nibble(0b1001)),
+        ConnectionValues::Single(true)
+    );
+}
@@ -0,0 +1,347 @@
+use super::*;
+use crate::logic_sim::propagation::OscillatingWires;
+use crate::logic_sim::simulation::{
+    SimulationMode, SimulationSettings, SimulationState, SimulationTick,
+};
+
+/// The connection with `id` of the block with `block_id`.
+fn connection(app: &mut App, block_id: usize, id: usize) -> Entity {
+    let world = app.world_mut();
+    let mut connections = world.query::<(Entity, &Connection, &BlockReference)>();
+    let mut blocks = world.query::<&Block>();
+    connections
+        .iter(world)
+        .find(|(_, connection, owner)| {
+            connection.id == id && blocks.get(world, owner.0).unwrap().id == block_id
+        })
+        .map(|(entity, ..)| entity)
+        .unwrap()
+}
+fn value(app: &mut App, block_id: usize, id: usize) -> ConnectionValues {
+    let connection = connection(app, block_id, id);
+    app.world().get::<Connection>(connection).unwrap().values
+}
+fn set_value(app: &mut App, block_id: usize, id: usize, value: bool) {
+    let connection = connection(app, block_id, id);
+    app.world_mut()
+        .get_mut::<Connection>(connection)
+        .unwrap()
+        .values = ConnectionValues::Single(value);
+}
+fn level(app: &mut App, block_id: usize, id: usize) -> bool {
+    value(app, block_id, id) == ConnectionValues::Single(true)
+}
+/// Runs one tick the way the simulation does each tick.
+fn tick(app: &mut App) {
+    app.world_mut().run_schedule(SimulationTick);
+    app.world_mut().resource_mut::<SimulationState>().tick += 1;
+}
+/// A primitive block with one output (id 3) and inputs 1 and 2, or only 1 for `Buf` and `Not`.
+fn gate(id: usize, kind: &str, delay: u32) -> String {
+    let inputs = if matches!(kind, "Buf" | "Not") {
+        r#"[{"id": 1, "value": {"Single": false}}]"#
+    } else {
+        r#"[{"id": 1, "value": {"Single": false}}, {"id": 2, "value": {"Single": false}}]"#
+    };
+    format!(
+        r#"{{
+            "id": {id}, "pos": [0.0, 0.0], "size": [50, 50], "name": "{kind}", "kind": "{kind}",
+            "delay": {delay},
+            "color": {{"Srgba": {{"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}}}},
+            "inputs": {inputs}, "outputs": [{{"id": 3, "value": {{"Single": false}}}}],
+            "inner_blocks": [], "wires": []
+        }}"#
+    )
+}
+/// An app with a circuit of `gates` and `wires`, with two inputs (ids 1 and 2) and two outputs
+/// (ids 3 and 4), run until it settled.
+fn gate_circuit(gates: &[String], wires: &str) -> App {
+    let definition: BlockDefinition = serde_json::from_str(&format!(
+        r#"{{
+            "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+            "color": {{"Srgba": {{"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}}}},
+            "inputs": [{{"id": 1, "value": {{"Single": false}}}}, {{"id": 2, "value": {{"Single": false}}}}],
+            "outputs": [{{"id": 3, "value": {{"Single": false}}}}, {{"id": 4, "value": {{"Single": false}}}}],
+            "inner_blocks": [{}],
+            "wires": {wires}
+        }}"#,
+        gates.join(", ")
+    ))
+    .unwrap();
+    let mut app = circuit_app();
+    spawn_circuit(&mut app, definition);
+    for _ in 0..10 {
+        tick(&mut app);
+    }
+    app
+}
+
+#[test]
+fn test_sink_on_several_wires_resolves_all_drivers() {
+    let definition: BlockDefinition = serde_json::from_str(
+        r#"{
+            "id": 1, "pos": [0.0, 0.0], "size": [200, 100], "name": "Outer",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"Single": true}}, {"id": 2, "value": {"Single": false}}],
+            "outputs": [],
+            "inner_blocks": [
+                {
+                    "id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "BUF", "kind": "Buf",
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "inputs": [{"id": 1, "value": {"Single": false}}],
+                    "outputs": [{"id": 2, "value": {"Single": false}}],
+                    "inner_blocks": [], "wires": []
+                }
+            ],
+            "wires": [
+                {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}]},
+                {"connections": [{"parent_block": 1, "id": 2}, {"parent_block": 2, "id": 1}]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let mut app = circuit_app();
+    spawn_circuit(&mut app, definition);
+    app.world_mut().run_schedule(SimulationTick);
+    // neither wire wins, the conflict shows as X
+    assert_eq!(value(&mut app, 2, 1).get_level(0), LogicLevel::Unknown);
+    assert_eq!(value(&mut app, 2, 2).get_level(0), LogicLevel::Unknown);
+
+    // with one driver letting go the other one decides
+    let input = connection(&mut app, 1, 2);
+    app.world_mut().get_mut::<Connection>(input).unwrap().values =
+        ConnectionValues::Logic(LogicVector::high_impedance(1));
+    app.world_mut().run_schedule(SimulationTick);
+    assert_eq!(value(&mut app, 2, 1), ConnectionValues::Single(true));
+}
+
+#[test]
+fn test_ring_oscillator_pauses() {
+    let definition: BlockDefinition = serde_json::from_str(
+        r#"{
+            "id": 1, "pos": [0.0, 0.0], "size": [200, 100], "name": "Outer",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [], "outputs": [],
+            "inner_blocks": [
+                {
+                    "id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "NOT", "kind": "Not",
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "inputs": [{"id": 1, "value": {"Single": false}}],
+                    "outputs": [{"id": 2, "value": {"Single": false}}],
+                    "inner_blocks": [], "wires": []
+                }
+            ],
+            "wires": [{"connections": [{"parent_block": 2, "id": 2}, {"parent_block": 2, "id": 1}]}]
+        }"#,
+    )
+    .unwrap();
+    let mut app = circuit_app();
+    app.init_resource::<ButtonInput<KeyCode>>();
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::Running);
+    app.update();
+    spawn_circuit(&mut app, definition);
+    let mut settings = app.world_mut().resource_mut::<SimulationSettings>();
+    settings.mode = SimulationMode::RunUntilStable;
+    settings.max_waves_per_step = 20;
+    let tick = app.world().resource::<SimulationState>().tick;
+    app.update();
+
+    // the tick that found the oscillation is the last one of the frame
+    assert_eq!(
+        app.world().resource::<SimulationSettings>().mode,
+        SimulationMode::Paused
+    );
+    assert_eq!(app.world().resource::<SimulationState>().tick, tick + 1);
+    let oscillating = app.world().resource::<OscillatingWires>().0.clone();
+    let names: Vec<_> = oscillating
+        .iter()
+        .map(|wire| app.world().get::<Name>(*wire).unwrap().as_str())
+        .collect();
+    assert_eq!(names, ["Wire: 1:0"]);
+}
+
+#[test]
+fn test_delayed_wire_and_block() {
+    // input 1 reaches the buffer 3 ticks late, which takes 2 more ticks to pass it on
+    let mut app = gate_circuit(
+        &[gate(2, "Buf", 2)],
+        r#"[
+            {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}], "delay": 3},
+            {"connections": [{"parent_block": 2, "id": 3}, {"parent_block": 1, "id": 3}]}
+        ]"#,
+    );
+    set_value(&mut app, 1, 1, true);
+    let mut seen = vec![];
+    for _ in 0..7 {
+        tick(&mut app);
+        seen.push((level(&mut app, 2, 1), level(&mut app, 1, 3)));
+    }
+    let (f, t) = (false, true);
+    assert_eq!(
+        seen,
+        [(f, f), (f, f), (f, f), (t, f), (t, f), (t, t), (t, t)]
+    );
+}
+
+#[test]
+fn test_glitch_lasts_for_the_delay() {
+    // the second input of the XOR follows input 1 two ticks late, for those two ticks they differ
+    let mut app = gate_circuit(
+        &[gate(2, "Xor", 0), gate(3, "Buf", 2)],
+        r#"[
+            {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}, {"parent_block": 3, "id": 1}]},
+            {"connections": [{"parent_block": 3, "id": 3}, {"parent_block": 2, "id": 2}]},
+            {"connections": [{"parent_block": 2, "id": 3}, {"parent_block": 1, "id": 3}]}
+        ]"#,
+    );
+    set_value(&mut app, 1, 1, true);
+    let mut seen = vec![];
+    for _ in 0..4 {
+        tick(&mut app);
+        seen.push(level(&mut app, 1, 3));
+    }
+    assert_eq!(seen, [true, true, false, false]);
+}
+
+#[test]
+fn test_later_event_supersedes_earlier_one() {
+    // within one tick the delayed XOR first sees only input 1 change, then both; the result
+    // scheduled last wins and the glitch never shows
+    let mut app = gate_circuit(
+        &[gate(2, "Xor", 2), gate(3, "Buf", 0)],
+        r#"[
+            {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}, {"parent_block": 3, "id": 1}]},
+            {"connections": [{"parent_block": 3, "id": 3}, {"parent_block": 2, "id": 2}]},
+            {"connections": [{"parent_block": 2, "id": 3}, {"parent_block": 1, "id": 3}]}
+        ]"#,
+    );
+    set_value(&mut app, 1, 1, true);
+    for _ in 0..4 {
+        tick(&mut app);
+        assert!(!level(&mut app, 1, 3));
+    }
+}
+
+#[test]
+fn test_run_until_stable_waits_for_scheduled_events() {
+    let mut app = gate_circuit(
+        &[gate(2, "Buf", 0)],
+        r#"[
+            {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}], "delay": 5},
+            {"connections": [{"parent_block": 2, "id": 3}, {"parent_block": 1, "id": 3}]}
+        ]"#,
+    );
+    app.init_resource::<ButtonInput<KeyCode>>();
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::Running);
+    app.world_mut().resource_mut::<SimulationSettings>().mode = SimulationMode::Paused;
+    app.update();
+
+    // nothing changes while the value travels along the wire, the run still goes on
+    set_value(&mut app, 1, 1, true);
+    app.world_mut().resource_mut::<SimulationSettings>().mode = SimulationMode::RunUntilStable;
+    app.update();
+    assert!(level(&mut app, 1, 3));
+    assert_eq!(
+        app.world().resource::<SimulationSettings>().mode,
+        SimulationMode::Paused
+    );
+}
+
+/// Gives the output of the NOT `block_id` the value its input would not give it, without the
+/// change being noticed. It only gets right again if the block is evaluated.
+fn contradict(app: &mut App, block_id: usize) {
+    let output = connection(app, block_id, 3);
+    let input = level(app, block_id, 1);
+    let mut connection = app.world_mut().get_mut::<Connection>(output).unwrap();
+    connection.bypass_change_detection().values = ConnectionValues::Single(input);
+}
+
+#[test]
+fn test_only_changes_are_evaluated() {
+    // two NOT gates on their own inputs
+    let mut app = gate_circuit(
+        &[gate(2, "Not", 0), gate(3, "Not", 0)],
+        r#"[
+            {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}]},
+            {"connections": [{"parent_block": 1, "id": 2}, {"parent_block": 3, "id": 1}]}
+        ]"#,
+    );
+    assert!(level(&mut app, 2, 3) && level(&mut app, 3, 3));
+
+    // an idle circuit evaluates nothing
+    contradict(&mut app, 2);
+    contradict(&mut app, 3);
+    tick(&mut app);
+    assert!(!level(&mut app, 2, 3) && !level(&mut app, 3, 3));
+
+    // a changed input re-evaluates only the blocks it reaches, the other one stays wrong
+    set_value(&mut app, 1, 1, true);
+    tick(&mut app);
+    assert!(!level(&mut app, 2, 3));
+    assert!(!level(&mut app, 3, 3));
+    set_value(&mut app, 1, 1, false);
+    tick(&mut app);
+    assert!(level(&mut app, 2, 3));
+    assert!(!level(&mut app, 3, 3));
+}
+
+#[test]
+fn test_wires_reach_child_blocks() {
+    // a half adder: sum on output 3, carry on output 4
+    let mut app = gate_circuit(
+        &[gate(2, "Xor", 0), gate(3, "And", 0)],
+        r#"[
+            {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}, {"parent_block": 3, "id": 1}]},
+            {"connections": [{"parent_block": 1, "id": 2}, {"parent_block": 2, "id": 2}, {"parent_block": 3, "id": 2}]},
+            {"connections": [{"parent_block": 2, "id": 3}, {"parent_block": 1, "id": 3}]},
+            {"connections": [{"parent_block": 1, "id": 4}, {"parent_block": 3, "id": 3}]}
+        ]"#,
+    );
+    let mut wires = app.world_mut().query::<&Wire>();
+    let mut wires: Vec<_> = wires
+        .iter(app.world())
+        .map(|wire| {
+            let entities = |connections: &[ConnectionReference]| {
+                connections.iter().map(|c| c.0).collect::<Vec<_>>()
+            };
+            (wire.index, entities(&wire.drivers), entities(&wire.sinks))
+        })
+        .collect();
+    wires.sort_by_key(|(index, ..)| *index);
+    let expected = [
+        (0, vec![(1, 1)], vec![(2, 1), (3, 1)]),
+        (1, vec![(1, 2)], vec![(2, 2), (3, 2)]),
+        (2, vec![(2, 3)], vec![(1, 3)]),
+        (3, vec![(3, 3)], vec![(1, 4)]),
+    ];
+    for ((index, drivers, sinks), (expected_index, expected_drivers, expected_sinks)) in
+        wires.into_iter().zip(expected)
+    {
+        let mut connections = |ends: Vec<(usize, usize)>| {
+            ends.into_iter()
+                .map(|(block_id, id)| connection(&mut app, block_id, id))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(index, expected_index);
+        assert_eq!(
+            drivers,
+            connections(expected_drivers),
+            "drivers of wire {index}"
+        );
+        assert_eq!(sinks, connections(expected_sinks), "sinks of wire {index}");
+    }
+
+    for (a, b) in [(false, false), (true, false), (false, true), (true, true)] {
+        set_value(&mut app, 1, 1, a);
+        set_value(&mut app, 1, 2, b);
+        for _ in 0..5 {
+            tick(&mut app);
+        }
+        assert_eq!(level(&mut app, 1, 3), a ^ b, "sum of {a} and {b}");
+        assert_eq!(level(&mut app, 1, 4), a && b, "carry of {a} and {b}");
+    }
+}
@@ -0,0 +1,117 @@
+use super::*;
+use crate::logic_sim::memory::Memory;
+use crate::logic_sim::reload::{PreservedState, restore_preserved_state, watch_block_definitions};
+
+/// A block of `kind` with the input values `inputs` (ids from 1) and one output (id 3).
+fn block(id: usize, kind: &str, inputs: &[&str], output: &str) -> String {
+    let inputs: Vec<_> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, value)| format!(r#"{{"id": {}, "value": {value}}}"#, i + 1))
+        .collect();
+    format!(
+        r#"{{
+            "id": {id}, "pos": [0.0, 0.0], "size": [50, 50], "name": "{kind}", "kind": "{kind}",
+            "color": {{"Srgba": {{"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}}}},
+            "inputs": [{}], "outputs": [{{"id": 3, "value": {output}}}],
+            "inner_blocks": [], "wires": []
+        }}"#,
+        inputs.join(", ")
+    )
+}
+/// A circuit with a 4 bit register (2), a RAM of 16 bit words (3), a register as wide as
+/// `register_width` (4) and a RAM with words as wide as `ram_width` (5).
+fn circuit(second_input: &str, register_width: &str, ram_width: &str) -> BlockDefinition {
+    let nibble = r#"{"HalfByte": [false, false, false, false]}"#;
+    let low = r#"{"Single": false}"#;
+    let x16 = r#"{"X16": 0}"#;
+    let blocks = [
+        block(2, "Register", &[nibble, low], nibble),
+        block(3, "Ram", &[nibble, x16, low, low], x16),
+        block(4, "Register", &[register_width, low], register_width),
+        block(5, "Ram", &[nibble, ram_width, low, low], ram_width),
+    ];
+    serde_json::from_str(&format!(
+        r#"{{
+            "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+            "color": {{"Srgba": {{"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}}}},
+            "inputs": [{{"id": 1, "value": {low}}}, {{"id": 2, "value": {second_input}}}],
+            "outputs": [],
+            "inner_blocks": [{}],
+            "wires": []
+        }}"#,
+        blocks.join(", ")
+    ))
+    .unwrap()
+}
+fn find_block(app: &mut App, id: usize) -> Entity {
+    app.world_mut()
+        .query::<(Entity, &Block)>()
+        .iter(app.world())
+        .find(|(_, block)| block.id == id)
+        .map(|(entity, _)| entity)
+        .unwrap()
+}
+fn connection(app: &mut App, block_id: usize, id: usize) -> Mut<'_, Connection> {
+    let block = find_block(app, block_id);
+    let connection = app
+        .world_mut()
+        .query::<(Entity, &Connection, &BlockReference)>()
+        .iter(app.world())
+        .find(|(_, connection, owner)| owner.0 == block && connection.id == id)
+        .map(|(entity, ..)| entity)
+        .unwrap();
+    app.world_mut().get_mut::<Connection>(connection).unwrap()
+}
+fn state(app: &mut App, block_id: usize) -> Mut<'_, BlockState> {
+    let block = find_block(app, block_id);
+    app.world_mut().get_mut::<BlockState>(block).unwrap()
+}
+fn memory(app: &mut App, block_id: usize) -> Mut<'_, Memory> {
+    let block = find_block(app, block_id);
+    app.world_mut().get_mut::<Memory>(block).unwrap()
+}
+
+#[test]
+fn test_reload_keeps_state() {
+    let mut app = circuit_app();
+    app.init_resource::<PreservedState>();
+    let nibble = r#"{"HalfByte": [false, false, false, false]}"#;
+    let byte = r#"{"Byte": 0}"#;
+    let outer = spawn_circuit(&mut app, circuit(nibble, r#"{"Single": false}"#, byte));
+    connection(&mut app, 1, 1).values = ConnectionValues::Single(true);
+    connection(&mut app, 1, 2).values = ConnectionValues::HalfByte(true, false, true, false);
+    let stored = ConnectionValues::HalfByte(true, true, false, false);
+    state(&mut app, 2).value = stored;
+    connection(&mut app, 2, 3).values = stored;
+    memory(&mut app, 3).write(2, BitVector::from_u128(16, 0xbeef));
+    state(&mut app, 4).value = ConnectionValues::Single(true);
+    memory(&mut app, 5).write(1, BitVector::from_u128(8, 0x12));
+    app.world_mut()
+        .run_system_once(watch_block_definitions)
+        .unwrap();
+
+    // the second input, the register 4 and the words of RAM 5 changed their width
+    app.world_mut().entity_mut(outer).despawn_recursive();
+    spawn_circuit(&mut app, circuit(r#"{"X16": 0}"#, nibble, r#"{"X16": 0}"#));
+    app.world_mut()
+        .run_system_once(restore_preserved_state)
+        .unwrap();
+
+    assert_eq!(
+        connection(&mut app, 1, 1).values,
+        ConnectionValues::Single(true)
+    );
+    assert_eq!(connection(&mut app, 1, 2).values, ConnectionValues::X16(0));
+    assert_eq!(state(&mut app, 2).value, stored);
+    assert_eq!(connection(&mut app, 2, 3).values, stored);
+    assert_eq!(
+        memory(&mut app, 3).read(2),
+        BitVector::from_u128(16, 0xbeef)
+    );
+    assert_eq!(
+        state(&mut app, 4).value,
+        ConnectionValues::HalfByte(false, false, false, false)
+    );
+    assert_eq!(memory(&mut app, 5).read(1), BitVector::from_u128(16, 0));
+}
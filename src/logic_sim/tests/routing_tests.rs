@@ -0,0 +1,201 @@
+use super::*;
+use crate::logic_sim::routing::{
+    ROUTE_MARGIN, RouteEnd, RoutingPlugin, WireRoute, route_path, route_wire, waypoint_insert_index,
+};
+
+fn assert_orthogonal(segments: &[(Vec2, Vec2)]) {
+    for (start, end) in segments {
+        assert!(
+            start.x == end.x || start.y == end.y,
+            "{start:?} to {end:?} is not horizontal or vertical"
+        );
+    }
+}
+fn assert_avoids(segments: &[(Vec2, Vec2)], obstacle: Rect) {
+    for (start, end) in segments {
+        let low = start.min(*end);
+        let high = start.max(*end);
+        let crosses = low.x < obstacle.max.x
+            && high.x > obstacle.min.x
+            && low.y < obstacle.max.y
+            && high.y > obstacle.min.y;
+        assert!(!crosses, "{start:?} to {end:?} crosses {obstacle:?}");
+    }
+}
+fn path_segments(path: &[Vec2]) -> Vec<(Vec2, Vec2)> {
+    path.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+fn on_route(point: Vec2, route: &WireRoute) -> bool {
+    route.segments.iter().any(|(start, end)| {
+        let low = start.min(*end);
+        let high = start.max(*end);
+        (start.x == end.x && point.x == start.x && (low.y..=high.y).contains(&point.y))
+            || (start.y == end.y && point.y == start.y && (low.x..=high.x).contains(&point.x))
+    })
+}
+
+#[test]
+fn test_route_path() {
+    let from = Vec2::new(0.0, 0.0);
+    let to = Vec2::new(100.0, 40.0);
+    assert_eq!(
+        route_path(from, Vec2::new(100.0, 0.0), &[]),
+        [from, Vec2::new(100.0, 0.0)]
+    );
+    // one bend is all it takes without anything in the way
+    assert_eq!(route_path(from, to, &[]).len(), 3);
+
+    let block = Rect::new(30.0, -30.0, 70.0, 60.0);
+    let path = route_path(from, to, &[block]);
+    assert_eq!((path[0], path[path.len() - 1]), (from, to));
+    assert_orthogonal(&path_segments(&path));
+    assert_avoids(&path_segments(&path), block);
+    // around the block at the margin
+    assert!(path.iter().any(
+        |point| point.y == block.max.y + ROUTE_MARGIN || point.y == block.min.y - ROUTE_MARGIN
+    ));
+
+    // ends inside a block do not keep the path from leaving it
+    let path = route_path(Vec2::new(50.0, 0.0), to, &[block]);
+    assert_eq!(path[path.len() - 1], to);
+}
+
+#[test]
+fn test_route_wire() {
+    // a driver on the right of one block and two sinks on the left of two others
+    let ends = [
+        RouteEnd {
+            position: Vec2::new(0.0, 0.0),
+            exit: Vec2::X,
+        },
+        RouteEnd {
+            position: Vec2::new(200.0, 60.0),
+            exit: -Vec2::X,
+        },
+        RouteEnd {
+            position: Vec2::new(200.0, -60.0),
+            exit: -Vec2::X,
+        },
+    ];
+    let blocks = [
+        Rect::new(-50.0, -25.0, 0.0, 25.0),
+        Rect::new(200.0, 35.0, 250.0, 85.0),
+        Rect::new(200.0, -85.0, 250.0, -35.0),
+        // right in the way of the straight lines
+        Rect::new(80.0, -100.0, 120.0, 100.0),
+    ];
+    let route = route_wire(&ends, &[], &blocks);
+    assert_orthogonal(&route.segments);
+    for block in blocks {
+        assert_avoids(&route.segments, block);
+    }
+    for end in ends {
+        assert!(on_route(end.position, &route), "{end:?} is not connected");
+    }
+    // the net branches once, towards both sinks
+    assert_eq!(route.junctions.len(), 1);
+
+    // a single wire between two connections does not branch
+    let route = route_wire(&ends[..2], &[], &blocks);
+    assert_eq!(route.junctions, []);
+
+    let mut updated = WireRoute::default();
+    updated.update(ends.to_vec(), vec![], blocks.to_vec());
+    assert_eq!(updated.segments, route_wire(&ends, &[], &blocks).segments);
+}
+
+#[test]
+fn test_route_wire_through_waypoints() {
+    let ends = [
+        RouteEnd {
+            position: Vec2::new(0.0, 0.0),
+            exit: Vec2::X,
+        },
+        RouteEnd {
+            position: Vec2::new(200.0, 0.0),
+            exit: -Vec2::X,
+        },
+    ];
+    let waypoints = [Vec2::new(50.0, 100.0), Vec2::new(150.0, 80.0)];
+    let route = route_wire(&ends, &waypoints, &[]);
+    assert_orthogonal(&route.segments);
+    for point in waypoints.iter().chain(ends.iter().map(|end| &end.position)) {
+        assert!(on_route(*point, &route), "the route misses {point:?}");
+    }
+}
+
+#[test]
+fn test_waypoint_insert_index() {
+    assert_eq!(waypoint_insert_index(&[], Vec2::new(5.0, 5.0)), 0);
+    let waypoints = [
+        Vec2::new(0.0, 0.0),
+        Vec2::new(100.0, 0.0),
+        Vec2::new(100.0, 100.0),
+    ];
+    assert_eq!(waypoint_insert_index(&waypoints, Vec2::new(50.0, 5.0)), 1);
+    assert_eq!(waypoint_insert_index(&waypoints, Vec2::new(105.0, 50.0)), 2);
+    assert_eq!(waypoint_insert_index(&waypoints, Vec2::new(-50.0, 0.0)), 0);
+    assert_eq!(
+        waypoint_insert_index(&waypoints, Vec2::new(100.0, 150.0)),
+        3
+    );
+}
+
+#[test]
+fn test_route_follows_moved_block() {
+    let mut app = circuit_app();
+    app.add_plugins(RoutingPlugin);
+    let definition: BlockDefinition = serde_json::from_str(
+        r#"{
+            "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"Single": false}}], "outputs": [],
+            "inner_blocks": [{
+                "id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "NOT", "kind": "Not",
+                "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                "inputs": [{"id": 1, "value": {"Single": false}}],
+                "outputs": [{"id": 2, "value": {"Single": true}}],
+                "inner_blocks": [], "wires": []
+            }],
+            "wires": [{"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}]}]
+        }"#,
+    )
+    .unwrap();
+    let block = spawn_circuit(&mut app, definition);
+    app.update();
+    let (inner_block, _) = app
+        .world_mut()
+        .query::<(Entity, &Block)>()
+        .iter(app.world())
+        .find(|(_, block)| block.id == 2)
+        .unwrap();
+    let input = app
+        .world_mut()
+        .query::<(Entity, &Connection, &BlockReference)>()
+        .iter(app.world())
+        .find(|(_, connection, owner)| owner.0 == inner_block && connection.id == 1)
+        .map(|(entity, ..)| entity)
+        .unwrap();
+
+    // routed in the same frame the block moved in, not one frame later
+    app.world_mut()
+        .get_mut::<Transform>(inner_block)
+        .unwrap()
+        .translation
+        .y += 100.0;
+    app.update();
+    let end = app
+        .world()
+        .get::<GlobalTransform>(input)
+        .unwrap()
+        .translation()
+        .xy();
+    let route = app
+        .world_mut()
+        .query::<(&WireRoute, &Parent)>()
+        .iter(app.world())
+        .find(|(_, parent)| parent.get() == block)
+        .map(|(route, _)| route.clone())
+        .unwrap();
+    assert!(on_route(end, &route), "{end:?} is not on {route:?}");
+}
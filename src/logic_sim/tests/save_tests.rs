@@ -0,0 +1,215 @@
+use super::library_tests::{HALF_ADDER, inner_block, parse, resolved_half_adder};
+use super::*;
+use crate::logic_sim::library::restore_instances;
+use crate::logic_sim::save::CircuitDefinitions;
+
+/// Saving and loading again gives the same definition, and saving that gives the same json.
+fn assert_round_trip(definition: &BlockDefinition) {
+    let json = serde_json::to_string_pretty(definition).unwrap();
+    let loaded = parse(&json);
+    assert_eq!(&loaded, definition);
+    assert_eq!(serde_json::to_string_pretty(&loaded).unwrap(), json);
+}
+
+#[test]
+fn test_round_trip() {
+    assert_round_trip(&parse(HALF_ADDER));
+    // composite blocks and delays of zero are left out like in the files written by hand
+    let json = serde_json::to_string(&parse(HALF_ADDER)).unwrap();
+    assert!(!json.contains("Composite") && !json.contains("delay"));
+    assert_round_trip(&parse(
+        r#"{
+            "id": 1, "pos": [12.5, -3.25], "size": [400, 300], "name": "Everything",
+            "color": {"Srgba": {"red": 0.1, "green": 0.2, "blue": 0.3, "alpha": 0.9}},
+            "inputs": [{"id": 1, "value": {"Bits": {"width": 12, "value": 2049}}}],
+            "outputs": [
+                {"id": 2, "value": {"Bits": {"width": 100, "value": "0x8000000000000000000000001"}}},
+                {"id": 3, "value": {"Logic": "01XZ"}},
+                {"id": 4, "value": {"X128": 340282366920938463463374607431768211455}},
+                {"id": 5, "value": {"X256": [1, 2]}}
+            ],
+            "inner_blocks": [
+                {
+                    "id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "Clock", "kind": "Clock",
+                    "delay": 2,
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "clock": {"period": 8, "duty_cycle": 0.25, "phase": 1, "start_enabled": false},
+                    "inputs": [], "outputs": [{"id": 1, "value": {"Single": true}}],
+                    "inner_blocks": [], "wires": []
+                },
+                {
+                    "id": 3, "pos": [0.0, 0.0], "size": [50, 50], "name": "Split", "kind": "Splitter",
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "bit_ranges": [{"start": 4, "end": 8}, {"start": 0, "end": 4}],
+                    "inputs": [{"id": 1, "value": {"Byte": 165}}],
+                    "outputs": [
+                        {"id": 2, "value": {"HalfByte": [true, false, true, false]}},
+                        {"id": 3, "value": {"HalfByte": [false, true, false, true]}}
+                    ],
+                    "inner_blocks": [], "wires": []
+                },
+                {
+                    "id": 4, "pos": [0.0, 0.0], "size": [50, 50], "name": "Memory", "kind": "Rom",
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "contents": "logisim/memory/hex_digits.hex",
+                    "inputs": [{"id": 1, "value": {"HalfByte": [false, false, false, false]}}],
+                    "outputs": [{"id": 2, "value": {"X16": 65535}}],
+                    "inner_blocks": [], "wires": []
+                },
+                {
+                    "id": 5, "pos": [0.0, 0.0], "size": [50, 50], "name": "Display",
+                    "kind": "NumberDisplay",
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "display": {"format": "Signed", "columns": 4},
+                    "inputs": [{"id": 1, "value": {"X32": 4294967295}}], "outputs": [],
+                    "inner_blocks": [], "wires": []
+                },
+                {"instance_of": "half_adder", "id": 6, "pos": [100.0, 0.0], "name": "Renamed"}
+            ],
+            "wires": [
+                {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 3, "id": 1}], "delay": 3},
+                {
+                    "connections": [{"parent_block": 2, "id": 1}, {"parent_block": 3, "id": 2}],
+                    "waypoints": [[-20.0, 35.5], [10.0, 35.5]]
+                }
+            ]
+        }"#,
+    ));
+}
+
+#[test]
+fn test_restore_instances() {
+    let (mut outer, instance) = resolved_half_adder(
+        r#"{"instance_of": "half_adder", "id": 2, "pos": [10.0, 20.0], "name": "HA",
+            "inputs": [{"id": 2, "value": {"Single": true}}]}"#,
+    );
+    // values inside the instance are its state, they do not keep it from being an instance
+    let half_adder = inner_block(&mut outer);
+    inner_block(half_adder).outputs[0].value = ConnectionValues::Single(true);
+    half_adder.wires[0].connections.reverse();
+    restore_instances(&mut outer);
+    assert_eq!(
+        outer.inner_blocks,
+        [InnerBlockDefinition::Instance(instance)]
+    );
+}
+
+#[test]
+fn test_restore_instances_with_changes() {
+    let (mut outer, _) =
+        resolved_half_adder(r#"{"instance_of": "half_adder", "id": 2, "pos": [10.0, 20.0]}"#);
+    let half_adder = inner_block(&mut outer);
+    half_adder.pos = Vec2::new(-5.0, 0.0);
+    half_adder.color = Color::srgb(1.0, 1.0, 1.0);
+    half_adder.delay = 4;
+    half_adder.outputs[1].value = ConnectionValues::Single(true);
+    let expected: InnerBlockDefinition = serde_json::from_str(
+        r#"{"instance_of": "half_adder", "id": 2, "pos": [-5.0, 0.0], "delay": 4,
+            "color": {"Srgba": {"red": 1.0, "green": 1.0, "blue": 1.0, "alpha": 1.0}},
+            "outputs": [{"id": 4, "value": {"Single": true}}]}"#,
+    )
+    .unwrap();
+    restore_instances(&mut outer);
+    assert_eq!(outer.inner_blocks, [expected]);
+
+    // a wire the file does not have can only be kept by writing the block out in place
+    let (mut outer, _) =
+        resolved_half_adder(r#"{"instance_of": "half_adder", "id": 2, "pos": [10.0, 20.0]}"#);
+    inner_block(&mut outer).wires.pop();
+    let changed = outer.clone();
+    restore_instances(&mut outer);
+    assert_eq!(outer, changed);
+}
+
+/// Spawns `definition` and reads it back from the spawned entities.
+fn spawned(definition: BlockDefinition) -> BlockDefinition {
+    let mut app = circuit_app();
+    let block = spawn_circuit(&mut app, definition);
+    app.world_mut()
+        .run_system_once(move |circuit: CircuitDefinitions| circuit.block(block))
+        .unwrap()
+        .unwrap()
+}
+
+#[test]
+fn test_spawned_round_trip() {
+    let (mut definition, instance) = resolved_half_adder(
+        r#"{"instance_of": "half_adder", "id": 2, "pos": [10.0, 20.0], "name": "HA",
+            "inputs": [{"id": 2, "value": {"Single": true}}]}"#,
+    );
+    // connections and wires out of the order of their ids, a splitter with and one without its
+    // default bit ranges, a ROM with an image and a block that is no longer an instance
+    definition.inner_blocks.extend(
+        [
+            r#"{
+                "id": 3, "pos": [0.0, 50.0], "size": [50, 50], "name": "Split", "kind": "Splitter",
+                "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                "delay": 2,
+                "inputs": [{"id": 1, "value": {"Byte": 165}}],
+                "outputs": [
+                    {"id": 3, "value": {"HalfByte": [true, false, true, false]}},
+                    {"id": 2, "value": {"HalfByte": [false, true, false, true]}}
+                ],
+                "inner_blocks": [], "wires": []
+            }"#,
+            r#"{
+                "id": 4, "pos": [0.0, -50.0], "size": [50, 50], "name": "Swap", "kind": "Splitter",
+                "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                "bit_ranges": [{"start": 4, "end": 8}, {"start": 0, "end": 4}],
+                "inputs": [{"id": 1, "value": {"Byte": 0}}],
+                "outputs": [
+                    {"id": 2, "value": {"HalfByte": [false, false, false, false]}},
+                    {"id": 3, "value": {"HalfByte": [false, false, false, false]}}
+                ],
+                "inner_blocks": [], "wires": []
+            }"#,
+            r#"{
+                "id": 5, "pos": [50.0, 0.0], "size": [50, 50], "name": "Memory", "kind": "Rom",
+                "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                "contents": "logisim/memory/hex_digits.hex",
+                "inputs": [{"id": 1, "value": {"HalfByte": [false, false, false, false]}}],
+                "outputs": [{"id": 2, "value": {"X16": 0}}],
+                "inner_blocks": [], "wires": []
+            }"#,
+        ]
+        .map(|inner_block| InnerBlockDefinition::Inline(parse(inner_block))),
+    );
+    let (changed, _) =
+        resolved_half_adder(r#"{"instance_of": "half_adder", "id": 6, "pos": [0.0, 0.0]}"#);
+    let mut changed = changed.inner_blocks[0].clone();
+    let InnerBlockDefinition::Inline(half_adder) = &mut changed else {
+        panic!("the half adder is an instance");
+    };
+    half_adder.wires.pop();
+    definition.inner_blocks.push(changed);
+    definition.inputs = vec![
+        serde_json::from_str(r#"{"id": 3, "value": {"Byte": 165}}"#).unwrap(),
+        serde_json::from_str(r#"{"id": 1, "value": {"Single": false}}"#).unwrap(),
+    ];
+    definition.outputs = vec![
+        serde_json::from_str(r#"{"id": 2, "value": {"HalfByte": [true, false, true, false]}}"#)
+            .unwrap(),
+    ];
+    definition.wires = [
+        r#"{"connections": [{"parent_block": 3, "id": 3}, {"parent_block": 1, "id": 2}], "delay": 3}"#,
+        r#"{"connections": [{"parent_block": 1, "id": 3}, {"parent_block": 3, "id": 1}, {"parent_block": 4, "id": 1}]}"#,
+        r#"{
+            "connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}],
+            "waypoints": [[-20.0, 35.5], [10.0, 35.5]]
+        }"#,
+    ]
+    .map(|wire| serde_json::from_str(wire).unwrap())
+    .into();
+
+    let mut loaded = spawned(definition.clone());
+    assert_eq!(loaded, definition);
+    restore_instances(&mut loaded);
+    assert_eq!(
+        loaded.inner_blocks[0],
+        InnerBlockDefinition::Instance(instance)
+    );
+    assert!(matches!(
+        loaded.inner_blocks[4],
+        InnerBlockDefinition::Inline(_)
+    ));
+}
@@ -0,0 +1,103 @@
+use super::*;
+use crate::logic_sim::primitives::BlockKind;
+
+const LOW: ConnectionValues = ConnectionValues::Single(false);
+const HIGH: ConnectionValues = ConnectionValues::Single(true);
+
+#[test]
+fn test_d_flip_flop_stores_on_rising_edge() {
+    let mut state = BlockState::new(LOW);
+    assert_eq!(state.step(BlockKind::DFlipFlop, &[HIGH, LOW]), LOW);
+    assert_eq!(state.step(BlockKind::DFlipFlop, &[HIGH, HIGH]), HIGH);
+    // no edge while the clock stays high
+    assert_eq!(state.step(BlockKind::DFlipFlop, &[LOW, HIGH]), HIGH);
+    assert_eq!(state.step(BlockKind::DFlipFlop, &[LOW, LOW]), HIGH);
+    assert_eq!(state.step(BlockKind::DFlipFlop, &[LOW, HIGH]), LOW);
+}
+
+#[test]
+fn test_clock_high_from_the_start_is_no_edge() {
+    let mut state = BlockState::new(LOW);
+    assert_eq!(state.step(BlockKind::DFlipFlop, &[HIGH, HIGH]), LOW);
+}
+
+#[test]
+fn test_enable_and_reset() {
+    let mut state = BlockState::new(LOW);
+    state.step(BlockKind::DFlipFlop, &[HIGH, LOW, LOW, LOW]);
+    assert_eq!(
+        state.step(BlockKind::DFlipFlop, &[HIGH, HIGH, LOW, LOW]),
+        LOW
+    );
+    state.step(BlockKind::DFlipFlop, &[HIGH, LOW, HIGH, LOW]);
+    assert_eq!(
+        state.step(BlockKind::DFlipFlop, &[HIGH, HIGH, HIGH, LOW]),
+        HIGH
+    );
+    // reset does not wait for the clock
+    assert_eq!(
+        state.step(BlockKind::DFlipFlop, &[HIGH, HIGH, HIGH, HIGH]),
+        LOW
+    );
+}
+
+#[test]
+fn test_jk_flip_flop() {
+    let mut state = BlockState::new(LOW);
+    let mut clock = |j, k| {
+        state.step(BlockKind::JkFlipFlop, &[j, k, LOW]);
+        state.step(BlockKind::JkFlipFlop, &[j, k, HIGH])
+    };
+    assert_eq!(clock(HIGH, LOW), HIGH);
+    assert_eq!(clock(LOW, LOW), HIGH);
+    assert_eq!(clock(HIGH, HIGH), LOW);
+    assert_eq!(clock(HIGH, HIGH), HIGH);
+    assert_eq!(clock(LOW, HIGH), LOW);
+}
+
+#[test]
+fn test_t_flip_flop_divides_the_clock() {
+    let mut state = BlockState::new(LOW);
+    let outputs: Vec<_> = [LOW, HIGH, LOW, HIGH, LOW, HIGH]
+        .into_iter()
+        .map(|clock| state.step(BlockKind::TFlipFlop, &[HIGH, clock]))
+        .collect();
+    assert_eq!(outputs, [LOW, HIGH, HIGH, LOW, LOW, HIGH]);
+}
+
+#[test]
+fn test_sr_latch() {
+    let mut state = BlockState::new(LOW);
+    assert_eq!(state.step(BlockKind::SrLatch, &[HIGH, LOW]), HIGH);
+    assert_eq!(state.step(BlockKind::SrLatch, &[LOW, LOW]), HIGH);
+    assert_eq!(state.step(BlockKind::SrLatch, &[LOW, HIGH]), LOW);
+    assert!(state.step(BlockKind::SrLatch, &[HIGH, HIGH]).has_unknown());
+    // disabled
+    assert!(
+        state
+            .step(BlockKind::SrLatch, &[HIGH, LOW, LOW])
+            .has_unknown()
+    );
+}
+
+#[test]
+fn test_d_latch_is_transparent_while_enabled() {
+    let mut state = BlockState::new(LOW);
+    assert_eq!(state.step(BlockKind::DLatch, &[HIGH, HIGH]), HIGH);
+    assert_eq!(state.step(BlockKind::DLatch, &[LOW, LOW]), HIGH);
+    assert_eq!(state.step(BlockKind::DLatch, &[LOW, HIGH]), LOW);
+}
+
+#[test]
+fn test_register_keeps_its_width() {
+    let mut state = BlockState::new(ConnectionValues::Byte(0));
+    state.step(BlockKind::Register, &[ConnectionValues::X16(0x1234), LOW]);
+    let value = state.step(BlockKind::Register, &[ConnectionValues::X16(0x1234), HIGH]);
+    assert_eq!(value, ConnectionValues::Byte(0x34));
+    assert_eq!(value.len(), 8);
+    let value = state.step(
+        BlockKind::Register,
+        &[ConnectionValues::X16(0x1234), HIGH, HIGH, HIGH],
+    );
+    assert_eq!(value, ConnectionValues::Byte(0));
+}
@@ -0,0 +1,128 @@
+use super::*;
+use crate::logic_sim::validation::{
+    DefinitionProblem, WidthMismatch, WireEnd, definition_width_mismatches, validate_definition,
+};
+
+fn end(block_id: usize, connection_id: usize, width: usize) -> WireEnd {
+    WireEnd {
+        block_id,
+        connection_id,
+        width,
+    }
+}
+
+#[test]
+fn test_matching_widths() {
+    assert_eq!(WidthMismatch::check(1, 0, vec![]), None);
+    assert_eq!(
+        WidthMismatch::check(1, 0, vec![end(1, 1, 4), end(2, 3, 4), end(3, 1, 4)]),
+        None
+    );
+}
+
+#[test]
+fn test_width_mismatch() {
+    let mismatch = WidthMismatch::check(1, 2, vec![end(3, 1, 16), end(1, 1, 4)]).unwrap();
+    assert_eq!(mismatch.ends, [end(1, 1, 4), end(3, 1, 16)]);
+    assert_eq!(
+        mismatch.to_string(),
+        "wire 2 of block 1 connects different widths: block 1 connection 1 (4 bits), block 3 connection 1 (16 bits)"
+    );
+}
+
+#[test]
+fn test_definition_width_mismatches() {
+    let definition: BlockDefinition = serde_json::from_str(
+        r#"{
+            "id": 1, "pos": [0.0, 0.0], "size": [100, 100], "name": "Outer",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"HalfByte": [false, false, false, false]}}],
+            "outputs": [{"id": 2, "value": {"Byte": 0}}],
+            "inner_blocks": [{
+                "id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "Inner",
+                "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                "inputs": [{"id": 1, "value": {"Byte": 0}}],
+                "outputs": [{"id": 2, "value": {"Single": false}}],
+                "inner_blocks": [],
+                "wires": [{"connections": [{"parent_block": 2, "id": 1}, {"parent_block": 2, "id": 2}]}]
+            }],
+            "wires": [
+                {"connections": [{"parent_block": 1, "id": 1}, {"parent_block": 2, "id": 1}]},
+                {"connections": [{"parent_block": 2, "id": 2}, {"parent_block": 1, "id": 7}]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let mismatches = definition_width_mismatches(&definition);
+    assert_eq!(
+        mismatches,
+        [
+            WidthMismatch {
+                block_id: 1,
+                wire_index: 0,
+                ends: vec![end(1, 1, 4), end(2, 1, 8)],
+            },
+            WidthMismatch {
+                block_id: 2,
+                wire_index: 0,
+                ends: vec![end(2, 1, 8), end(2, 2, 1)],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_definition_problems() {
+    let definition: BlockDefinition = serde_json::from_str(
+        r#"{
+            "id": 1, "pos": [0.0, 0.0], "size": [100, 100], "name": "Outer",
+            "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+            "inputs": [{"id": 1, "value": {"Single": false}}, {"id": 2, "value": {"Single": false}}],
+            "outputs": [{"id": 1, "value": {"Single": false}}],
+            "inner_blocks": [
+                {
+                    "id": 1, "pos": [0.0, 0.0], "size": [50, 50], "name": "Same id as outer",
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "inputs": [], "outputs": [], "inner_blocks": [], "wires": []
+                },
+                {
+                    "id": 2, "pos": [0.0, 0.0], "size": [50, 50], "name": "Inner",
+                    "color": {"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}},
+                    "inputs": [{"id": 3, "value": {"Single": false}}],
+                    "outputs": [{"id": 3, "value": {"Single": false}}],
+                    "inner_blocks": [], "wires": []
+                }
+            ],
+            "wires": [
+                {"connections": [{"parent_block": 1, "id": 2}, {"parent_block": 2, "id": 3}]},
+                {"connections": [{"parent_block": 2, "id": 4}, {"parent_block": 5, "id": 1}]}
+            ]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_definition(&definition),
+        [
+            DefinitionProblem::new(
+                "$.outputs[0].id",
+                "connection id 1 is already used by $.inputs[0]"
+            ),
+            DefinitionProblem::new(
+                "$.inner_blocks[0].id",
+                "block id 1 is the id of the block containing it"
+            ),
+            DefinitionProblem::new(
+                "$.inner_blocks[1].outputs[0].id",
+                "connection id 3 is already used by $.inner_blocks[1].inputs[0]"
+            ),
+            DefinitionProblem::new(
+                "$.wires[1].connections[0].id",
+                "block 2 has no connection with id 4"
+            ),
+            DefinitionProblem::new(
+                "$.wires[1].connections[1].parent_block",
+                "no block with id 5 (wires can only reach the block itself and its direct children)"
+            ),
+        ]
+    );
+}
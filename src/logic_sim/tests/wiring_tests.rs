@@ -0,0 +1,208 @@
+use super::*;
+use crate::logic_sim::wiring::{
+    BlockQuery, ConnectionQuery, WirePlan, WireQuery, WireTarget, check_widths, container_of,
+    plan_wire,
+};
+
+/// An outer block (1) with an AND (2), a NOT (3), a byte register (4) and a block (5) holding a
+/// buffer (6). Its input 1 drives the AND, the NOT drives its output 3 and its byte input 2
+/// drives the register.
+fn wiring_app() -> App {
+    let color = r#"{"Srgba": {"red": 0.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}}"#;
+    let low = r#"{"Single": false}"#;
+    let byte = r#"{"Byte": 0}"#;
+    let block = |id: usize, kind: &str, inputs: &[&str], output: &str, inner: &str| {
+        let inputs: Vec<_> = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!(r#"{{"id": {}, "value": {value}}}"#, i + 1))
+            .collect();
+        format!(
+            r#"{{
+                "id": {id}, "pos": [0.0, 0.0], "size": [50, 50], "name": "{kind}",
+                "kind": "{kind}", "color": {color},
+                "inputs": [{}], "outputs": [{{"id": 3, "value": {output}}}],
+                "inner_blocks": [{inner}], "wires": []
+            }}"#,
+            inputs.join(", ")
+        )
+    };
+    let inner_blocks = [
+        block(2, "And", &[low, low], low, ""),
+        block(3, "Not", &[low], low, ""),
+        block(4, "Register", &[byte, low], byte, ""),
+        block(
+            5,
+            "Composite",
+            &[low],
+            low,
+            &block(6, "Buf", &[low], low, ""),
+        ),
+    ];
+    let definition: BlockDefinition = serde_json::from_str(&format!(
+        r#"{{
+            "id": 1, "pos": [0.0, 0.0], "size": [400, 300], "name": "Outer", "color": {color},
+            "inputs": [{{"id": 1, "value": {low}}}, {{"id": 2, "value": {byte}}}],
+            "outputs": [{{"id": 3, "value": {low}}}],
+            "inner_blocks": [{}],
+            "wires": [
+                {{"connections": [{{"parent_block": 1, "id": 1}}, {{"parent_block": 2, "id": 1}}]}},
+                {{"connections": [{{"parent_block": 3, "id": 3}}, {{"parent_block": 1, "id": 3}}]}},
+                {{"connections": [{{"parent_block": 1, "id": 2}}, {{"parent_block": 4, "id": 1}}]}}
+            ]
+        }}"#,
+        inner_blocks.join(", ")
+    ))
+    .unwrap();
+    let mut app = circuit_app();
+    spawn_circuit(&mut app, definition);
+    app
+}
+fn block(app: &mut App, id: usize) -> Entity {
+    app.world_mut()
+        .query::<(Entity, &Block)>()
+        .iter(app.world())
+        .find(|(_, block)| block.id == id)
+        .map(|(entity, _)| entity)
+        .unwrap()
+}
+fn connection(app: &mut App, block_id: usize, id: usize) -> Entity {
+    let block = block(app, block_id);
+    app.world_mut()
+        .query::<(Entity, &Connection, &BlockReference)>()
+        .iter(app.world())
+        .find(|(_, connection, owner)| owner.0 == block && connection.id == id)
+        .map(|(entity, ..)| entity)
+        .unwrap()
+}
+/// The wire with `index` inside the outer block.
+fn wire(app: &mut App, index: usize) -> Entity {
+    app.world_mut()
+        .query::<(Entity, &Wire)>()
+        .iter(app.world())
+        .find(|(_, wire)| wire.index == index)
+        .map(|(entity, _)| entity)
+        .unwrap()
+}
+fn plan(app: &mut App, from: Entity, target: WireTarget) -> Result<WirePlan, String> {
+    app.world_mut()
+        .run_system_once(
+            move |connections: ConnectionQuery, blocks: BlockQuery, wires: WireQuery| {
+                plan_wire(from, target, &connections, &blocks, &wires)
+            },
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_container_of() {
+    let mut app = wiring_app();
+    let [outer, and, not, inner, buf] = [1, 2, 3, 5, 6].map(|id| block(&mut app, id));
+    let container = |app: &mut App, a: Entity, b: Entity| {
+        app.world_mut()
+            .run_system_once(move |blocks: BlockQuery| container_of(a, b, &blocks))
+            .unwrap()
+    };
+    assert_eq!(container(&mut app, and, not), Ok(outer));
+    assert_eq!(container(&mut app, and, outer), Ok(outer));
+    assert_eq!(container(&mut app, outer, not), Ok(outer));
+    // connecting a primitive block to itself goes around it, a composite block holds the wire
+    assert_eq!(container(&mut app, and, and), Ok(outer));
+    assert_eq!(container(&mut app, inner, inner), Ok(inner));
+    assert_eq!(container(&mut app, buf, inner), Ok(inner));
+    assert!(container(&mut app, buf, and).is_err());
+    assert!(container(&mut app, buf, outer).is_err());
+}
+
+#[test]
+fn test_plan_wire() {
+    let mut app = wiring_app();
+    let outer = block(&mut app, 1);
+    let [input, and_a, and_b, and_out, not_in, not_out] =
+        [(1, 1), (2, 1), (2, 2), (2, 3), (3, 1), (3, 3)]
+            .map(|(block_id, id)| connection(&mut app, block_id, id));
+    let [input_wire, output_wire] = [0, 1].map(|index| wire(&mut app, index));
+    let plan_of = |wire, merged, connections| {
+        Ok(WirePlan {
+            container: outer,
+            wire,
+            merged,
+            connections,
+        })
+    };
+
+    // a new wire
+    assert_eq!(
+        plan(&mut app, and_out, WireTarget::Connection(not_in)),
+        plan_of(None, None, vec![and_out, not_in])
+    );
+    // extending a wire, from a connection on it or to one
+    assert_eq!(
+        plan(&mut app, and_a, WireTarget::Connection(and_b)),
+        plan_of(Some(input_wire), None, vec![and_b])
+    );
+    assert_eq!(
+        plan(&mut app, and_b, WireTarget::Connection(input)),
+        plan_of(Some(input_wire), None, vec![and_b])
+    );
+    assert_eq!(
+        plan(&mut app, and_b, WireTarget::Wire(input_wire)),
+        plan_of(Some(input_wire), None, vec![and_b])
+    );
+    // joining two nets
+    assert_eq!(
+        plan(&mut app, and_a, WireTarget::Connection(not_out)),
+        plan_of(Some(output_wire), Some(input_wire), vec![])
+    );
+    assert_eq!(
+        plan(&mut app, input, WireTarget::Connection(and_a)),
+        Err("already connected".to_string())
+    );
+    // the buffer inside block 5 is out of reach
+    let buf_in = connection(&mut app, 6, 1);
+    assert!(plan(&mut app, buf_in, WireTarget::Connection(and_b)).is_err());
+    assert!(plan(&mut app, buf_in, WireTarget::Wire(input_wire)).is_err());
+}
+
+#[test]
+fn test_width_mismatch() {
+    let mut app = wiring_app();
+    let [byte_input, and_b, and_a, register_in] =
+        [(1, 2), (2, 2), (2, 1), (4, 1)].map(|(block_id, id)| connection(&mut app, block_id, id));
+    let byte_wire = wire(&mut app, 2);
+    let mismatch = "the widths differ (1, 8 bits)".to_string();
+    assert_eq!(
+        plan(&mut app, byte_input, WireTarget::Connection(and_b)),
+        Err(mismatch.clone())
+    );
+    assert_eq!(
+        plan(&mut app, and_b, WireTarget::Wire(byte_wire)),
+        Err(mismatch.clone())
+    );
+    // merging nets of different widths
+    assert_eq!(
+        plan(&mut app, and_a, WireTarget::Connection(register_in)),
+        Err(mismatch.clone())
+    );
+
+    let outer = block(&mut app, 1);
+    let check = |app: &mut App, plan: WirePlan| {
+        app.world_mut()
+            .run_system_once(move |connections: ConnectionQuery, wires: WireQuery| {
+                check_widths(&plan, &connections, &wires)
+            })
+            .unwrap()
+    };
+    let plan = WirePlan {
+        container: outer,
+        wire: Some(byte_wire),
+        merged: None,
+        connections: vec![],
+    };
+    assert_eq!(check(&mut app, plan.clone()), Ok(()));
+    let with_bit = WirePlan {
+        connections: vec![and_b],
+        ..plan
+    };
+    assert_eq!(check(&mut app, with_bit), Err(mismatch));
+}
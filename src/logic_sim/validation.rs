@@ -0,0 +1,353 @@
+use super::*;
+use crate::logic_sim::simulation::SimulationSettings;
+use bevy::color::palettes::basic::RED;
+use std::fmt::{Display, Formatter};
+
+/// One end of a wire, named by the ids from the block definitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WireEnd {
+    pub block_id: usize,
+    pub connection_id: usize,
+    pub width: usize,
+}
+
+/// A wire between connections of different widths. Values crossing it are cut or zero extended
+/// to the width of each sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WidthMismatch {
+    /// Id of the block containing the wire.
+    pub block_id: usize,
+    /// Index of the wire in the wires of that block.
+    pub wire_index: usize,
+    /// All ends of the wire, ordered by block and connection id.
+    pub ends: Vec<WireEnd>,
+}
+
+impl WidthMismatch {
+    /// `None` if all ends of the wire have the same width.
+    pub fn check(block_id: usize, wire_index: usize, mut ends: Vec<WireEnd>) -> Option<Self> {
+        let width = ends.first()?.width;
+        if ends.iter().all(|end| end.width == width) {
+            return None;
+        }
+        ends.sort();
+        Some(Self {
+            block_id,
+            wire_index,
+            ends,
+        })
+    }
+}
+impl Display for WidthMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ends: Vec<_> = self
+            .ends
+            .iter()
+            .map(|end| {
+                format!(
+                    "block {} connection {} ({} bits)",
+                    end.block_id, end.connection_id, end.width
+                )
+            })
+            .collect();
+        write!(
+            f,
+            "wire {} of block {} connects different widths: {}",
+            self.wire_index,
+            self.block_id,
+            ends.join(", ")
+        )
+    }
+}
+
+/// The width mismatches of the wires of `definition` and of all of its inner blocks, which have
+/// to be resolved by [`BlockLibrary::resolve`] already. Wire ends that do not exist are skipped.
+pub fn definition_width_mismatches(definition: &BlockDefinition) -> Vec<WidthMismatch> {
+    let children: Vec<_> = definition
+        .inner_blocks
+        .iter()
+        .filter_map(|inner_block| match inner_block {
+            InnerBlockDefinition::Inline(child) => Some(child),
+            InnerBlockDefinition::Instance(_) => None,
+        })
+        .collect();
+    let width = |connection: &ConnectionDefinitionRef| {
+        // looked up in the same order as when spawning the wire
+        let mut connections = if connection.parent_block == definition.id {
+            definition.inputs.iter().chain(definition.outputs.iter())
+        } else {
+            let child = children
+                .iter()
+                .find(|child| child.id == connection.parent_block)?;
+            child.outputs.iter().chain(child.inputs.iter())
+        };
+        connections
+            .find(|c| c.id == connection.id)
+            .map(|c| c.value.len())
+    };
+    let mut mismatches: Vec<_> = definition
+        .wires
+        .iter()
+        .enumerate()
+        .filter_map(|(index, wire)| {
+            let ends = wire
+                .connections
+                .iter()
+                .filter_map(|connection| {
+                    Some(WireEnd {
+                        block_id: connection.parent_block,
+                        connection_id: connection.id,
+                        width: width(connection)?,
+                    })
+                })
+                .collect();
+            WidthMismatch::check(definition.id, index, ends)
+        })
+        .collect();
+    for child in children {
+        mismatches.extend(definition_width_mismatches(child));
+    }
+    mismatches
+}
+
+/// The wires connecting different widths, found when the circuit is loaded and whenever a wire
+/// or connection changes.
+#[derive(Resource, Debug, Default)]
+pub struct WidthMismatches {
+    pub mismatches: Vec<WidthMismatch>,
+    /// The spawned wires of the mismatches.
+    pub wires: Vec<Entity>,
+}
+impl WidthMismatches {
+    /// Replaces the known mismatches, reporting the ones that are new.
+    pub fn update(&mut self, mismatches: Vec<WidthMismatch>, settings: &SimulationSettings) {
+        let new: Vec<_> = mismatches
+            .iter()
+            .filter(|mismatch| !self.mismatches.contains(mismatch))
+            .collect();
+        for mismatch in new.iter() {
+            error!("{mismatch}");
+        }
+        if !new.is_empty() && settings.refuse_width_mismatches {
+            error!("the circuit will not run until the widths of all wires match");
+        }
+        self.mismatches = mismatches;
+    }
+    /// Whether the simulation has to stay stopped, see
+    /// [`SimulationSettings::refuse_width_mismatches`].
+    pub fn blocks_simulation(&self, settings: &SimulationSettings) -> bool {
+        settings.refuse_width_mismatches && !self.mismatches.is_empty()
+    }
+}
+
+type WiringChangeQuery<'w, 's> = Query<'w, 's, (), Or<(Changed<Wire>, Changed<Connection>)>>;
+
+/// Checks the widths of all wires again once any wire or connection changed.
+pub(super) fn check_wire_widths(
+    changed: WiringChangeQuery,
+    mut removed_wires: RemovedComponents<Wire>,
+    wires: Query<(Entity, &Wire, &Parent)>,
+    blocks: Query<&Block>,
+    connections: Query<(&Connection, &BlockReference)>,
+    mut width_mismatches: ResMut<WidthMismatches>,
+    settings: Res<SimulationSettings>,
+) {
+    let removed = removed_wires.read().count();
+    if changed.is_empty() && removed == 0 {
+        return;
+    }
+    let mut mismatches = vec![];
+    let mut mismatched_wires = vec![];
+    for (entity, wire, parent) in wires.iter() {
+        let Ok(block) = blocks.get(parent.get()) else {
+            continue;
+        };
+        let ends = wire
+            .connections()
+            .filter_map(|connection| {
+                let (connection, owner) = connections.get(connection.0).ok()?;
+                Some(WireEnd {
+                    block_id: blocks.get(owner.0).ok()?.id,
+                    connection_id: connection.id,
+                    width: connection.values.len(),
+                })
+            })
+            .collect();
+        if let Some(mismatch) = WidthMismatch::check(block.id, wire.index, ends) {
+            mismatches.push(mismatch);
+            mismatched_wires.push(entity);
+        }
+    }
+    width_mismatches.update(mismatches, &settings);
+    width_mismatches.wires = mismatched_wires;
+}
+
+/// A problem found in a block definition before spawning it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefinitionProblem {
+    /// JSON path of the offending value, e.g. `$.inner_blocks[1].inputs[0].id`.
+    pub path: String,
+    pub message: String,
+}
+impl DefinitionProblem {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+impl Display for DefinitionProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// All problems of `definition` and of its inner blocks that would leave the spawned circuit
+/// incomplete: connection ids used twice within a block, child blocks sharing an id with each
+/// other or with their parent, and wire ends that point nowhere.
+///
+/// Meant for definitions resolved by [`BlockLibrary::resolve`], so the paths of blocks from other
+/// files lead through the `inner_blocks` of the file including them.
+pub fn validate_definition(definition: &BlockDefinition) -> Vec<DefinitionProblem> {
+    let mut problems = vec![];
+    validate_block(definition, "$", &mut problems);
+    problems
+}
+
+fn validate_block(definition: &BlockDefinition, path: &str, problems: &mut Vec<DefinitionProblem>) {
+    let mut connection_paths: Vec<(usize, String)> = vec![];
+    for (field, connections) in [
+        ("inputs", &definition.inputs),
+        ("outputs", &definition.outputs),
+    ] {
+        for (i, connection) in connections.iter().enumerate() {
+            let connection_path = format!("{path}.{field}[{i}]");
+            match connection_paths.iter().find(|(id, _)| *id == connection.id) {
+                Some((_, first)) => problems.push(DefinitionProblem::new(
+                    format!("{connection_path}.id"),
+                    format!("connection id {} is already used by {first}", connection.id),
+                )),
+                None => connection_paths.push((connection.id, connection_path)),
+            }
+        }
+    }
+
+    let mut children: Vec<&BlockDefinition> = vec![];
+    let mut child_paths: Vec<(usize, String)> = vec![];
+    for (i, inner_block) in definition.inner_blocks.iter().enumerate() {
+        let child_path = format!("{path}.inner_blocks[{i}]");
+        let InnerBlockDefinition::Inline(child) = inner_block else {
+            problems.push(DefinitionProblem::new(
+                child_path,
+                "block instance is not resolved",
+            ));
+            continue;
+        };
+        if child.id == definition.id {
+            problems.push(DefinitionProblem::new(
+                format!("{child_path}.id"),
+                format!("block id {} is the id of the block containing it", child.id),
+            ));
+        } else if let Some((_, first)) = child_paths.iter().find(|(id, _)| *id == child.id) {
+            problems.push(DefinitionProblem::new(
+                format!("{child_path}.id"),
+                format!("block id {} is already used by {first}", child.id),
+            ));
+        } else {
+            child_paths.push((child.id, child_path.clone()));
+        }
+        validate_block(child, &child_path, problems);
+        children.push(child);
+    }
+
+    for (w, wire) in definition.wires.iter().enumerate() {
+        for (c, connection) in wire.connections.iter().enumerate() {
+            let connection_path = format!("{path}.wires[{w}].connections[{c}]");
+            // the same lookup as when spawning the wire
+            let owner = if connection.parent_block == definition.id {
+                Some(definition)
+            } else {
+                children
+                    .iter()
+                    .find(|child| child.id == connection.parent_block)
+                    .copied()
+            };
+            let Some(owner) = owner else {
+                problems.push(DefinitionProblem::new(
+                    format!("{connection_path}.parent_block"),
+                    format!(
+                        "no block with id {} (wires can only reach the block itself and its direct children)",
+                        connection.parent_block
+                    ),
+                ));
+                continue;
+            };
+            let exists = owner
+                .inputs
+                .iter()
+                .chain(owner.outputs.iter())
+                .any(|c| c.id == connection.id);
+            if !exists {
+                problems.push(DefinitionProblem::new(
+                    format!("{connection_path}.id"),
+                    format!(
+                        "block {} has no connection with id {}",
+                        owner.id, connection.id
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// The problems that kept the last block definition from being spawned, listed on the console
+/// and on screen.
+#[derive(Resource, Debug, Default)]
+pub struct DefinitionProblems {
+    /// Asset path of the definition.
+    pub file: String,
+    pub problems: Vec<DefinitionProblem>,
+}
+
+/// Marks the text listing the [`DefinitionProblems`].
+#[derive(Component)]
+pub(super) struct ProblemPanel;
+
+pub(super) fn show_definition_problems(
+    mut commands: Commands,
+    problems: Res<DefinitionProblems>,
+    panels: Query<Entity, With<ProblemPanel>>,
+) {
+    if !problems.is_changed() {
+        return;
+    }
+    for panel in panels.iter() {
+        commands.entity(panel).despawn_recursive();
+    }
+    if problems.problems.is_empty() {
+        return;
+    }
+    let mut text = format!("Could not load '{}':", problems.file);
+    for problem in problems.problems.iter() {
+        error!("{}: {problem}", problems.file);
+        text.push_str(&format!("\n{problem}"));
+    }
+    commands.spawn((
+        ProblemPanel,
+        Text::new(text),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(RED.into()),
+        BackgroundColor(Color::BLACK.with_alpha(0.8)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            left: Val::Px(12.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+    ));
+}
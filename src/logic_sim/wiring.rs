@@ -0,0 +1,569 @@
+use super::*;
+use crate::logic_sim::editor::{EditorState, Selected, editing, keyboard_free};
+use crate::logic_sim::history::{EditCommand, EditHistory};
+use crate::logic_sim::reload::{BlockTreeQuery, block_path};
+use crate::logic_sim::routing::{route_wires, waypoint_insert_index};
+use crate::logic_sim::save::CircuitDefinitions;
+use crate::utils::get_cursor_world_pos;
+use bevy::color::palettes::basic::{AQUA, LIME};
+use bevy::picking::focus::HoverMap;
+use bevy::picking::pointer::PointerId;
+
+/// How close to one of its lines a click has to be to hit a wire.
+const WIRE_PICK_DISTANCE: f32 = 6.0;
+
+/// Drawing wires while editing: dragging from a connection to another one connects them,
+/// dragging onto a wire joins its net. Clicking a wire selects it, W adds a waypoint to the
+/// selected wire or removes the one under the cursor, and its waypoints can be dragged around.
+pub struct WiringPlugin;
+impl Plugin for WiringPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WireDraft>()
+            .init_resource::<WaypointDrag>()
+            .add_observer(on_press_connection)
+            .add_systems(
+                Update,
+                (
+                    update_wire_draft,
+                    finish_wire_draft,
+                    drag_waypoint,
+                    drop_waypoint,
+                    toggle_waypoint.run_if(keyboard_free),
+                    select_wire,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running).and(editing)),
+            )
+            .add_systems(
+                PostUpdate,
+                draw_wire_draft
+                    .after(route_wires)
+                    .run_if(in_state(AppState::Running).and(editing)),
+            );
+    }
+}
+
+/// The wire being dragged out of a connection.
+#[derive(Resource, Debug, Default)]
+struct WireDraft {
+    /// The cursor in world coordinates, updated every frame.
+    cursor: Option<Vec2>,
+    from: Option<Entity>,
+    /// What is under the cursor and what connecting to it would do.
+    target: Option<(WireTarget, Result<WirePlan, String>)>,
+}
+
+/// The waypoint of a selected wire that follows the cursor: the wire, the index of the
+/// waypoint and the waypoints from before it was picked up.
+#[derive(Resource, Debug, Default)]
+struct WaypointDrag(Option<(Entity, usize, Vec<Vec2>)>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum WireTarget {
+    Connection(Entity),
+    /// Joins the net of the wire.
+    Wire(Entity),
+}
+
+/// The changes to the wires that connect the dragged connection to its target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct WirePlan {
+    /// The block holding the wire.
+    pub(super) container: Entity,
+    /// The wire to extend, a new one is spawned if `None`.
+    pub(super) wire: Option<Entity>,
+    /// A wire whose connections move to `wire`, joining both nets.
+    pub(super) merged: Option<Entity>,
+    /// Connections that are not on any wire of `container` yet.
+    pub(super) connections: Vec<Entity>,
+}
+
+pub(super) type ConnectionQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Connection,
+        &'static BlockReference,
+        Has<InputConnection>,
+        &'static GlobalTransform,
+    ),
+>;
+pub(super) type BlockQuery<'w, 's> =
+    Query<'w, 's, (&'static Block, &'static BlockKind, &'static Parent)>;
+pub(super) type WireQuery<'w, 's> =
+    Query<'w, 's, (Entity, &'static Wire, &'static Parent, &'static WireRoute)>;
+
+fn on_press_connection(
+    mut press: Trigger<Pointer<Down>>,
+    editor: Res<EditorState>,
+    connections: Query<(), With<Connection>>,
+    mut draft: ResMut<WireDraft>,
+) {
+    if !editor.enabled || press.button != PointerButton::Primary {
+        return;
+    }
+    let entity = press.entity();
+    if !connections.contains(entity) {
+        return;
+    }
+    // the block of the connection should not be picked up
+    press.propagate(false);
+    draft.from = Some(entity);
+    draft.target = None;
+}
+
+/// Finds the connection or wire under the cursor and checks whether it can be connected.
+fn update_wire_draft(
+    mut draft: ResMut<WireDraft>,
+    hover_map: Res<HoverMap>,
+    connections: ConnectionQuery,
+    blocks: BlockQuery,
+    wires: WireQuery,
+    camera_query: Single<(&Camera, &GlobalTransform)>,
+    windows: Query<&Window>,
+) {
+    draft.cursor = get_cursor_world_pos(camera_query, windows);
+    let Some(from) = draft.from else {
+        return;
+    };
+    let hovered = hover_map
+        .get(&PointerId::Mouse)
+        .and_then(|hits| {
+            hits.keys()
+                .copied()
+                .find(|entity| *entity != from && connections.contains(*entity))
+        })
+        .map(WireTarget::Connection);
+    let target = hovered.or_else(|| {
+        let point = draft.cursor?;
+        wire_at(point, &wires).map(WireTarget::Wire)
+    });
+    draft.target = target.map(|target| {
+        (
+            target,
+            plan_wire(from, target, &connections, &blocks, &wires),
+        )
+    });
+}
+
+/// Connects the dragged connection once the button is released.
+fn finish_wire_draft(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut draft: ResMut<WireDraft>,
+    connections: ConnectionQuery,
+    wires: WireQuery,
+    tree: BlockTreeQuery,
+    circuit: CircuitDefinitions,
+    mut history: ResMut<EditHistory>,
+) {
+    if draft.from.is_none() || mouse.pressed(MouseButton::Left) {
+        return;
+    }
+    draft.from = None;
+    match draft.target.take() {
+        Some((_, Ok(plan))) => {
+            history.perform(plan_edit(plan, &connections, &wires, &tree, &circuit));
+        }
+        Some((_, Err(problem))) => warn!("cannot connect: {problem}"),
+        None => {}
+    }
+}
+
+/// The block a wire between connections of the blocks `a` and `b` belongs to. Wires reach the
+/// connections of the block holding them and of its direct children.
+pub(super) fn container_of(a: Entity, b: Entity, blocks: &BlockQuery) -> Result<Entity, String> {
+    let parent = |block: Entity| {
+        blocks
+            .get(block)
+            .ok()
+            .map(|(_, _, parent)| parent.get())
+            .filter(|parent| blocks.contains(*parent))
+    };
+    if a == b {
+        // the inside of a primitive block cannot hold wires
+        return match blocks.get(a) {
+            Ok((_, BlockKind::Composite, _)) => Ok(a),
+            _ => parent(a).ok_or_else(|| "the block is not inside another block".to_string()),
+        };
+    }
+    if parent(a) == Some(b) {
+        Ok(b)
+    } else if parent(b) == Some(a) {
+        Ok(a)
+    } else {
+        match (parent(a), parent(b)) {
+            (Some(pa), Some(pb)) if pa == pb => Ok(pa),
+            _ => Err("wires only connect blocks inside the same block".to_string()),
+        }
+    }
+}
+
+pub(super) fn plan_wire(
+    from: Entity,
+    target: WireTarget,
+    connections: &ConnectionQuery,
+    blocks: &BlockQuery,
+    wires: &WireQuery,
+) -> Result<WirePlan, String> {
+    let Ok((_, from_block, _, _)) = connections.get(from) else {
+        return Err("the connection no longer exists".to_string());
+    };
+    let (container, target_wire, to) = match target {
+        WireTarget::Connection(to) => {
+            let Ok((_, to_block, _, _)) = connections.get(to) else {
+                return Err("the connection no longer exists".to_string());
+            };
+            (
+                container_of(from_block.0, to_block.0, blocks)?,
+                None,
+                Some(to),
+            )
+        }
+        WireTarget::Wire(wire) => {
+            let Ok((_, _, parent, _)) = wires.get(wire) else {
+                return Err("the wire no longer exists".to_string());
+            };
+            let container = parent.get();
+            let reachable = from_block.0 == container
+                || blocks
+                    .get(from_block.0)
+                    .is_ok_and(|(_, _, parent)| parent.get() == container);
+            if !reachable {
+                return Err("the wire belongs to another block".to_string());
+            }
+            (container, Some(wire), None)
+        }
+    };
+    let wire_of = |connection: Entity| {
+        wires
+            .iter()
+            .find(|(_, wire, parent, _)| {
+                parent.get() == container && wire.connections().any(|c| c.0 == connection)
+            })
+            .map(|(entity, ..)| entity)
+    };
+    let other = target_wire.or_else(|| to.and_then(wire_of));
+    let plan = match (wire_of(from), other) {
+        (Some(a), Some(b)) if a == b => return Err("already connected".to_string()),
+        (Some(a), Some(b)) => WirePlan {
+            container,
+            wire: Some(b),
+            merged: Some(a),
+            connections: vec![],
+        },
+        (Some(a), None) => WirePlan {
+            container,
+            wire: Some(a),
+            merged: None,
+            connections: to.into_iter().collect(),
+        },
+        (None, wire) => WirePlan {
+            container,
+            wire,
+            merged: None,
+            connections: [Some(from), to.filter(|_| wire.is_none())]
+                .into_iter()
+                .flatten()
+                .collect(),
+        },
+    };
+    check_widths(&plan, connections, wires)?;
+    Ok(plan)
+}
+
+/// Every connection of the joined net has to have the same width.
+pub(super) fn check_widths(
+    plan: &WirePlan,
+    connections: &ConnectionQuery,
+    wires: &WireQuery,
+) -> Result<(), String> {
+    let on_wires: Vec<Entity> = [plan.wire, plan.merged]
+        .into_iter()
+        .flatten()
+        .filter_map(|wire| wires.get(wire).ok())
+        .flat_map(|(_, wire, ..)| wire.connections().map(|c| c.0).collect::<Vec<_>>())
+        .collect();
+    let mut widths: Vec<usize> = plan
+        .connections
+        .iter()
+        .chain(on_wires.iter())
+        .filter_map(|connection| connections.get(*connection).ok())
+        .map(|(connection, _, _, _)| connection.values.len())
+        .collect();
+    widths.sort();
+    widths.dedup();
+    if widths.len() < 2 {
+        return Ok(());
+    }
+    let widths: Vec<_> = widths.iter().map(ToString::to_string).collect();
+    Err(format!("the widths differ ({} bits)", widths.join(", ")))
+}
+
+/// The commands that carry out `plan`. Wires that change are removed and added again.
+fn plan_edit(
+    plan: WirePlan,
+    connections: &ConnectionQuery,
+    wires: &WireQuery,
+    tree: &BlockTreeQuery,
+    circuit: &CircuitDefinitions,
+) -> Vec<EditCommand> {
+    let block = block_path(plan.container, tree);
+    let mut ends: Vec<ConnectionDefinitionRef> = plan
+        .connections
+        .iter()
+        .filter_map(|connection| {
+            let (connection, owner, _, _) = connections.get(*connection).ok()?;
+            let (owner, _) = tree.get(owner.0).ok()?;
+            Some(ConnectionDefinitionRef {
+                parent_block: owner.id,
+                id: connection.id,
+            })
+        })
+        .collect();
+    let existing = |wire: Option<Entity>| {
+        let wire = wire?;
+        let (_, spawned, ..) = wires.get(wire).ok()?;
+        Some((spawned.index, circuit.wire(wire)?))
+    };
+    let mut edit = vec![];
+    if let Some((index, merged)) = existing(plan.merged) {
+        ends.extend(merged.connections.iter().copied());
+        edit.push(EditCommand::RemoveWire {
+            block: block.clone(),
+            index,
+            wire: merged,
+        });
+    }
+    match existing(plan.wire) {
+        Some((index, old)) => {
+            let mut extended = old.clone();
+            extended.connections.extend(ends);
+            edit.push(EditCommand::RemoveWire {
+                block: block.clone(),
+                index,
+                wire: old,
+            });
+            edit.push(EditCommand::AddWire {
+                block,
+                index,
+                wire: extended,
+            });
+        }
+        None => {
+            let index = wires
+                .iter()
+                .filter(|(_, _, parent, _)| parent.get() == plan.container)
+                .map(|(_, wire, ..)| wire.index + 1)
+                .max()
+                .unwrap_or_default();
+            edit.push(EditCommand::AddWire {
+                block,
+                index,
+                wire: WireDefinition {
+                    connections: ends,
+                    delay: 0,
+                    waypoints: vec![],
+                },
+            });
+        }
+    }
+    edit
+}
+
+/// The wire with a line closest to `point`, if any is close enough.
+fn wire_at(point: Vec2, wires: &WireQuery) -> Option<Entity> {
+    wires
+        .iter()
+        .filter_map(|(entity, _, _, route)| {
+            let distance = route
+                .segments
+                .iter()
+                .map(|(start, end)| distance_to_segment(point, *start, *end))
+                .reduce(f32::min)?;
+            (distance <= WIRE_PICK_DISTANCE).then_some((entity, distance))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
+
+fn distance_to_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let line = end - start;
+    let t = if line.length_squared() > 0.0 {
+        ((point - start).dot(line) / line.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    point.distance(start + line * t)
+}
+
+/// Waypoints are kept in the coordinates of the block containing the wire, which the wire
+/// shares.
+fn waypoint_position(waypoint: Vec2, wire: &GlobalTransform) -> Vec2 {
+    wire.transform_point(waypoint.extend(0.0)).xy()
+}
+fn waypoint_from_position(point: Vec2, wire: &GlobalTransform) -> Vec2 {
+    wire.affine()
+        .inverse()
+        .transform_point3(point.extend(0.0))
+        .xy()
+}
+
+/// The waypoint close enough to `point` to be picked.
+fn waypoint_at(point: Vec2, waypoints: &[Vec2], wire: &GlobalTransform) -> Option<usize> {
+    waypoints
+        .iter()
+        .map(|waypoint| waypoint_position(*waypoint, wire).distance(point))
+        .enumerate()
+        .filter(|(_, distance)| *distance <= WIRE_PICK_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
+
+/// Picks up the waypoint of a selected wire under the cursor and moves it along while the
+/// button is held.
+fn drag_waypoint(
+    mouse: Res<ButtonInput<MouseButton>>,
+    draft: Res<WireDraft>,
+    mut drag: ResMut<WaypointDrag>,
+    mut selected: Query<(Entity, &mut WireWaypoints, &GlobalTransform), With<Selected>>,
+) {
+    let Some(cursor) = draft.cursor else {
+        return;
+    };
+    if mouse.just_pressed(MouseButton::Left) && draft.from.is_none() {
+        drag.0 = selected.iter().find_map(|(wire, waypoints, transform)| {
+            let index = waypoint_at(cursor, &waypoints.0, transform)?;
+            Some((wire, index, waypoints.0.clone()))
+        });
+    }
+    let Some((wire, index, _)) = drag.0.as_ref() else {
+        return;
+    };
+    let Ok((_, mut waypoints, transform)) = selected.get_mut(*wire) else {
+        return;
+    };
+    let moved = waypoint_from_position(cursor, transform);
+    if let Some(waypoint) = waypoints.0.get_mut(*index) {
+        *waypoint = moved;
+    }
+}
+
+/// Lets go of the dragged waypoint once the button is released, which can be undone.
+fn drop_waypoint(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut drag: ResMut<WaypointDrag>,
+    wires: Query<(&WireWaypoints, &Parent)>,
+    tree: BlockTreeQuery,
+    circuit: CircuitDefinitions,
+    mut history: ResMut<EditHistory>,
+) {
+    if mouse.pressed(MouseButton::Left) {
+        return;
+    }
+    let Some((wire, _, before)) = drag.0.take() else {
+        return;
+    };
+    let (Ok((waypoints, parent)), Some(definition)) = (wires.get(wire), circuit.wire(wire)) else {
+        return;
+    };
+    if waypoints.0 != before {
+        history.record(vec![EditCommand::SetWaypoints {
+            block: block_path(parent.get(), &tree),
+            wire: definition,
+            from: before,
+            to: waypoints.0.clone(),
+        }]);
+    }
+}
+
+/// W adds a waypoint to the selected wires at the cursor, where it lengthens the line through
+/// their waypoints the least, or removes the waypoint under the cursor.
+fn toggle_waypoint(
+    keys: Res<ButtonInput<KeyCode>>,
+    draft: Res<WireDraft>,
+    selected: Query<(Entity, &WireWaypoints, &GlobalTransform, &Parent), With<Selected>>,
+    tree: BlockTreeQuery,
+    circuit: CircuitDefinitions,
+    mut history: ResMut<EditHistory>,
+) {
+    let Some(cursor) = draft.cursor.filter(|_| keys.just_pressed(KeyCode::KeyW)) else {
+        return;
+    };
+    for (wire, waypoints, transform, parent) in selected.iter() {
+        let Some(definition) = circuit.wire(wire) else {
+            continue;
+        };
+        let mut changed = waypoints.0.clone();
+        match waypoint_at(cursor, &changed, transform) {
+            Some(index) => {
+                changed.remove(index);
+            }
+            None => {
+                let waypoint = waypoint_from_position(cursor, transform);
+                changed.insert(waypoint_insert_index(&changed, waypoint), waypoint);
+            }
+        }
+        history.perform(vec![EditCommand::SetWaypoints {
+            block: block_path(parent.get(), &tree),
+            wire: definition,
+            from: waypoints.0.clone(),
+            to: changed,
+        }]);
+    }
+}
+
+/// Selects the wire under the cursor. A press that hits neither a wire nor a block clears the
+/// selection, one that picks up a waypoint keeps it.
+fn select_wire(
+    mut commands: Commands,
+    mouse: Res<ButtonInput<MouseButton>>,
+    draft: Res<WireDraft>,
+    drag: Res<WaypointDrag>,
+    pressed_blocks: Query<(), (Changed<Selected>, With<Block>)>,
+    selected: Query<Entity, With<Selected>>,
+    wires: WireQuery,
+) {
+    if !mouse.just_pressed(MouseButton::Left)
+        || draft.from.is_some()
+        || drag.0.is_some()
+        || !pressed_blocks.is_empty()
+    {
+        return;
+    }
+    let hit = draft.cursor.and_then(|point| wire_at(point, &wires));
+    for previous in selected.iter() {
+        commands.entity(previous).remove::<Selected>();
+    }
+    if let Some(wire) = hit {
+        commands.entity(wire).insert(Selected);
+    }
+}
+
+fn draw_wire_draft(
+    draft: Res<WireDraft>,
+    selected: Query<(&WireRoute, &WireWaypoints, &GlobalTransform), With<Selected>>,
+    connections: ConnectionQuery,
+    mut gizmos: Gizmos,
+) {
+    for (route, waypoints, transform) in selected.iter() {
+        for (start, end) in route.segments.iter() {
+            gizmos.line_2d(*start, *end, AQUA);
+        }
+        for waypoint in waypoints.0.iter() {
+            let position = waypoint_position(*waypoint, transform);
+            gizmos.circle_2d(position, WIRE_PICK_DISTANCE, AQUA);
+        }
+    }
+    let (Some(from), Some(cursor)) = (draft.from, draft.cursor) else {
+        return;
+    };
+    let Ok((_, _, _, from)) = connections.get(from) else {
+        return;
+    };
+    let color = match &draft.target {
+        Some((_, Ok(_))) => LIME,
+        Some((_, Err(_))) => RED,
+        None => WHITE,
+    };
+    gizmos.line_2d(from.translation().xy(), cursor, color);
+}